@@ -0,0 +1,46 @@
+use criterion::{BenchmarkId, Criterion, criterion_group, criterion_main};
+use nalgebra::Vector2;
+use std::hint::black_box;
+
+use omega_optimizer::error_functions::ErrorFunction;
+use omega_optimizer::functions::decay::Decay;
+use omega_optimizer::utils::Dataset;
+
+/// A decay with `len` points, where a deterministic ripple stands in for noise.
+fn large_decay(len: usize) -> Dataset {
+    let x: Vec<f64> = (0..len).map(|i| i as f64 * 5.0 / len as f64).collect();
+    let y: Vec<f64> = (x.iter().enumerate())
+        .map(|(i, x)| 3.0 * (-0.7 * x).exp() + 0.01 * (i as f64 * 1.3).sin())
+        .collect();
+    Dataset {
+        sigma: Some(vec![0.01; len]),
+        x_sigma: None,
+        x,
+        y,
+    }
+}
+
+/// Evaluates the error, and the error with its gradient and hessian, with one thread
+/// and with one thread per core. The last evaluation is cached, so the parameters
+/// alternate between two values.
+fn parallel_evaluation(c: &mut Criterion) {
+    let data = large_decay(1_000_000);
+    let parameters = [Vector2::new(2.9, 0.72), Vector2::new(3.1, 0.68)];
+    let mut group = c.benchmark_group("million_points");
+    group.sample_size(10);
+    for (threads, name) in [(1, "serial"), (0, "parallel")] {
+        let error_function = ErrorFunction::from_data(Decay, &data).with_threads(threads);
+        group.bench_function(BenchmarkId::new("error", name), |b| {
+            let mut p = parameters.iter().cycle();
+            b.iter(|| error_function.f(black_box(p.next().unwrap())))
+        });
+        group.bench_function(BenchmarkId::new("evaluate_all", name), |b| {
+            let mut p = parameters.iter().cycle();
+            b.iter(|| error_function.evaluate_all(black_box(p.next().unwrap())))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parallel_evaluation);
+criterion_main!(benches);
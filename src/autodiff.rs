@@ -0,0 +1,355 @@
+use nalgebra::{SMatrix, SVector, Scalar};
+
+use std::ops::{Add, Div, Mul, Neg, Sub};
+
+/// A number type that functions can be written for, so that the same code evaluates
+/// them as `f64`, and as `Dual` or `HyperDual` numbers, which carry exact derivatives
+/// along with the value.
+pub trait Real:
+    Scalar
+    + Copy
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + Mul<Output = Self>
+    + Div<Output = Self>
+    + Neg<Output = Self>
+    + Add<f64, Output = Self>
+    + Sub<f64, Output = Self>
+    + Mul<f64, Output = Self>
+    + Div<f64, Output = Self>
+{
+    /// A number that doesn't depend on the variables.
+    fn constant(value: f64) -> Self;
+
+    fn exp(self) -> Self;
+    fn ln(self) -> Self;
+    fn sqrt(self) -> Self;
+    fn abs(self) -> Self;
+    fn sin(self) -> Self;
+    fn powi(self, n: i32) -> Self;
+
+    /// The power with an exponent that is also a number, which needs a positive base.
+    fn pow(self, n: Self) -> Self {
+        (self.ln() * n).exp()
+    }
+}
+
+impl Real for f64 {
+    fn constant(value: f64) -> Self {
+        value
+    }
+
+    fn exp(self) -> Self {
+        f64::exp(self)
+    }
+
+    fn ln(self) -> Self {
+        f64::ln(self)
+    }
+
+    fn sqrt(self) -> Self {
+        f64::sqrt(self)
+    }
+
+    fn abs(self) -> Self {
+        f64::abs(self)
+    }
+
+    fn sin(self) -> Self {
+        f64::sin(self)
+    }
+
+    fn powi(self, n: i32) -> Self {
+        f64::powi(self, n)
+    }
+
+    fn pow(self, n: Self) -> Self {
+        f64::powf(self, n)
+    }
+}
+
+/// A number along with its gradient with respect to D variables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Dual<const D: usize> {
+    pub value: f64,
+    pub gradient: SVector<f64, D>,
+}
+
+/// A number along with its gradient and hessian with respect to D variables.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HyperDual<const D: usize> {
+    pub value: f64,
+    pub gradient: SVector<f64, D>,
+    pub hessian: SMatrix<f64, D, D>,
+}
+
+impl<const D: usize> Dual<D> {
+    /// The i-th variable, at the given value.
+    pub fn variable(value: f64, i: usize) -> Self {
+        let mut gradient = SVector::zeros();
+        gradient[i] = 1.0;
+        Self { value, gradient }
+    }
+
+    /// Each parameter as a variable.
+    pub fn variables(params: &SVector<f64, D>) -> SVector<Self, D> {
+        SVector::from_fn(|i, _| Self::variable(params[i], i))
+    }
+
+    /// Apply a function with value `f` and derivative `df` at this number.
+    fn chain(self, f: f64, df: f64, _d2f: f64) -> Self {
+        Self {
+            value: f,
+            gradient: self.gradient * df,
+        }
+    }
+}
+
+impl<const D: usize> HyperDual<D> {
+    /// The i-th variable, at the given value.
+    pub fn variable(value: f64, i: usize) -> Self {
+        let mut gradient = SVector::zeros();
+        gradient[i] = 1.0;
+        Self {
+            value,
+            gradient,
+            hessian: SMatrix::zeros(),
+        }
+    }
+
+    /// Each parameter as a variable.
+    pub fn variables(params: &SVector<f64, D>) -> SVector<Self, D> {
+        SVector::from_fn(|i, _| Self::variable(params[i], i))
+    }
+
+    /// Apply a function with value `f` and derivatives `df` and `d2f` at this number.
+    fn chain(self, f: f64, df: f64, d2f: f64) -> Self {
+        Self {
+            value: f,
+            gradient: self.gradient * df,
+            hessian: self.hessian * df + self.gradient * self.gradient.transpose() * d2f,
+        }
+    }
+}
+
+impl<const D: usize> Mul for Dual<D> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        Self {
+            value: self.value * rhs.value,
+            gradient: self.gradient * rhs.value + rhs.gradient * self.value,
+        }
+    }
+}
+
+impl<const D: usize> Mul for HyperDual<D> {
+    type Output = Self;
+
+    fn mul(self, rhs: Self) -> Self {
+        let cross = self.gradient * rhs.gradient.transpose();
+        Self {
+            value: self.value * rhs.value,
+            gradient: self.gradient * rhs.value + rhs.gradient * self.value,
+            hessian: self.hessian * rhs.value
+                + rhs.hessian * self.value
+                + cross
+                + cross.transpose(),
+        }
+    }
+}
+
+/// The arithmetic and elementary functions that follow from `chain` and `mul`, along
+/// with the linear operations, which act on every part of the number alike.
+macro_rules! impl_real {
+    ($type:ident, $($part:ident),*) => {
+        impl<const D: usize> Add for $type<D> {
+            type Output = Self;
+
+            fn add(self, rhs: Self) -> Self {
+                Self {
+                    value: self.value + rhs.value,
+                    $($part: self.$part + rhs.$part),*
+                }
+            }
+        }
+
+        impl<const D: usize> Sub for $type<D> {
+            type Output = Self;
+
+            fn sub(self, rhs: Self) -> Self {
+                Self {
+                    value: self.value - rhs.value,
+                    $($part: self.$part - rhs.$part),*
+                }
+            }
+        }
+
+        impl<const D: usize> Neg for $type<D> {
+            type Output = Self;
+
+            fn neg(self) -> Self {
+                Self {
+                    value: -self.value,
+                    $($part: -self.$part),*
+                }
+            }
+        }
+
+        impl<const D: usize> Div for $type<D> {
+            type Output = Self;
+
+            fn div(self, rhs: Self) -> Self {
+                let inverse = 1.0 / rhs.value;
+                self * rhs.chain(inverse, -inverse * inverse, 2.0 * inverse.powi(3))
+            }
+        }
+
+        impl<const D: usize> Add<f64> for $type<D> {
+            type Output = Self;
+
+            fn add(self, rhs: f64) -> Self {
+                Self {
+                    value: self.value + rhs,
+                    ..self
+                }
+            }
+        }
+
+        impl<const D: usize> Sub<f64> for $type<D> {
+            type Output = Self;
+
+            fn sub(self, rhs: f64) -> Self {
+                Self {
+                    value: self.value - rhs,
+                    ..self
+                }
+            }
+        }
+
+        impl<const D: usize> Mul<f64> for $type<D> {
+            type Output = Self;
+
+            fn mul(self, rhs: f64) -> Self {
+                Self {
+                    value: self.value * rhs,
+                    $($part: self.$part * rhs),*
+                }
+            }
+        }
+
+        impl<const D: usize> Div<f64> for $type<D> {
+            type Output = Self;
+
+            fn div(self, rhs: f64) -> Self {
+                self * (1.0 / rhs)
+            }
+        }
+
+        impl<const D: usize> Real for $type<D> {
+            fn constant(value: f64) -> Self {
+                Self {
+                    value,
+                    $($part: nalgebra::zero()),*
+                }
+            }
+
+            fn exp(self) -> Self {
+                let exp = self.value.exp();
+                self.chain(exp, exp, exp)
+            }
+
+            fn ln(self) -> Self {
+                let inverse = 1.0 / self.value;
+                self.chain(self.value.ln(), inverse, -inverse * inverse)
+            }
+
+            fn sqrt(self) -> Self {
+                let sqrt = self.value.sqrt();
+                self.chain(sqrt, 0.5 / sqrt, -0.25 / (sqrt * self.value))
+            }
+
+            fn abs(self) -> Self {
+                self.chain(self.value.abs(), self.value.signum(), 0.0)
+            }
+
+            fn sin(self) -> Self {
+                let (sin, cos) = self.value.sin_cos();
+                self.chain(sin, cos, -sin)
+            }
+
+            fn powi(self, n: i32) -> Self {
+                match n {
+                    0 => Self::constant(1.0),
+                    1 => self,
+                    _ => {
+                        let below = self.value.powi(n - 2);
+                        let n_f64 = n as f64;
+                        self.chain(
+                            below * self.value * self.value,
+                            n_f64 * below * self.value,
+                            n_f64 * (n_f64 - 1.0) * below,
+                        )
+                    }
+                }
+            }
+        }
+    };
+}
+
+impl_real!(Dual, gradient);
+impl_real!(HyperDual, gradient, hessian);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use nalgebra::Vector3;
+
+    /// Uses every operation, so that the test below checks all of them.
+    fn function<T: Real>(params: &SVector<T, 3>) -> T {
+        let (a, b, c) = (params.x, params.y, params.z);
+        let quotient = (a * b + c.sin()) / (c.exp() + 2.0);
+        let powers = a.powi(3) - b.powi(-2) + a.pow(c) * 0.5;
+        let roots = (a - 3.0).abs().sqrt() / 2.0;
+        quotient * (-b).exp() + powers + (c * a).ln() - roots
+    }
+
+    #[test]
+    fn test_derivatives() {
+        let params = Vector3::new(1.3, 0.7, 0.4);
+        let dual = function(&Dual::variables(&params));
+        let hyper_dual = function(&HyperDual::variables(&params));
+        assert_eq!(dual.value, function(&params));
+        assert_eq!(hyper_dual.value, function(&params));
+        assert!((dual.gradient - hyper_dual.gradient).norm() < 1e-14);
+
+        let h = 1e-5;
+        for i in 0..3 {
+            let step = Vector3::ith(i, h);
+            let numeric = (function(&(params + step)) - function(&(params - step))) / (2.0 * h);
+            assert!(
+                (dual.gradient[i] - numeric).abs() < 1e-8,
+                "derivative {} is {}, expected {}",
+                i,
+                dual.gradient[i],
+                numeric
+            );
+
+            let numeric = (function(&Dual::variables(&(params + step))).gradient
+                - function(&Dual::variables(&(params - step))).gradient)
+                / (2.0 * h);
+            for j in 0..3 {
+                assert!(
+                    (hyper_dual.hessian[(i, j)] - numeric[j]).abs()
+                        < 1e-8 * numeric[j].abs().max(1.0),
+                    "second derivative ({}, {}) is {}, expected {}",
+                    i,
+                    j,
+                    hyper_dual.hessian[(i, j)],
+                    numeric[j]
+                );
+            }
+        }
+    }
+}
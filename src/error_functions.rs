@@ -1,62 +1,1222 @@
 use itertools::izip;
-use nalgebra::{SMatrix, SVector};
+use nalgebra::{DefaultAllocator, Dim, OMatrix, OVector, U1};
+use std::any::Any;
+use std::cell::RefCell;
 use std::marker::PhantomData;
+use std::ops::{Add, Range, Sub};
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use strum::VariantNames;
+use strum_macros::{EnumIter, EnumString, VariantNames};
 
-use crate::functions::{Differentiated, Functions};
+use crate::functions::{Functions, Model, ParameterAllocator};
+use crate::loss::Loss;
+use crate::parameters::{Bounds, Tie};
+use crate::utils::{Dataset, SUMMATION_BLOCK, blockwise_sum, prettify_list};
+
+/// The number of error functions each thread keeps the last evaluation of. Error
+/// functions that are no longer used keep theirs until newer ones push them out.
+const CACHED_FUNCTIONS: usize = 4;
+/// The maximum number of Gauss-Newton steps used to correct an x-value.
+const CORRECTION_ITERATIONS: usize = 20;
+/// Corrections of x-values stop when a step is this small relative to σ_x.
+const CORRECTION_TOLERANCE: f64 = 1e-10;
+
+/// What the error function measures the disagreement between the function and the
+/// data with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, EnumIter, VariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum Objective {
+    /// The mean squared error, weighted by 1/σ² if the data has uncertainties.
+    #[default]
+    LeastSquares,
+    /// The mean Poisson deviance, 2(f - y + y ln(y/f)), for data that are counts,
+    /// such as histograms. The function is the expected count, so it must be positive.
+    Poisson,
+}
+
+impl Objective {
+    /// Tries to create an objective from a name, returns a string with
+    /// a descriptive error message if the name is invalid.
+    pub fn descriptive_from_str(s: &str) -> Result<Objective, String> {
+        Self::from_str(&s.to_lowercase()).map_err(|_| {
+            format!(
+                "Got malformed objective '{}'. Legal objectives are {}.",
+                s,
+                prettify_list(Self::VARIANTS)
+            )
+        })
+    }
+}
+
+/// How `ErrorFunction::hess` calculates the hessian.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, EnumIter, VariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum HessianMode {
+    /// The true hessian, which needs the hessian of the function.
+    #[default]
+    Exact,
+    /// The Gauss-Newton approximation, which only needs the gradient of the function
+    /// and is always positive semi-definite, but is only accurate near a good fit.
+    GaussNewton,
+}
+
+impl HessianMode {
+    /// Tries to create a hessian mode from a name, returns a string with
+    /// a descriptive error message if the name is invalid.
+    pub fn descriptive_from_str(s: &str) -> Result<HessianMode, String> {
+        Self::from_str(&s.to_lowercase()).map_err(|_| {
+            format!(
+                "Got malformed hessian mode '{}'. Legal hessian modes are {}.",
+                s,
+                prettify_list(Self::VARIANTS)
+            )
+        })
+    }
+}
+
+/// The Poisson deviance of a count y with expected count f, which is infinite when
+/// f is not positive.
+fn poisson_deviance(y: f64, f: f64) -> f64 {
+    if f <= 0.0 {
+        f64::INFINITY
+    } else if y == 0.0 {
+        2.0 * f
+    } else {
+        2.0 * (f - y + y * (y / f).ln())
+    }
+}
 
 /// Computes the outer product of a column vector
 #[inline]
-pub fn outer<const D: usize>(vector: &SVector<f64, D>) -> SMatrix<f64, D, D> {
+pub fn outer<D: Dim>(vector: &OVector<f64, D>) -> OMatrix<f64, D, D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
     vector * vector.transpose()
 }
 
-pub struct ErrorFunction<const D: usize, F: Differentiated<D>> {
+/// Finds the correction δ of an x-value with uncertainty that minimizes
+/// w_y(y - f(x + δ))² + w_xδ², where `f` and `dfdx` are the function and its
+/// derivative with respect to x. Each point is its own one-dimensional problem, so
+/// this is cheap even for many points. Returns δ and the derivative at x + δ.
+fn correct_x(
+    x: f64,
+    y: f64,
+    weight: f64,
+    x_weight: f64,
+    f: impl Fn(f64) -> f64,
+    dfdx: impl Fn(f64) -> f64,
+) -> (f64, f64) {
+    let objective = |delta: f64| weight * (y - f(x + delta)).powi(2) + x_weight * delta.powi(2);
+    let tolerance = CORRECTION_TOLERANCE / x_weight.sqrt();
+
+    let mut delta = 0.0;
+    let mut value = objective(delta);
+    let mut slope = dfdx(x);
+    for _ in 0..CORRECTION_ITERATIONS {
+        // minimize the objective with f linearized around x + δ
+        let residual = y - f(x + delta);
+        let mut step =
+            (weight * slope * residual - x_weight * delta) / (weight * slope.powi(2) + x_weight);
+        while objective(delta + step) > value && step.abs() > tolerance {
+            step *= 0.5;
+        }
+        if !step.is_finite() || objective(delta + step) > value {
+            break;
+        }
+
+        delta += step;
+        value = objective(delta);
+        slope = dfdx(x + delta);
+        if step.abs() <= tolerance {
+            break;
+        }
+    }
+    (delta, slope)
+}
+
+/// A data point as the error function sees it, where x has been corrected by
+/// `x_correction` if it has an uncertainty.
+struct Point {
+    x: f64,
+    /// The weighted square of the correction of x, which is part of the error.
+    penalty: f64,
+    /// The weight of the squared residual.
+    weight: f64,
+    /// The weight of the outer product of gradients in the hessian. When x has an
+    /// uncertainty, this is 1/(σ_y² + (σ_x df/dx)²), which accounts for the change
+    /// of the correction with the parameters.
+    curvature_weight: f64,
+}
+
+impl Point {
+    /// The weighted squared distance between the point and the function, given the
+    /// value of the function at the corrected x. The loss is applied to this.
+    fn distance(&self, y: f64, f: f64) -> f64 {
+        self.weight * (y - f).powi(2) + self.penalty
+    }
+}
+
+/// How much of the error function an evaluation calculates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Order {
+    Value,
+    Gradient,
+    Hessian(HessianMode),
+}
+
+/// The error function along with its gradient and hessian at some parameters.
+#[derive(Debug, Clone)]
+pub struct Evaluation<D: Dim>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    pub value: f64,
+    pub gradient: OVector<f64, D>,
+    pub hessian: OMatrix<f64, D, D>,
+}
+
+impl<D: Dim> Add for Evaluation<D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    type Output = Evaluation<D>;
+
+    fn add(self, rhs: Self) -> Self::Output {
+        Self {
+            value: self.value + rhs.value,
+            gradient: self.gradient + rhs.gradient,
+            hessian: self.hessian + rhs.hessian,
+        }
+    }
+}
+
+/// Gives every error function its own key in the caches of the threads.
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+thread_local! {
+    /// The last evaluation of the error functions this thread has used most recently,
+    /// by their id, with the most recent last. A cache's type depends on the error
+    /// function, so it is stored as `Any`.
+    static CACHES: RefCell<Vec<(usize, Box<dyn Any>)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The last evaluation of an error function by some thread, which answers repeated
+/// queries at the same internal parameters.
+struct Cache<D: Dim>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    internal: OVector<f64, D>,
+    value: f64,
+    gradient: Option<OVector<f64, D>>,
+    hessian: Option<(HessianMode, OMatrix<f64, D, D>)>,
+}
+
+impl<D: Dim> Cache<D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    /// The cached evaluation, if it is at `internal` and has everything `order` asks for.
+    fn get(&self, internal: &OVector<f64, D>, order: Order) -> Option<Evaluation<D>> {
+        if self.internal != *internal {
+            return None;
+        }
+        let dimension = internal.shape_generic().0;
+        let zeros = || OVector::zeros_generic(dimension, U1);
+        match order {
+            Order::Value => Some(Evaluation {
+                value: self.value,
+                gradient: self.gradient.clone().unwrap_or_else(zeros),
+                hessian: OMatrix::zeros_generic(dimension, dimension),
+            }),
+            Order::Gradient => self.gradient.clone().map(|gradient| Evaluation {
+                value: self.value,
+                gradient,
+                hessian: OMatrix::zeros_generic(dimension, dimension),
+            }),
+            Order::Hessian(mode) => match (&self.gradient, &self.hessian) {
+                (Some(gradient), Some((cached_mode, hessian))) if *cached_mode == mode => {
+                    Some(Evaluation {
+                        value: self.value,
+                        gradient: gradient.clone(),
+                        hessian: hessian.clone(),
+                    })
+                }
+                _ => None,
+            },
+        }
+    }
+}
+
+/// The number of times an error function and its derivatives have been evaluated.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct EvaluationCounts {
+    pub function: usize,
+    pub gradient: usize,
+    pub hessian: usize,
+}
+
+impl Sub for EvaluationCounts {
+    type Output = EvaluationCounts;
+
+    fn sub(self, rhs: Self) -> Self::Output {
+        Self {
+            function: self.function - rhs.function,
+            gradient: self.gradient - rhs.gradient,
+            hessian: self.hessian - rhs.hessian,
+        }
+    }
+}
+
+/// The mean squared error of a function on some data, weighted by 1/σ² if the data
+/// has uncertainties and passed through a robust loss if there is one, or the mean
+/// Poisson deviance if that is the objective. It is a function of the internal
+/// parameters the minimizers work with. These are the function's parameters,
+/// except for bounded parameters, which are transformed as described in `Bounds`,
+/// fixed parameters, which keep their value whatever the internal parameter is, and
+/// tied parameters, which follow the parameter they are tied to and ignore their own
+/// internal parameter and bounds.
+pub struct ErrorFunction<D: Dim, M: Model<D>>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    model: M,
     x_ray: Vec<f64>,
     y_ray: Vec<f64>,
+    /// The weight of each data point, which is 1/σ² for data with uncertainties.
+    weights: Vec<f64>,
+    /// The weight 1/σ_x² of the correction of each x-value, if x has uncertainties.
+    x_weights: Option<Vec<f64>>,
     ray_len: f64,
-    function: PhantomData<F>,
+    bounds: Vec<Bounds>,
+    fixed: Vec<Option<f64>>,
+    ties: Vec<Option<Tie>>,
+    loss: Loss,
+    objective: Objective,
+    hessian_mode: HessianMode,
+    /// The number of threads large datasets are split between, where zero means one
+    /// per core.
+    threads: usize,
+    function_evaluations: AtomicUsize,
+    gradient_evaluations: AtomicUsize,
+    hessian_evaluations: AtomicUsize,
+    /// The key of the error function in `CACHES`. Every thread has its own cache, so
+    /// threads sharing the error function don't overwrite each other's evaluations.
+    id: usize,
+    /// The type of the parameters, which only the caches store.
+    parameters: PhantomData<D>,
 }
 
-impl<const D: usize, F: Differentiated<D>> ErrorFunction<D, F> {
-    pub fn new(x_ray: &[f64], y_ray: &[f64]) -> Self {
+impl<D: Dim, M: Model<D>> ErrorFunction<D, M>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    pub fn new(model: M, x_ray: &[f64], y_ray: &[f64]) -> Self {
         let ray_len = x_ray.len() as f64;
+        let parameter_count = model.dimension().value();
         Self {
+            model,
             x_ray: x_ray.to_vec(),
             y_ray: y_ray.to_vec(),
+            weights: vec![1.0; x_ray.len()],
+            x_weights: None,
             ray_len,
-            function: PhantomData,
+            bounds: vec![Bounds::default(); parameter_count],
+            fixed: vec![None; parameter_count],
+            ties: vec![None; parameter_count],
+            loss: Loss::default(),
+            objective: Objective::default(),
+            hessian_mode: HessianMode::default(),
+            threads: 0,
+            function_evaluations: AtomicUsize::new(0),
+            gradient_evaluations: AtomicUsize::new(0),
+            hessian_evaluations: AtomicUsize::new(0),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+            parameters: PhantomData,
+        }
+    }
+
+    /// Create an error function for a dataset, where points are weighted by their
+    /// uncertainty if the dataset has uncertainties.
+    pub fn from_data(model: M, data: &Dataset) -> Self {
+        let mut error_function = Self::new(model, &data.x, &data.y);
+        if let Some(sigma) = &data.sigma {
+            error_function = error_function.with_sigma(sigma);
+        }
+        if let Some(x_sigma) = &data.x_sigma {
+            error_function = error_function.with_x_sigma(x_sigma);
+        }
+        error_function
+    }
+
+    /// Weight every data point by 1/σ², making this χ²/N.
+    pub fn with_sigma(mut self, sigma: &[f64]) -> Self {
+        self.weights = sigma.iter().map(|s| 1.0 / (s * s)).collect();
+        self
+    }
+
+    /// Fit errors in variables, where every x-value is corrected to the point on the
+    /// function closest to the data point, with distances in x measured in σ_x.
+    /// The error then includes the squared corrections, weighted by 1/σ_x².
+    pub fn with_x_sigma(mut self, x_sigma: &[f64]) -> Self {
+        self.x_weights = Some(x_sigma.iter().map(|s| 1.0 / (s * s)).collect());
+        self
+    }
+
+    /// Pass the squared distance of every point through a robust loss.
+    pub fn with_loss(mut self, loss: Loss) -> Self {
+        self.loss = loss;
+        self
+    }
+
+    /// Minimize another objective than the mean squared error. The Poisson deviance
+    /// ignores the uncertainties of the data and the loss.
+    pub fn with_objective(mut self, objective: Objective) -> Self {
+        self.objective = objective;
+        self
+    }
+
+    /// Choose how `hess` calculates the hessian.
+    pub fn with_hessian_mode(mut self, hessian_mode: HessianMode) -> Self {
+        self.hessian_mode = hessian_mode;
+        self
+    }
+
+    /// Split the data between this many threads when evaluating the error function on
+    /// large datasets, where zero means one thread per core. The result doesn't depend
+    /// on the number of threads.
+    pub fn with_threads(mut self, threads: usize) -> Self {
+        self.threads = threads;
+        self
+    }
+
+    /// The x-values where the function is not positive, which the Poisson deviance
+    /// can't be calculated for.
+    pub fn nonpositive_points(&self, params: &OVector<f64, D>) -> Vec<f64> {
+        self.x_ray
+            .iter()
+            .filter(|x| self.model.f(**x, params) <= 0.0)
+            .copied()
+            .collect()
+    }
+
+    /// The i-th data point, corrected for the given parameters. The Poisson deviance
+    /// never corrects x.
+    fn point(&self, i: usize, params: &OVector<f64, D>) -> Point {
+        let (x, y, weight) = (self.x_ray[i], self.y_ray[i], self.weights[i]);
+        let x_weights = self
+            .x_weights
+            .as_ref()
+            .filter(|_| self.objective == Objective::LeastSquares);
+        let Some(x_weights) = x_weights else {
+            return Point {
+                x,
+                penalty: 0.0,
+                weight,
+                curvature_weight: weight,
+            };
+        };
+
+        let x_weight = x_weights[i];
+        let (x_correction, slope) = correct_x(
+            x,
+            y,
+            weight,
+            x_weight,
+            |x| self.model.f(x, params),
+            |x| self.model.dfdx(x, params),
+        );
+        Point {
+            x: x + x_correction,
+            penalty: x_weight * x_correction.powi(2),
+            weight,
+            curvature_weight: weight * x_weight / (weight * slope.powi(2) + x_weight),
+        }
+    }
+
+    /// The data with every x-value corrected for the given parameters, and with y
+    /// uncertainties that include the uncertainties of x and the down-weighting of the
+    /// loss. Uncertainties calculated from this data account for both, where points
+    /// the loss ignores get an infinite uncertainty.
+    ///
+    /// For the Poisson deviance, the uncertainties are instead √f, the standard
+    /// deviation of a count with expected count f. Uncertainties calculated from this
+    /// data are then the ones given by the Fisher information.
+    pub fn corrected_data(&self, params: &OVector<f64, D>) -> Dataset {
+        if self.objective == Objective::Poisson {
+            return Dataset {
+                x: self.x_ray.clone(),
+                y: self.y_ray.clone(),
+                sigma: Some(
+                    self.x_ray
+                        .iter()
+                        .map(|x| self.model.f(*x, params).sqrt())
+                        .collect(),
+                ),
+                x_sigma: None,
+            };
+        }
+        let points: Vec<Point> = (0..self.x_ray.len())
+            .map(|i| self.point(i, params))
+            .collect();
+        let sigma = izip!(&points, &self.y_ray)
+            .map(|(p, y)| {
+                let loss_weight = self.loss.weight(p.distance(*y, self.model.f(p.x, params)));
+                (loss_weight * p.curvature_weight).powf(-0.5)
+            })
+            .collect();
+        Dataset {
+            x: points.iter().map(|p| p.x).collect(),
+            y: self.y_ray.clone(),
+            sigma: Some(sigma),
+            x_sigma: None,
+        }
+    }
+
+    /// The weight the loss gives each point relative to the squared loss, which is
+    /// one for every point unless the loss is robust.
+    pub fn loss_weights(&self, params: &OVector<f64, D>) -> Vec<f64> {
+        if self.objective == Objective::Poisson {
+            return vec![1.0; self.x_ray.len()];
+        }
+        (0..self.x_ray.len())
+            .map(|i| {
+                let point = self.point(i, params);
+                let f = self.model.f(point.x, params);
+                self.loss.weight(point.distance(self.y_ray[i], f))
+            })
+            .collect()
+    }
+
+    /// The number of times the error, its gradient and its hessian have been calculated.
+    /// Repeated queries at the same parameters are answered by a cache, so they are
+    /// not counted.
+    pub fn evaluation_counts(&self) -> EvaluationCounts {
+        EvaluationCounts {
+            function: self.function_evaluations.load(Ordering::Relaxed),
+            gradient: self.gradient_evaluations.load(Ordering::Relaxed),
+            hessian: self.hessian_evaluations.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Can `hess` be used? It panics if the function doesn't implement `hess`, unless
+    /// the hessian is the Gauss-Newton approximation.
+    pub fn has_hessian(&self) -> bool {
+        self.model.has_hessian() || self.hessian_mode == HessianMode::GaussNewton
+    }
+
+    /// Is `hess` the true hessian, and not an approximation that is always positive
+    /// semi-definite?
+    pub fn has_exact_hessian(&self) -> bool {
+        self.model.has_hessian() && self.hessian_mode == HessianMode::Exact
+    }
+
+    /// The function being fitted.
+    pub fn model(&self) -> &M {
+        &self.model
+    }
+
+    /// The number of parameters, as the dimension of the parameter vectors.
+    pub fn dimension(&self) -> D {
+        self.model.dimension()
+    }
+
+    /// Restrict the parameters to the given bounds.
+    pub fn with_bounds(mut self, bounds: &[Bounds]) -> Self {
+        self.bounds.copy_from_slice(bounds);
+        self
+    }
+
+    /// Keep the parameters that have a value at that value.
+    pub fn with_fixed(mut self, fixed: &[Option<f64>]) -> Self {
+        self.fixed.copy_from_slice(fixed);
+        self
+    }
+
+    /// Make the parameters that have a tie follow the parameter they are tied to.
+    pub fn with_ties(mut self, ties: &[Option<Tie>]) -> Self {
+        self.ties.copy_from_slice(ties);
+        self
+    }
+
+    /// Is the parameter fixed or tied, so that its internal parameter has no effect?
+    pub fn is_constrained(&self, i: usize) -> bool {
+        self.fixed[i].is_some() || self.ties[i].is_some()
+    }
+
+    /// Map internal parameters to the function's parameters.
+    pub fn external(&self, internal: &OVector<f64, D>) -> OVector<f64, D> {
+        let untied = OVector::<f64, D>::from_fn_generic(self.dimension(), U1, |i, _| {
+            self.fixed[i].unwrap_or_else(|| self.bounds[i].external(internal[i]))
+        });
+        OVector::from_fn_generic(self.dimension(), U1, |i, _| {
+            match (self.fixed[i], self.ties[i]) {
+                (None, Some(tie)) => tie.value(untied.as_slice()),
+                _ => untied[i],
+            }
+        })
+    }
+
+    /// Map the function's parameters to internal parameters.
+    pub fn internal(&self, params: &OVector<f64, D>) -> OVector<f64, D> {
+        OVector::from_fn_generic(self.dimension(), U1, |i, _| {
+            self.bounds[i].internal(params[i])
+        })
+    }
+
+    /// The jacobian of the function's parameters with respect to the internal
+    /// parameters, along with the matching second derivatives. As every parameter
+    /// depends on at most one internal parameter, the second derivatives of parameter
+    /// i are all zero, except for the one at (i, j) for the internal parameter j.
+    fn transform_derivatives(
+        &self,
+        internal: &OVector<f64, D>,
+    ) -> (OMatrix<f64, D, D>, OMatrix<f64, D, D>) {
+        let dimension = self.dimension();
+        let mut jacobian = OMatrix::zeros_generic(dimension, dimension);
+        let mut second = OMatrix::zeros_generic(dimension, dimension);
+        for i in (0..dimension.value()).filter(|i| self.fixed[*i].is_none()) {
+            let (j, factor) = self.ties[i].map_or((i, 1.0), |tie| (tie.to, tie.factor));
+            if self.fixed[j].is_none() {
+                let (d, d2) = self.bounds[j].derivatives(internal[j]);
+                jacobian[(i, j)] = factor * d;
+                second[(i, j)] = factor * d2;
+            }
         }
+        (jacobian, second)
+    }
+
+    /// Are any parameters transformed in a way that has second derivatives?
+    fn has_curved_transform(&self) -> bool {
+        self.bounds.iter().any(Bounds::is_bounded)
     }
 
-    pub fn f(&self, params: &SVector<f64, D>) -> f64 {
-        let mut sum = 0.0;
-        for (x, y) in izip!(self.x_ray.iter(), self.y_ray.iter()) {
-            sum += (y - F::f(*x, params)).powi(2);
+    /// Fixed and tied parameters have no curvature, which makes the hessian singular.
+    /// Giving them unit curvature instead keeps it invertible, and as their gradient
+    /// is zero, steps still never change them. This way, the minimizers effectively
+    /// work in the space of the free parameters.
+    fn fix_curvature(&self, hess: &mut OMatrix<f64, D, D>) {
+        for i in (0..self.fixed.len()).filter(|i| self.is_constrained(*i)) {
+            hess[(i, i)] = 1.0;
         }
-        sum / self.ray_len
     }
 
-    pub fn grad(&self, params: &SVector<f64, D>) -> SVector<f64, D> {
-        let mut gradient = SVector::<f64, D>::zeros();
-        for (x, y) in izip!(self.x_ray.iter(), self.y_ray.iter()) {
-            gradient += (y - F::f(*x, params)) * F::grad(*x, params);
+    /// The error along with the gradient and hessian with respect to the function's
+    /// parameters, as far as `order` asks for, calculated in a single pass over the
+    /// data. As the corrections of x minimize the error, their change with the
+    /// parameters does not contribute to the gradient. The hessian is approximate
+    /// when x has uncertainties, as it leaves out the derivatives of the function with
+    /// respect to both x and the parameters.
+    ///
+    /// Large datasets are evaluated in blocks split between threads, whose sums are
+    /// added pairwise to keep the rounding error small.
+    fn external_evaluation(&self, params: &OVector<f64, D>, order: Order) -> Evaluation<D> {
+        let sum = blockwise_sum(self.y_ray.len(), self.threads, |block| {
+            self.block_evaluation(block, params, order)
+        });
+        let scale = match self.objective {
+            Objective::LeastSquares => -2.0 / self.ray_len,
+            Objective::Poisson => 2.0 / self.ray_len,
+        };
+        Evaluation {
+            value: sum.value / self.ray_len,
+            gradient: scale * sum.gradient,
+            hessian: scale * sum.hessian,
         }
-        (-2.0 / self.ray_len) * gradient
     }
 
-    pub fn hess(&self, params: &SVector<f64, D>) -> SMatrix<f64, D, D> {
-        let mut hess = SMatrix::<f64, D, D>::zeros();
-        for (x, y) in izip!(self.x_ray.iter(), self.y_ray.iter()) {
-            hess += (y - F::f(*x, params)) * F::hess(*x, params) - outer(&F::grad(*x, params));
+    /// The sum of the errors of a block of points, along with the sums of their
+    /// derivatives up to the factor applied by `external_evaluation`.
+    fn block_evaluation(
+        &self,
+        block: Range<usize>,
+        params: &OVector<f64, D>,
+        order: Order,
+    ) -> Evaluation<D> {
+        let uncorrected = self.x_weights.is_none() || self.objective == Objective::Poisson;
+        let dimension = self.dimension();
+        let mut gradient = OVector::zeros_generic(dimension, U1);
+        let mut hessian = OMatrix::zeros_generic(dimension, dimension);
+        if order == Order::Value && uncorrected {
+            return Evaluation {
+                value: self.block_value(block, params),
+                gradient,
+                hessian,
+            };
         }
-        (-2.0 / self.ray_len) * hess
+
+        let mut value = 0.0;
+        for i in block {
+            let y = &self.y_ray[i];
+            let point = self.point(i, params);
+            let (f, grad, hess) = match order {
+                Order::Value => (self.model.f(point.x, params), None, None),
+                Order::Gradient | Order::Hessian(HessianMode::GaussNewton) => (
+                    self.model.f(point.x, params),
+                    Some(self.model.grad(point.x, params)),
+                    None,
+                ),
+                Order::Hessian(HessianMode::Exact) => {
+                    let (f, grad, hess) = self.model.evaluate_all(point.x, params);
+                    (f, Some(grad), Some(hess))
+                }
+            };
+
+            // the error of the point, along with its derivative with respect to f and
+            // the weight of the outer product of gradients in the hessian, both up to
+            // the factor `scale`
+            let (error, slope, curvature) = match self.objective {
+                Objective::LeastSquares => {
+                    let residual = y - f;
+                    let (error, loss_weight, loss_curvature) =
+                        self.loss.evaluate(point.distance(*y, f));
+                    let curvature = -loss_weight * point.curvature_weight;
+                    let curvature = match order {
+                        Order::Hessian(HessianMode::Exact) => {
+                            curvature - 2.0 * loss_curvature * (point.weight * residual).powi(2)
+                        }
+                        _ => curvature,
+                    };
+                    (error, loss_weight * point.weight * residual, curvature)
+                }
+                Objective::Poisson => {
+                    let curvature = match order {
+                        Order::Hessian(HessianMode::Exact) => y / (f * f),
+                        _ => 1.0 / f,
+                    };
+                    (poisson_deviance(*y, f), 1.0 - y / f, curvature)
+                }
+            };
+
+            value += error;
+            match (order, grad, hess) {
+                (Order::Gradient, Some(grad), _) => gradient += slope * grad,
+                (Order::Hessian(HessianMode::GaussNewton), Some(grad), _) => {
+                    gradient += slope * &grad;
+                    hessian += curvature * outer(&grad);
+                }
+                (Order::Hessian(HessianMode::Exact), Some(grad), Some(hess)) => {
+                    gradient += slope * &grad;
+                    hessian += slope * hess + curvature * outer(&grad);
+                }
+                _ => {}
+            }
+        }
+        Evaluation {
+            value,
+            gradient,
+            hessian,
+        }
+    }
+
+    /// The sum of the errors of a block of points whose x-values need no correction.
+    /// The function is evaluated in a loop of its own, which the compiler can
+    /// vectorise for simple functions.
+    fn block_value(&self, block: Range<usize>, params: &OVector<f64, D>) -> f64 {
+        let mut f_ray = [0.0; SUMMATION_BLOCK];
+        let f_ray = &mut f_ray[..block.len()];
+        for (f, x) in izip!(f_ray.iter_mut(), &self.x_ray[block.clone()]) {
+            *f = self.model.f(*x, params);
+        }
+
+        let (y_ray, weights) = (&self.y_ray[block.clone()], &self.weights[block]);
+        let mut value = 0.0;
+        match self.objective {
+            Objective::LeastSquares => {
+                for (y, weight, f) in izip!(y_ray, weights, f_ray.iter()) {
+                    value += self.loss.evaluate(weight * (y - f).powi(2)).0;
+                }
+            }
+            Objective::Poisson => {
+                for (y, f) in izip!(y_ray, f_ray.iter()) {
+                    value += poisson_deviance(*y, *f);
+                }
+            }
+        }
+        value
+    }
+
+    /// The error and as much of its derivatives with respect to the internal parameters
+    /// as `order` asks for. The last evaluation is cached, so asking for the error,
+    /// gradient and hessian at the same parameters only passes over the data once.
+    fn evaluate(&self, internal: &OVector<f64, D>, order: Order) -> Evaluation<D> {
+        let cached = CACHES.with_borrow(|caches| {
+            let (_, cache) = caches.iter().find(|(id, _)| *id == self.id)?;
+            cache.downcast_ref::<Cache<D>>()?.get(internal, order)
+        });
+        if let Some(evaluation) = cached {
+            return evaluation;
+        }
+
+        self.function_evaluations.fetch_add(1, Ordering::Relaxed);
+        if order != Order::Value {
+            self.gradient_evaluations.fetch_add(1, Ordering::Relaxed);
+        }
+        let params = self.external(internal);
+        let external = self.external_evaluation(&params, order);
+        let (jacobian, second) = self.transform_derivatives(internal);
+        let gradient = jacobian.transpose() * &external.gradient;
+
+        let dimension = self.dimension();
+        let mut hessian = OMatrix::zeros_generic(dimension, dimension);
+        if let Order::Hessian(mode) = order {
+            self.hessian_evaluations.fetch_add(1, Ordering::Relaxed);
+            // the chain rule, where the second derivatives of the transform only
+            // contribute to the diagonal
+            hessian = jacobian.transpose() * external.hessian * &jacobian;
+            if self.has_curved_transform() {
+                let curvature = second.transpose() * &external.gradient;
+                for i in 0..dimension.value() {
+                    hessian[(i, i)] += match mode {
+                        HessianMode::Exact => curvature[i],
+                        HessianMode::GaussNewton => curvature[i].max(0.0),
+                    };
+                }
+            }
+            self.fix_curvature(&mut hessian);
+        }
+
+        let cache = Cache {
+            internal: internal.clone(),
+            value: external.value,
+            gradient: (order != Order::Value).then(|| gradient.clone()),
+            hessian: match order {
+                Order::Hessian(mode) => Some((mode, hessian.clone())),
+                _ => None,
+            },
+        };
+        CACHES.with_borrow_mut(|caches| {
+            caches.retain(|(id, _)| *id != self.id);
+            if caches.len() >= CACHED_FUNCTIONS {
+                caches.remove(0);
+            }
+            caches.push((self.id, Box::new(cache)));
+        });
+        Evaluation {
+            value: external.value,
+            gradient,
+            hessian,
+        }
+    }
+
+    pub fn f(&self, internal: &OVector<f64, D>) -> f64 {
+        self.evaluate(internal, Order::Value).value
+    }
+
+    pub fn grad(&self, internal: &OVector<f64, D>) -> OVector<f64, D> {
+        self.evaluate(internal, Order::Gradient).gradient
+    }
+
+    /// The hessian, which is approximate when x has uncertainties, as it leaves out
+    /// the derivatives of the function with respect to both x and the parameters.
+    /// Robust losses can make it indefinite, as they bend down for large residuals.
+    /// With the Gauss-Newton hessian mode, this is `gauss_newton_hess` instead.
+    pub fn hess(&self, internal: &OVector<f64, D>) -> OMatrix<f64, D, D> {
+        self.evaluate(internal, Order::Hessian(self.hessian_mode))
+            .hessian
+    }
+
+    /// The error, gradient and hessian in a single pass over the data, which is
+    /// cheaper than asking for them one by one.
+    pub fn evaluate_all(&self, internal: &OVector<f64, D>) -> Evaluation<D> {
+        self.evaluate(internal, Order::Hessian(self.hessian_mode))
+    }
+
+    /// The Gauss-Newton approximation of the hessian, 2JᵀWJ/N, where J is the
+    /// jacobian of the residuals and W holds the weights, including the weights of the
+    /// loss. For robust losses, this makes Levenberg-Marquardt a form of iteratively
+    /// reweighted least squares. Unlike the true hessian, this is always positive
+    /// semi-definite. For the Poisson deviance, this is the Fisher information
+    /// 2JᵀJ/(fN), which is the expected hessian.
+    ///
+    /// Near a bound, JᵀJ vanishes for the transformed parameter, so the positive
+    /// part of the curvature added by the transform is kept. Otherwise steps would
+    /// overshoot the bound back and forth instead of converging to it.
+    pub fn gauss_newton_hess(&self, internal: &OVector<f64, D>) -> OMatrix<f64, D, D> {
+        self.evaluate(internal, Order::Hessian(HessianMode::GaussNewton))
+            .hessian
     }
 }
 
-pub fn error(x_ray: &[f64], y_ray: &[f64], function: &Functions, parameters: &[f64]) -> f64 {
+/// The same error as `ErrorFunction::f`, for a function chosen at runtime.
+pub fn error(
+    data: &Dataset,
+    function: &Functions,
+    parameters: &[f64],
+    loss: &Loss,
+    objective: Objective,
+) -> f64 {
     let mut sum = 0.0;
-    for (x, y) in izip!(x_ray.iter(), y_ray.iter()) {
-        sum += (y - function.f(*x, parameters)).powi(2);
+    for (i, (x, y)) in izip!(data.x.iter(), data.y.iter()).enumerate() {
+        if objective == Objective::Poisson {
+            sum += poisson_deviance(*y, function.f(*x, parameters));
+            continue;
+        }
+        let weight = data.sigma.as_ref().map_or(1.0, |sigma| sigma[i].powi(-2));
+        let Some(x_sigma) = &data.x_sigma else {
+            sum += loss
+                .evaluate(weight * (y - function.f(*x, parameters)).powi(2))
+                .0;
+            continue;
+        };
+
+        let x_weight = x_sigma[i].powi(-2);
+        let (x_correction, _) = correct_x(
+            *x,
+            *y,
+            weight,
+            x_weight,
+            |x| function.f(x, parameters),
+            |x| function.dfdx(x, parameters),
+        );
+        sum += loss
+            .evaluate(
+                weight * (y - function.f(x + x_correction, parameters)).powi(2)
+                    + x_weight * x_correction.powi(2),
+            )
+            .0;
+    }
+    sum / (data.x.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::functions::decay::Decay;
+    use crate::functions::line::Line;
+    use crate::loss::{DOWN_WEIGHT_THRESHOLD, LossFunction};
+    use crate::minimizers::{Combined, Minimizer, MinimizerConfig, RefinementStage};
+    use crate::parameters::{Bounds, ParameterStatus};
+    use crate::statistics::get_uncertainties;
+    use nalgebra::Vector2;
+    use std::thread;
+    use strum::IntoEnumIterator;
+
+    /// Points scattered around a line in both x and y.
+    fn scattered_line() -> Dataset {
+        let x: Vec<f64> = (0..50)
+            .map(|i| i as f64 / 10.0 + 0.3 * (i as f64).sin())
+            .collect();
+        let y: Vec<f64> = (0..50)
+            .map(|i| 2.0 * i as f64 / 10.0 + 1.0 + 0.3 * (1.7 * i as f64).cos())
+            .collect();
+        Dataset {
+            sigma: Some(vec![0.3; x.len()]),
+            x_sigma: Some(vec![0.3; x.len()]),
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn test_errors_in_variables() {
+        let data = scattered_line();
+        let error_function = ErrorFunction::from_data(Line, &data);
+
+        // the gradient ignores how the corrections change, which is exact at the corrections
+        let p = Vector2::new(1.5, 0.5);
+        let h = 1e-6;
+        for i in 0..2 {
+            let step = Vector2::from_fn(|j, _| if i == j { h } else { 0.0 });
+            let numeric =
+                (error_function.f(&(p + step)) - error_function.f(&(p - step))) / (2.0 * h);
+            assert!((error_function.grad(&p)[i] - numeric).abs() < 1e-6);
+        }
+
+        // with equal uncertainties in x and y, the fit is the orthogonal regression line
+        let n = data.x.len() as f64;
+        let (mean_x, mean_y) = (
+            data.x.iter().sum::<f64>() / n,
+            data.y.iter().sum::<f64>() / n,
+        );
+        let (mut sxx, mut syy, mut sxy) = (0.0, 0.0, 0.0);
+        for (x, y) in izip!(&data.x, &data.y) {
+            sxx += (x - mean_x).powi(2);
+            syy += (y - mean_y).powi(2);
+            sxy += (x - mean_x) * (y - mean_y);
+        }
+        let slope = (syy - sxx + ((syy - sxx).powi(2) + 4.0 * sxy.powi(2)).sqrt()) / (2.0 * sxy);
+        let expected = Vector2::new(slope, mean_y - slope * mean_x);
+
+        let combined = Combined {
+            refinement: RefinementStage::LevenbergMarquardt,
+        };
+        let (optimal, report) = combined.minimize(
+            &error_function,
+            &Vector2::new(1.0, 1.0),
+            &MinimizerConfig::default(),
+        );
+        assert!(report.termination.is_success(), "{}", report.termination);
+        assert!(
+            (optimal - expected).abs().max() < 1e-8,
+            "{} != {}",
+            optimal,
+            expected
+        );
+
+        // the error of a function chosen at runtime matches
+        let runtime_error = error(
+            &data,
+            &Functions::Line,
+            optimal.as_slice(),
+            &Loss::default(),
+            Objective::LeastSquares,
+        );
+        assert!((runtime_error - error_function.f(&optimal)).abs() < 1e-12);
+
+        // the corrected points lie on the line, so the uncertainties include x
+        let corrected = error_function.corrected_data(&optimal);
+        let sigma = corrected.sigma.unwrap();
+        assert!((sigma[0] - 0.3 * (1.0 + slope.powi(2)).sqrt()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_robust_loss() {
+        // a line with a little noise and a single far outlier
+        let x: Vec<f64> = (0..30).map(|i| i as f64 / 3.0).collect();
+        let mut y: Vec<f64> = x
+            .iter()
+            .enumerate()
+            .map(|(i, x)| 2.0 * x + 1.0 + 0.05 * (2.3 * i as f64).sin())
+            .collect();
+        y[12] += 20.0;
+
+        let p = Vector2::new(1.8, 1.2);
+        let h = 1e-5;
+        for function in LossFunction::iter() {
+            let loss = Loss {
+                function,
+                scale: 0.5,
+            };
+            let error_function = ErrorFunction::new(Line, &x, &y).with_loss(loss);
+
+            // the derivatives include the derivatives of the loss
+            let (grad, hess) = (error_function.grad(&p), error_function.hess(&p));
+            for i in 0..2 {
+                let step = Vector2::from_fn(|j, _| if i == j { h } else { 0.0 });
+                let numeric =
+                    (error_function.f(&(p + step)) - error_function.f(&(p - step))) / (2.0 * h);
+                assert!((grad[i] - numeric).abs() < 1e-5, "{:?}", function);
+                let numeric = (error_function.grad(&(p + step)) - error_function.grad(&(p - step)))
+                    / (2.0 * h);
+                assert!(
+                    (hess.column(i) - numeric).abs().max() < 1e-4,
+                    "{:?}",
+                    function
+                );
+            }
+
+            let combined = Combined {
+                refinement: RefinementStage::LevenbergMarquardt,
+            };
+            let (optimal, report) = combined.minimize(
+                &error_function,
+                &Vector2::new(1.0, 1.0),
+                &MinimizerConfig::default(),
+            );
+            assert!(report.termination.is_success(), "{}", report.termination);
+
+            // the outlier pulls the squared loss away, but not the robust losses
+            let weights = error_function.loss_weights(&optimal);
+            if loss.is_robust() {
+                assert!(
+                    (optimal - Vector2::new(2.0, 1.0)).abs().max() < 0.05,
+                    "{:?} gave {}",
+                    function,
+                    optimal
+                );
+                assert!(weights[12] < DOWN_WEIGHT_THRESHOLD, "{:?}", function);
+                assert!(weights[0] > DOWN_WEIGHT_THRESHOLD, "{:?}", function);
+            } else {
+                assert!((optimal - Vector2::new(2.0, 1.0)).abs().max() > 0.05);
+                assert!(weights.iter().all(|w| *w == 1.0));
+            }
+
+            let data = Dataset {
+                x: x.clone(),
+                y: y.clone(),
+                ..Default::default()
+            };
+            let runtime_error = error(
+                &data,
+                &Functions::Line,
+                optimal.as_slice(),
+                &loss,
+                Objective::LeastSquares,
+            );
+            assert!((runtime_error - error_function.f(&optimal)).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_evaluate_all() {
+        let data = scattered_line();
+        let loss = Loss {
+            function: LossFunction::Huber,
+            scale: 1.0,
+        };
+        let bounds = [Bounds::parse("0:3").unwrap(), Bounds::default()];
+        let create = || {
+            ErrorFunction::from_data(Line, &data)
+                .with_loss(loss)
+                .with_bounds(&bounds)
+        };
+        let error_function = create();
+        let p = Vector2::new(0.4, 0.5);
+
+        // a single pass gives the same as separate evaluations
+        let evaluation = error_function.evaluate_all(&p);
+        assert_eq!(evaluation.value, create().f(&p));
+        assert_eq!(evaluation.gradient, create().grad(&p));
+        assert_eq!(evaluation.hessian, create().hess(&p));
+        let counts = EvaluationCounts {
+            function: 1,
+            gradient: 1,
+            hessian: 1,
+        };
+        assert_eq!(error_function.evaluation_counts(), counts);
+
+        // repeated queries at the same parameters come from the cache
+        error_function.f(&p);
+        error_function.grad(&p);
+        error_function.hess(&p);
+        assert_eq!(error_function.evaluation_counts(), counts);
+
+        // the Gauss-Newton hessian and other parameters need another pass
+        assert_eq!(
+            error_function.gauss_newton_hess(&p),
+            create().gauss_newton_hess(&p)
+        );
+        error_function.f(&(2.0 * p));
+        let counts = EvaluationCounts {
+            function: 3,
+            gradient: 2,
+            hessian: 2,
+        };
+        assert_eq!(error_function.evaluation_counts(), counts);
+
+        // every thread has its own cache
+        thread::scope(|scope| {
+            scope.spawn(|| error_function.f(&p));
+        });
+        assert_eq!(error_function.evaluation_counts().function, 4);
+        error_function.f(&(2.0 * p));
+        assert_eq!(error_function.evaluation_counts().function, 4);
+    }
+
+    #[test]
+    fn test_poisson() {
+        // counts from a decay, with a bin that has no counts
+        let x: Vec<f64> = (0..20).map(|i| i as f64).collect();
+        let mut y: Vec<f64> = x
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                (30.0 * (-0.2 * x).exp() + 2.0 * (1.3 * i as f64).sin())
+                    .round()
+                    .max(0.0)
+            })
+            .collect();
+        y[15] = 0.0;
+        let error_function = ErrorFunction::new(Decay, &x, &y).with_objective(Objective::Poisson);
+
+        let p = Vector2::new(25.0, 0.25);
+        let h = 1e-6;
+        let (grad, hess) = (error_function.grad(&p), error_function.hess(&p));
+        for i in 0..2 {
+            let step = Vector2::from_fn(|j, _| if i == j { h * p[i] } else { 0.0 });
+            let numeric =
+                (error_function.f(&(p + step)) - error_function.f(&(p - step))) / (2.0 * step[i]);
+            assert!((grad[i] - numeric).abs() < 1e-5 * grad[i].abs().max(1.0));
+            let numeric = (error_function.grad(&(p + step)) - error_function.grad(&(p - step)))
+                / (2.0 * step[i]);
+            assert!((hess.column(i) - numeric).abs().max() < 1e-4 * hess.abs().max());
+        }
+
+        let combined = Combined {
+            refinement: RefinementStage::LevenbergMarquardt,
+        };
+        let (optimal, report) = combined.minimize(&error_function, &p, &MinimizerConfig::default());
+        assert!(report.termination.is_success(), "{}", report.termination);
+
+        // the amplitude makes the expected total count equal the total count
+        let expected_total: f64 = x.iter().map(|x| Decay.f(*x, &optimal)).sum();
+        assert!((expected_total - y.iter().sum::<f64>()).abs() < 1e-6);
+
+        // the uncertainties are the ones given by the Fisher information
+        let data = error_function.corrected_data(&optimal);
+        let statuses = [ParameterStatus::Free; 2];
+        let uncertainties = get_uncertainties(&Decay, &data, &optimal, &statuses, true);
+        let fisher = error_function.gauss_newton_hess(&optimal) * (x.len() as f64 / 2.0);
+        let expected = fisher.try_inverse().unwrap().diagonal().map(f64::sqrt);
+        assert!((uncertainties - expected).abs().max() < 1e-9 * expected.max());
+
+        let runtime_data = Dataset {
+            x: x.clone(),
+            y: y.clone(),
+            ..Default::default()
+        };
+        let runtime_error = error(
+            &runtime_data,
+            &Functions::Decay,
+            optimal.as_slice(),
+            &Loss::default(),
+            Objective::Poisson,
+        );
+        assert!((runtime_error - error_function.f(&optimal)).abs() < 1e-12);
+
+        // the deviance is infinite where the function is not positive
+        let negative = Vector2::new(-1.0, 0.2);
+        assert_eq!(error_function.nonpositive_points(&negative).len(), x.len());
+        assert_eq!(error_function.f(&negative), f64::INFINITY);
+    }
+
+    /// Many points along a decay with a bit of noise.
+    fn large_decay(len: usize) -> Dataset {
+        let x: Vec<f64> = (0..len).map(|i| i as f64 * 5.0 / len as f64).collect();
+        let y: Vec<f64> = (x.iter().enumerate())
+            .map(|(i, x)| 3.0 * (-0.7 * x).exp() + 0.01 * (i as f64 * 1.3).sin())
+            .collect();
+        Dataset {
+            sigma: Some(vec![0.01; len]),
+            x_sigma: None,
+            x,
+            y,
+        }
+    }
+
+    #[test]
+    fn test_parallel_evaluation() {
+        let data = large_decay(crate::utils::PARALLEL_THRESHOLD + 100);
+        let loss = Loss {
+            function: LossFunction::Huber,
+            scale: 1.0,
+        };
+        let create = |threads| {
+            ErrorFunction::from_data(Decay, &data)
+                .with_loss(loss)
+                .with_threads(threads)
+        };
+        let p = Vector2::new(2.9, 0.72);
+
+        // the result doesn't depend on the number of threads
+        let serial = create(1).evaluate_all(&p);
+        let parallel = create(4).evaluate_all(&p);
+        assert_eq!(serial.value, parallel.value);
+        assert_eq!(serial.gradient, parallel.gradient);
+        assert_eq!(serial.hessian, parallel.hessian);
+        assert_eq!(create(4).f(&p), serial.value);
+
+        let expected = error(
+            &data,
+            &Functions::Decay,
+            p.as_slice(),
+            &loss,
+            Objective::LeastSquares,
+        );
+        assert!((serial.value - expected).abs() < 1e-10 * expected);
     }
-    sum / (x_ray.len() as f64)
 }
@@ -0,0 +1,556 @@
+use strum::VariantNames;
+use strum_macros::{EnumString, VariantNames};
+
+use std::str::FromStr;
+
+use crate::utils::prettify_list;
+
+/// The functions a formula can call.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, EnumString, VariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum Elementary {
+    Exp,
+    Ln,
+    Sqrt,
+    Sin,
+    Cos,
+    Tan,
+    Atan,
+    Sinh,
+    Cosh,
+    Tanh,
+    Abs,
+    Sign,
+}
+
+impl Elementary {
+    fn apply(&self, value: f64) -> f64 {
+        match self {
+            Self::Exp => value.exp(),
+            Self::Ln => value.ln(),
+            Self::Sqrt => value.sqrt(),
+            Self::Sin => value.sin(),
+            Self::Cos => value.cos(),
+            Self::Tan => value.tan(),
+            Self::Atan => value.atan(),
+            Self::Sinh => value.sinh(),
+            Self::Cosh => value.cosh(),
+            Self::Tanh => value.tanh(),
+            Self::Abs => value.abs(),
+            Self::Sign => value.signum(),
+        }
+    }
+
+    /// The derivative of the function at `argument`.
+    fn derivative(&self, argument: &Expression) -> Expression {
+        use Expression as E;
+        let call = |function| E::call(function, argument.clone());
+        match self {
+            Self::Exp => call(Self::Exp),
+            Self::Ln => E::quotient(E::Number(1.0), argument.clone()),
+            Self::Sqrt => E::quotient(E::Number(0.5), call(Self::Sqrt)),
+            Self::Sin => call(Self::Cos),
+            Self::Cos => E::negate(call(Self::Sin)),
+            Self::Tan => E::quotient(E::Number(1.0), E::power(call(Self::Cos), E::Number(2.0))),
+            Self::Atan => E::quotient(
+                E::Number(1.0),
+                E::sum(E::Number(1.0), E::power(argument.clone(), E::Number(2.0))),
+            ),
+            Self::Sinh => call(Self::Cosh),
+            Self::Cosh => call(Self::Sinh),
+            Self::Tanh => E::difference(E::Number(1.0), E::power(call(Self::Tanh), E::Number(2.0))),
+            Self::Abs => call(Self::Sign),
+            Self::Sign => E::Number(0.0),
+        }
+    }
+}
+
+/// A mathematical expression of x and some parameters.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expression {
+    Number(f64),
+    X,
+    /// The parameter with the given index.
+    Parameter(usize),
+    Negate(Box<Expression>),
+    Sum(Box<Expression>, Box<Expression>),
+    Difference(Box<Expression>, Box<Expression>),
+    Product(Box<Expression>, Box<Expression>),
+    Quotient(Box<Expression>, Box<Expression>),
+    Power(Box<Expression>, Box<Expression>),
+    Call(Elementary, Box<Expression>),
+}
+
+/// The constructors below simplify as they go, so that derivatives don't fill up with
+/// terms that are multiplied by zero.
+impl Expression {
+    fn number(&self) -> Option<f64> {
+        match self {
+            Self::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn negate(a: Expression) -> Expression {
+        match a {
+            Self::Number(a) => Self::Number(-a),
+            Self::Negate(a) => *a,
+            a => Self::Negate(Box::new(a)),
+        }
+    }
+
+    pub fn sum(a: Expression, b: Expression) -> Expression {
+        match (a.number(), b.number()) {
+            (Some(a), Some(b)) => Self::Number(a + b),
+            (Some(0.0), _) => b,
+            (_, Some(0.0)) => a,
+            _ => Self::Sum(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn difference(a: Expression, b: Expression) -> Expression {
+        match (a.number(), b.number()) {
+            (Some(a), Some(b)) => Self::Number(a - b),
+            (Some(0.0), _) => Self::negate(b),
+            (_, Some(0.0)) => a,
+            _ => Self::Difference(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn product(a: Expression, b: Expression) -> Expression {
+        match (a.number(), b.number()) {
+            (Some(a), Some(b)) => Self::Number(a * b),
+            (Some(0.0), _) | (_, Some(0.0)) => Self::Number(0.0),
+            (Some(1.0), _) => b,
+            (_, Some(1.0)) => a,
+            (Some(-1.0), _) => Self::negate(b),
+            (_, Some(-1.0)) => Self::negate(a),
+            _ => Self::Product(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn quotient(a: Expression, b: Expression) -> Expression {
+        match (a.number(), b.number()) {
+            (Some(a), Some(b)) if b != 0.0 => Self::Number(a / b),
+            (Some(0.0), _) => Self::Number(0.0),
+            (_, Some(1.0)) => a,
+            _ => Self::Quotient(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn power(a: Expression, b: Expression) -> Expression {
+        match (a.number(), b.number()) {
+            (Some(a), Some(b)) => Self::Number(a.powf(b)),
+            (_, Some(0.0)) => Self::Number(1.0),
+            (_, Some(1.0)) => a,
+            _ => Self::Power(Box::new(a), Box::new(b)),
+        }
+    }
+
+    pub fn call(function: Elementary, a: Expression) -> Expression {
+        match a {
+            Self::Number(a) => Self::Number(function.apply(a)),
+            a => Self::Call(function, Box::new(a)),
+        }
+    }
+
+    /// Does the expression depend on `variable`, which is either x or a parameter?
+    fn depends_on(&self, variable: &Expression) -> bool {
+        match self {
+            Self::Number(_) => false,
+            Self::X | Self::Parameter(_) => self == variable,
+            Self::Negate(a) | Self::Call(_, a) => a.depends_on(variable),
+            Self::Sum(a, b)
+            | Self::Difference(a, b)
+            | Self::Product(a, b)
+            | Self::Quotient(a, b)
+            | Self::Power(a, b) => a.depends_on(variable) || b.depends_on(variable),
+        }
+    }
+
+    pub fn evaluate(&self, x: f64, params: &[f64]) -> f64 {
+        match self {
+            Self::Number(value) => *value,
+            Self::X => x,
+            Self::Parameter(i) => params[*i],
+            Self::Negate(a) => -a.evaluate(x, params),
+            Self::Sum(a, b) => a.evaluate(x, params) + b.evaluate(x, params),
+            Self::Difference(a, b) => a.evaluate(x, params) - b.evaluate(x, params),
+            Self::Product(a, b) => a.evaluate(x, params) * b.evaluate(x, params),
+            Self::Quotient(a, b) => a.evaluate(x, params) / b.evaluate(x, params),
+            Self::Power(a, b) => a.evaluate(x, params).powf(b.evaluate(x, params)),
+            Self::Call(function, a) => function.apply(a.evaluate(x, params)),
+        }
+    }
+
+    /// The derivative with respect to `variable`, which is either x or a parameter.
+    pub fn derivative(&self, variable: &Expression) -> Expression {
+        if !self.depends_on(variable) {
+            return Self::Number(0.0);
+        }
+        let d = |a: &Expression| a.derivative(variable);
+        match self {
+            Self::Number(_) => Self::Number(0.0),
+            Self::X | Self::Parameter(_) => Self::Number(1.0),
+            Self::Negate(a) => Self::negate(d(a)),
+            Self::Sum(a, b) => Self::sum(d(a), d(b)),
+            Self::Difference(a, b) => Self::difference(d(a), d(b)),
+            Self::Product(a, b) => Self::sum(
+                Self::product(d(a), (**b).clone()),
+                Self::product((**a).clone(), d(b)),
+            ),
+            Self::Quotient(a, b) => Self::quotient(
+                Self::difference(
+                    Self::product(d(a), (**b).clone()),
+                    Self::product((**a).clone(), d(b)),
+                ),
+                Self::power((**b).clone(), Self::Number(2.0)),
+            ),
+            // a^b with a constant exponent is b a^(b - 1) a', which also works for
+            // negative a, unlike the general case
+            Self::Power(a, b) if !b.depends_on(variable) => Self::product(
+                Self::product(
+                    (**b).clone(),
+                    Self::power(
+                        (**a).clone(),
+                        Self::difference((**b).clone(), Self::Number(1.0)),
+                    ),
+                ),
+                d(a),
+            ),
+            Self::Power(a, b) => Self::product(
+                self.clone(),
+                Self::sum(
+                    Self::product(d(b), Self::call(Elementary::Ln, (**a).clone())),
+                    Self::quotient(Self::product((**b).clone(), d(a)), (**a).clone()),
+                ),
+            ),
+            Self::Call(function, a) => Self::product(function.derivative(a), d(a)),
+        }
+    }
+}
+
+/// Parse a formula of x, where every other name is a parameter, like `a*exp(-l*x) + c`.
+/// Returns the expression and the names of the parameters, in the order they first
+/// appear in the formula.
+pub fn parse(formula: &str) -> Result<(Expression, Vec<String>), String> {
+    let mut parser = Parser {
+        chars: formula.chars().collect(),
+        position: 0,
+        names: Vec::new(),
+    };
+    let expression = parser.expression()?;
+    parser.skip_whitespace();
+    if let Some(c) = parser.peek() {
+        return Err(parser.unexpected(c));
+    }
+    Ok((expression, parser.names))
+}
+
+/// A recursive descent parser, where each method parses one level of precedence.
+struct Parser {
+    chars: Vec<char>,
+    position: usize,
+    names: Vec<String>,
+}
+
+impl Parser {
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    /// Skip whitespace and consume `token` if it comes next.
+    fn eat(&mut self, token: &str) -> bool {
+        self.skip_whitespace();
+        let end = self.position + token.chars().count();
+        let matches = end <= self.chars.len()
+            && self.chars[self.position..end]
+                .iter()
+                .copied()
+                .eq(token.chars());
+        if matches {
+            self.position = end;
+        }
+        matches
+    }
+
+    fn unexpected(&self, c: char) -> String {
+        format!(
+            "Unexpected '{}' at position {} of the formula.",
+            c,
+            self.position + 1
+        )
+    }
+
+    /// Terms separated by + and -.
+    fn expression(&mut self) -> Result<Expression, String> {
+        let mut expression = self.term()?;
+        loop {
+            if self.eat("+") {
+                expression = Expression::sum(expression, self.term()?);
+            } else if self.eat("-") {
+                expression = Expression::difference(expression, self.term()?);
+            } else {
+                return Ok(expression);
+            }
+        }
+    }
+
+    /// Factors separated by * and /.
+    fn term(&mut self) -> Result<Expression, String> {
+        let mut term = self.factor()?;
+        loop {
+            if self.eat("*") {
+                term = Expression::product(term, self.factor()?);
+            } else if self.eat("/") {
+                term = Expression::quotient(term, self.factor()?);
+            } else {
+                return Ok(term);
+            }
+        }
+    }
+
+    /// A power, possibly with a sign in front, which binds looser than the power, so
+    /// that -x^2 is -(x^2).
+    fn factor(&mut self) -> Result<Expression, String> {
+        if self.eat("-") {
+            Ok(Expression::negate(self.factor()?))
+        } else if self.eat("+") {
+            self.factor()
+        } else {
+            self.power()
+        }
+    }
+
+    /// An atom, possibly raised to a power with ^ or **, which is right associative.
+    fn power(&mut self) -> Result<Expression, String> {
+        let base = self.atom()?;
+        if self.eat("^") || self.eat("**") {
+            Ok(Expression::power(base, self.factor()?))
+        } else {
+            Ok(base)
+        }
+    }
+
+    /// A number, x, a parameter, a function call or an expression in parentheses.
+    fn atom(&mut self) -> Result<Expression, String> {
+        self.skip_whitespace();
+        let Some(c) = self.peek() else {
+            return Err("The formula ended unexpectedly.".into());
+        };
+
+        if self.eat("(") {
+            let expression = self.expression()?;
+            return if self.eat(")") {
+                Ok(expression)
+            } else {
+                Err(format!(
+                    "Missing ')' at position {} of the formula.",
+                    self.position + 1
+                ))
+            };
+        }
+
+        if c.is_ascii_digit() || c == '.' {
+            return self.number();
+        }
+
+        if !(c.is_alphabetic() || c == '_') {
+            return Err(self.unexpected(c));
+        }
+        let start = self.position;
+        while self.peek().is_some_and(|c| c.is_alphanumeric() || c == '_') {
+            self.position += 1;
+        }
+        let name: String = self.chars[start..self.position].iter().collect();
+
+        if let Ok(function) = Elementary::from_str(&name) {
+            if !self.eat("(") {
+                return Err(format!(
+                    "The function '{}' must be called, as in '{}(x)'.",
+                    name, name
+                ));
+            }
+            let argument = self.expression()?;
+            if !self.eat(")") {
+                return Err(format!(
+                    "Missing ')' after the argument of '{}' at position {} of the formula.",
+                    name,
+                    self.position + 1
+                ));
+            }
+            return Ok(Expression::call(function, argument));
+        }
+        if self.eat("(") {
+            return Err(format!(
+                "Unknown function '{}'. Legal functions are {}.",
+                name,
+                prettify_list(Elementary::VARIANTS)
+            ));
+        }
+
+        Ok(match name.as_str() {
+            "x" => Expression::X,
+            "pi" => Expression::Number(std::f64::consts::PI),
+            _ => match self.names.iter().position(|n| *n == name) {
+                Some(i) => Expression::Parameter(i),
+                None => {
+                    self.names.push(name);
+                    Expression::Parameter(self.names.len() - 1)
+                }
+            },
+        })
+    }
+
+    /// A decimal number, possibly with an exponent, like 1.5e-3.
+    fn number(&mut self) -> Result<Expression, String> {
+        let start = self.position;
+        let digits = |parser: &mut Parser| {
+            while parser.peek().is_some_and(|c| c.is_ascii_digit()) {
+                parser.position += 1;
+            }
+        };
+        digits(self);
+        if self.peek() == Some('.') {
+            self.position += 1;
+            digits(self);
+        }
+        // an exponent needs digits, otherwise the e is left for the caller to reject
+        if let Some('e' | 'E') = self.peek() {
+            let mut end = self.position + 1;
+            if let Some('+' | '-') = self.chars.get(end) {
+                end += 1;
+            }
+            if self.chars.get(end).is_some_and(|c| c.is_ascii_digit()) {
+                self.position = end;
+                digits(self);
+            }
+        }
+
+        let string: String = self.chars[start..self.position].iter().collect();
+        string
+            .parse()
+            .map(Expression::Number)
+            .map_err(|_| format!("Malformed number '{}' in the formula.", string))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let (expression, names) = parse("a*exp(-l*x) + c").unwrap();
+        assert_eq!(names, ["a", "l", "c"]);
+        let params = [2.0, 0.5, 1.0];
+        assert_eq!(
+            expression.evaluate(1.5, &params),
+            2.0 * (-0.5 * 1.5_f64).exp() + 1.0
+        );
+
+        // precedence and associativity
+        let value = |formula: &str| parse(formula).unwrap().0.evaluate(2.0, &[3.0]);
+        assert_eq!(value("1 + 2*3^2"), 19.0);
+        assert_eq!(value("-x^2"), -4.0);
+        assert_eq!(value("2^3^2"), 512.0);
+        assert_eq!(value("2**-1"), 0.5);
+        assert_eq!(value("12/a/2"), 2.0);
+        assert_eq!(value("8 - 2 - 1"), 5.0);
+        assert_eq!(value("1.5e1 + 2e-1"), 15.2);
+        assert_eq!(value("σ₁ * (x + 1)"), 9.0);
+        assert_eq!(value("sqrt(abs(-a - 1))"), 2.0);
+
+        for malformed in [
+            "a +",
+            "a * (x",
+            "exp x",
+            "foo(x)",
+            "a $ x",
+            "2 ** ** 3",
+            "2e",
+            "",
+        ] {
+            assert!(
+                parse(malformed).is_err(),
+                "'{}' should not parse",
+                malformed
+            );
+        }
+    }
+
+    #[test]
+    fn test_derivatives() {
+        let formula = "a*sin(b*x)^2/(1 + c*x) + atan(x*c) - tanh(a)*cosh(b) + sinh(x)*tan(b)\
+            + sqrt(x + c^2)*ln(a) + x^a + abs(b - 2)*cos(c) + exp(-(x - b)^2)";
+        let (expression, names) = parse(formula).unwrap();
+        let x = 0.7;
+        let params = [1.3, 0.4, 0.9];
+        let variables = [
+            Expression::X,
+            Expression::Parameter(0),
+            Expression::Parameter(1),
+            Expression::Parameter(2),
+        ];
+        assert_eq!(names.len(), 3);
+
+        // central differences of the expression and of each derivative
+        let h = 1e-6;
+        let step = |i: usize, h: f64| -> (f64, Vec<f64>) {
+            let mut params = params.to_vec();
+            if i == 0 {
+                (x + h, params)
+            } else {
+                params[i - 1] += h;
+                (x, params)
+            }
+        };
+        for (i, variable) in variables.iter().enumerate() {
+            let derivative = expression.derivative(variable);
+            let ((x_above, above), (x_below, below)) = (step(i, h), step(i, -h));
+            let numeric = (expression.evaluate(x_above, &above)
+                - expression.evaluate(x_below, &below))
+                / (2.0 * h);
+            let value = derivative.evaluate(x, &params);
+            assert!(
+                (value - numeric).abs() < 1e-7 * numeric.abs().max(1.0),
+                "derivative {} is {}, expected {}",
+                i,
+                value,
+                numeric
+            );
+
+            for (j, other) in variables.iter().enumerate() {
+                let second = derivative.derivative(other);
+                let ((x_above, above), (x_below, below)) = (step(j, h), step(j, -h));
+                let numeric = (derivative.evaluate(x_above, &above)
+                    - derivative.evaluate(x_below, &below))
+                    / (2.0 * h);
+                let value = second.evaluate(x, &params);
+                assert!(
+                    (value - numeric).abs() < 1e-6 * numeric.abs().max(1.0),
+                    "second derivative ({}, {}) is {}, expected {}",
+                    i,
+                    j,
+                    value,
+                    numeric
+                );
+            }
+        }
+
+        // terms without the variable vanish
+        let (line, _) = parse("a*x + b").unwrap();
+        assert_eq!(line.derivative(&variables[1]), Expression::X);
+        assert_eq!(line.derivative(&variables[2]), Expression::Number(1.0));
+        assert_eq!(
+            line.derivative(&variables[1]).derivative(&variables[1]),
+            Expression::Number(0.0)
+        );
+    }
+}
@@ -0,0 +1,35 @@
+use nalgebra::{Matrix2, Vector2};
+
+use super::Differentiated;
+use crate::autodiff::Real;
+
+/// The Arrhenius equation A e^(-E/T) for a rate at the temperature T, given as x. The
+/// activation energy E is divided by the gas constant, so it is in units of temperature.
+pub struct Arrhenius;
+
+impl Differentiated<2> for Arrhenius {
+    const PARAMETER_NAMES: [&'static str; 2] = ["A", "E"];
+    const NAME: &'static str = "arrhenius";
+
+    fn generic_f<T: Real>(x: T, params: &Vector2<T>) -> T {
+        let (a, e) = (params.x, params.y);
+        a * (-e / x).exp()
+    }
+
+    fn grad(x: f64, params: &Vector2<f64>) -> Vector2<f64> {
+        let (a, e) = (params.x, params.y);
+        let exp = (-e / x).exp();
+        Vector2::new(exp, -a * exp / x)
+    }
+
+    fn dfdx(x: f64, params: &Vector2<f64>) -> f64 {
+        let (a, e) = (params.x, params.y);
+        a * e * (-e / x).exp() / x.powi(2)
+    }
+
+    fn hess(x: f64, params: &Vector2<f64>) -> Matrix2<f64> {
+        let (a, e) = (params.x, params.y);
+        let exp = (-e / x).exp();
+        Matrix2::new(0.0, -exp / x, -exp / x, a * exp / x.powi(2))
+    }
+}
@@ -0,0 +1,371 @@
+use itertools::Itertools;
+use nalgebra::{DMatrix, DVector};
+use strum::VariantNames;
+
+use std::{
+    collections::HashMap,
+    fmt,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+    str::FromStr,
+};
+
+use super::{DynDifferentiated, Functions};
+use crate::parameters::ParameterSpecs;
+use crate::utils::prettify_list;
+use crate::{FitSettings, OptimizinateResult};
+
+/// The composite model `Functions::Composite` has when it is chosen by name.
+pub const DEFAULT_COMPOSITE: &str = "normal + normal + line";
+
+/// Is this meant to be a composite model rather than a formula? That is the case
+/// when it only combines names, and all of them are built-in functions. Any other
+/// name is a parameter of a formula, as in `line + c`.
+pub fn is_composite(source: &str) -> bool {
+    let names: Vec<&str> = source.split(['+', '*']).map(str::trim).collect();
+    names.len() > 1 && (names.iter()).all(|name| built_in(name).is_some())
+}
+
+fn built_in(name: &str) -> Option<Functions> {
+    Functions::from_str(&name.trim().to_lowercase())
+        .ok()
+        .filter(Functions::is_built_in)
+}
+
+/// The product of the values, leaving out the values at the skipped indices.
+fn product_without(values: &[f64], skipped: &[usize]) -> f64 {
+    (values.iter().enumerate())
+        .filter(|(i, _)| !skipped.contains(i))
+        .map(|(_, value)| value)
+        .product()
+}
+
+/// A built-in function that is part of a composite model.
+#[derive(Debug, Clone)]
+struct Component {
+    function: Functions,
+    /// The index of the first parameter of the component in the composite model.
+    offset: usize,
+}
+
+impl Component {
+    fn count(&self) -> usize {
+        self.function.parameter_count()
+    }
+
+    fn parameters<'a>(&self, params: &'a [f64]) -> &'a [f64] {
+        &params[self.offset..self.offset + self.count()]
+    }
+}
+
+/// A sum of products of built-in functions, like `normal + normal + line`, where
+/// every component has its own parameters. The derivatives are put together from
+/// the derivatives of the components.
+#[derive(Clone)]
+pub struct Composite {
+    pub source: String,
+    /// The parameter names of the components, prefixed by the component name, and
+    /// by its number if the model has more than one of that component.
+    pub parameter_names: Vec<String>,
+    /// The terms of the sum, which are products of components.
+    terms: Vec<Vec<Component>>,
+}
+
+impl Composite {
+    /// Parse a sum of products of built-in functions, like `normal + normal + line`
+    /// or `decay * sine`.
+    pub fn parse(source: &str) -> Result<Composite, String> {
+        let mut terms = Vec::new();
+        let mut offset = 0;
+        for term in source.split('+') {
+            let mut components = Vec::new();
+            for name in term.split('*') {
+                let Some(function) = built_in(name) else {
+                    let built_ins: Vec<&str> = (Functions::VARIANTS.iter())
+                        .filter(|name| built_in(name).is_some())
+                        .copied()
+                        .collect();
+                    return Err(format!(
+                        "Got malformed composite model '{}'. '{}' is not a built-in \
+                        function, legal components are {}.",
+                        source,
+                        name.trim(),
+                        prettify_list(&built_ins)
+                    ));
+                };
+                let count = function.parameter_count();
+                components.push(Component { function, offset });
+                offset += count;
+            }
+            terms.push(components);
+        }
+
+        let components: Vec<&Component> = terms.iter().flatten().collect();
+        let counts = components.iter().map(|c| &c.function).counts();
+        let mut numbers = HashMap::new();
+        let mut parameter_names = Vec::with_capacity(offset);
+        for component in &components {
+            let function = &component.function;
+            let prefix = if counts[function] > 1 {
+                let number = numbers.entry(function).or_insert(0);
+                *number += 1;
+                format!("{}{}", function.name(), number)
+            } else {
+                function.name().to_string()
+            };
+            for name in function.parameter_names() {
+                parameter_names.push(format!("{}.{}", prefix, name));
+            }
+        }
+
+        Ok(Composite {
+            source: source.to_string(),
+            parameter_names,
+            terms,
+        })
+    }
+
+    pub fn parameter_count(&self) -> usize {
+        self.parameter_names.len()
+    }
+
+    pub fn has_hessian(&self) -> bool {
+        self.components()
+            .all(|(_, function)| function.has_hessian())
+    }
+
+    /// A sum of functions that are linear in their parameters is linear too, but
+    /// products are not.
+    pub fn is_linear(&self) -> bool {
+        (self.terms.iter()).all(|term| matches!(term.as_slice(), [c] if c.function.is_linear()))
+    }
+
+    /// The components in order, along with whether they start a new term.
+    pub fn components(&self) -> impl Iterator<Item = (bool, Functions)> + '_ {
+        (self.terms.iter()).flat_map(|term| {
+            (term.iter().enumerate()).map(|(i, component)| (i == 0, component.function.clone()))
+        })
+    }
+
+    /// The values of the components of a term.
+    fn values(term: &[Component], x: f64, params: &[f64]) -> Vec<f64> {
+        (term.iter())
+            .map(|c| c.function.f(x, c.parameters(params)))
+            .collect()
+    }
+
+    pub fn f(&self, x: f64, params: &[f64]) -> f64 {
+        (self.terms.iter())
+            .map(|term| Self::values(term, x, params).iter().product::<f64>())
+            .sum()
+    }
+
+    pub fn dfdx(&self, x: f64, params: &[f64]) -> f64 {
+        let mut dfdx = 0.0;
+        for term in &self.terms {
+            let values = Self::values(term, x, params);
+            for (k, c) in term.iter().enumerate() {
+                dfdx += c.function.dfdx(x, c.parameters(params)) * product_without(&values, &[k]);
+            }
+        }
+        dfdx
+    }
+
+    pub fn grad(&self, x: f64, params: &[f64]) -> DVector<f64> {
+        let mut grad = DVector::zeros(self.parameter_count());
+        for term in &self.terms {
+            let values = Self::values(term, x, params);
+            for (k, c) in term.iter().enumerate() {
+                let component_grad = c.function.grad(x, c.parameters(params));
+                grad.rows_mut(c.offset, c.count())
+                    .copy_from(&(component_grad * product_without(&values, &[k])));
+            }
+        }
+        grad
+    }
+
+    /// The value, gradient and hessian at once, where each component is evaluated
+    /// once. The hessian is block diagonal for sums, while the components of a
+    /// product also share blocks.
+    pub fn evaluate_all(&self, x: f64, params: &[f64]) -> (f64, DVector<f64>, DMatrix<f64>) {
+        let count = self.parameter_count();
+        let (mut f, mut grad, mut hess) =
+            (0.0, DVector::zeros(count), DMatrix::zeros(count, count));
+        for term in &self.terms {
+            let evaluations: Vec<_> = (term.iter())
+                .map(|c| c.function.evaluate_all(x, c.parameters(params)))
+                .collect();
+            let values: Vec<f64> = evaluations.iter().map(|(value, _, _)| *value).collect();
+            f += values.iter().product::<f64>();
+
+            for (k, (c, (_, grad_k, hess_k))) in term.iter().zip(&evaluations).enumerate() {
+                let others = product_without(&values, &[k]);
+                grad.rows_mut(c.offset, c.count())
+                    .copy_from(&(grad_k * others));
+                hess.view_mut((c.offset, c.offset), (c.count(), c.count()))
+                    .copy_from(&(hess_k * others));
+
+                for (l, (d, (_, grad_l, _))) in term.iter().zip(&evaluations).enumerate() {
+                    if l != k {
+                        let block = grad_k * grad_l.transpose() * product_without(&values, &[k, l]);
+                        hess.view_mut((c.offset, d.offset), (c.count(), d.count()))
+                            .copy_from(&block);
+                    }
+                }
+            }
+        }
+        (f, grad, hess)
+    }
+
+    pub fn hess(&self, x: f64, params: &[f64]) -> DMatrix<f64> {
+        self.evaluate_all(x, params).2
+    }
+}
+
+impl Default for Composite {
+    fn default() -> Self {
+        Composite::parse(DEFAULT_COMPOSITE).expect("the default composite should parse")
+    }
+}
+
+/// Composite models are identified by their source, like formulas.
+impl PartialEq for Composite {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Composite {}
+
+impl Hash for Composite {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state);
+    }
+}
+
+impl fmt::Debug for Composite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.source)
+    }
+}
+
+impl DynDifferentiated for Composite {
+    fn name(&self) -> &str {
+        "composite"
+    }
+
+    fn parameter_count(&self) -> usize {
+        Composite::parameter_count(self)
+    }
+
+    fn has_hessian(&self) -> bool {
+        Composite::has_hessian(self)
+    }
+
+    fn is_linear(&self) -> bool {
+        Composite::is_linear(self)
+    }
+
+    fn f(&self, x: f64, params: &DVector<f64>) -> f64 {
+        Composite::f(self, x, params.as_slice())
+    }
+
+    fn grad(&self, x: f64, params: &DVector<f64>) -> DVector<f64> {
+        Composite::grad(self, x, params.as_slice())
+    }
+
+    fn dfdx(&self, x: f64, params: &DVector<f64>) -> f64 {
+        Composite::dfdx(self, x, params.as_slice())
+    }
+
+    fn hess(&self, x: f64, params: &DVector<f64>) -> DMatrix<f64> {
+        Composite::hess(self, x, params.as_slice())
+    }
+
+    fn evaluate_all(&self, x: f64, params: &DVector<f64>) -> (f64, DVector<f64>, DMatrix<f64>) {
+        Composite::evaluate_all(self, x, params.as_slice())
+    }
+}
+
+/// Fit a composite model, see `Functions::optimizinate`.
+pub fn optimizinate(
+    composite: &Composite,
+    datafile: &PathBuf,
+    initial_parameter_opt: Option<&[f64]>,
+    specs: &ParameterSpecs,
+    settings: &FitSettings,
+    plot_result: bool,
+) -> Result<OptimizinateResult, String> {
+    let initial_parameters = match initial_parameter_opt {
+        Some(parameters) => DVector::from_column_slice(parameters),
+        None => DVector::from_element(composite.parameter_count(), 1.0),
+    };
+    crate::optimizinate(
+        datafile,
+        composite.clone(),
+        initial_parameters,
+        specs,
+        settings,
+        plot_result,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::functions::formula::Formula;
+
+    #[test]
+    fn test_composite() {
+        let composite = Composite::parse("normal + normal + line").unwrap();
+        assert_eq!(
+            composite.parameter_names,
+            [
+                "normal1.a",
+                "normal1.μ",
+                "normal1.σ",
+                "normal2.a",
+                "normal2.μ",
+                "normal2.σ",
+                "line.a",
+                "line.b"
+            ]
+        );
+
+        // the derivatives agree with the symbolic derivatives of the same formula
+        let composite = Composite::parse("decay * line + normal").unwrap();
+        let formula =
+            Formula::parse("a*exp(-l*x) * (c*x + d) + e*exp(-0.5*((x - m)/s)^2)").unwrap();
+        let (x, params) = (0.7, [2.0, 0.5, 1.5, -0.5, 0.8, 0.3, 0.9]);
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-12 * a.abs().max(1.0);
+        assert!(close(composite.f(x, &params), formula.f(x, &params)));
+        assert!(close(composite.dfdx(x, &params), formula.dfdx(x, &params)));
+        let (f, grad, hess) = composite.evaluate_all(x, &params);
+        assert_eq!(f, composite.f(x, &params));
+        assert_eq!(grad, composite.grad(x, &params));
+        for (i, g) in formula.grad(x, &params).enumerate() {
+            assert!(close(grad[i], g), "gradient at index {}", i);
+        }
+        let expected = DMatrix::from_iterator(7, 7, formula.hess(x, &params));
+        for (i, j) in itertools::iproduct!(0..7, 0..7) {
+            assert!(
+                close(hess[(i, j)], expected[(i, j)]),
+                "hessian at ({}, {})",
+                i,
+                j
+            );
+        }
+
+        assert!(is_composite("Normal*sine"));
+        assert!(!is_composite("a*exp(-l*x)") && !is_composite("a + b"));
+
+        // a name that is not a built-in function makes it a formula
+        for source in ["line+c", "a*x + line"] {
+            assert!(!is_composite(source), "{}", source);
+            let function = Functions::descriptive_from_str(source).unwrap();
+            assert!(matches!(function, Functions::Formula(_)), "{}", source);
+        }
+        let err = Composite::parse("normal + gauss").err().unwrap();
+        assert!(err.contains("'gauss' is not a built-in function"));
+    }
+}
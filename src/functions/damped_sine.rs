@@ -0,0 +1,44 @@
+use nalgebra::{Matrix5, RowVector5, Vector5};
+
+use super::Differentiated;
+use crate::autodiff::Real;
+
+/// A sine whose amplitude a decays with the rate γ, a e^(-γt) sin(ωt + φ) + b.
+pub struct DampedSine;
+
+impl Differentiated<5> for DampedSine {
+    const PARAMETER_NAMES: [&'static str; 5] = ["ω", "φ", "a", "γ", "b"];
+    const NAME: &'static str = "damped_sine";
+
+    fn generic_f<T: Real>(t: T, params: &Vector5<T>) -> T {
+        let (omega, phi, a, gamma, b) = (params[0], params[1], params[2], params[3], params[4]);
+        a * (-gamma * t).exp() * (omega * t + phi).sin() + b
+    }
+
+    fn grad(t: f64, params: &Vector5<f64>) -> Vector5<f64> {
+        let (omega, phi, a, gamma) = (params[0], params[1], params[2], params[3]);
+        let (sin, cos) = (omega * t + phi).sin_cos();
+        let exp = (-gamma * t).exp();
+        Vector5::new(a * exp * t * cos, a * exp * cos, exp * sin, -a * exp * t * sin, 1.0)
+    }
+
+    fn dfdx(t: f64, params: &Vector5<f64>) -> f64 {
+        let (omega, phi, a, gamma) = (params[0], params[1], params[2], params[3]);
+        let (sin, cos) = (omega * t + phi).sin_cos();
+        a * (-gamma * t).exp() * (omega * cos - gamma * sin)
+    }
+
+    fn hess(t: f64, params: &Vector5<f64>) -> Matrix5<f64> {
+        let (omega, phi, a, gamma) = (params[0], params[1], params[2], params[3]);
+        let (sin, cos) = (omega * t + phi).sin_cos();
+        let exp = (-gamma * t).exp();
+        let (exp_sin, exp_cos) = (exp * sin, exp * cos);
+        Matrix5::from_rows(&[
+            RowVector5::new(-a * t * t * exp_sin, -a * t * exp_sin, t * exp_cos, -a * t * t * exp_cos, 0.0),
+            RowVector5::new(-a * t * exp_sin, -a * exp_sin, exp_cos, -a * t * exp_cos, 0.0),
+            RowVector5::new(t * exp_cos, exp_cos, 0.0, -t * exp_sin, 0.0),
+            RowVector5::new(-a * t * t * exp_cos, -a * t * exp_cos, -t * exp_sin, a * t * t * exp_sin, 0.0),
+            RowVector5::new(0.0, 0.0, 0.0, 0.0, 0.0),
+        ])
+    }
+}
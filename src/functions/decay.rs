@@ -1,6 +1,7 @@
 use nalgebra::{Matrix2, Vector2};
 
 use super::Differentiated;
+use crate::autodiff::Real;
 
 pub struct Decay;
 
@@ -8,7 +9,7 @@ impl Differentiated<2> for Decay {
     const PARAMETER_NAMES: [&'static str; 2] = ["a", "λ"];
     const NAME: &'static str = "decay";
 
-    fn f(x: f64, params: &Vector2<f64>) -> f64 {
+    fn generic_f<T: Real>(x: T, params: &Vector2<T>) -> T {
         let (a, l) = (params.x, params.y);
         a * (-l * x).exp()
     }
@@ -19,9 +20,23 @@ impl Differentiated<2> for Decay {
         Vector2::new(1.0, -a * x) * exp
     }
 
+    fn dfdx(x: f64, params: &Vector2<f64>) -> f64 {
+        -params.y * Self::f(x, params)
+    }
+
     fn hess(x: f64, params: &Vector2<f64>) -> Matrix2<f64> {
         let (a, l) = (params.x, params.y);
         let exp = (-l * x).exp();
         Matrix2::new(0.0, -x, -x, a * x * x) * exp
     }
+
+    fn evaluate_all(x: f64, params: &Vector2<f64>) -> (f64, Vector2<f64>, Matrix2<f64>) {
+        let (a, l) = (params.x, params.y);
+        let exp = (-l * x).exp();
+        (
+            a * exp,
+            Vector2::new(1.0, -a * x) * exp,
+            Matrix2::new(0.0, -x, -x, a * x * x) * exp,
+        )
+    }
 }
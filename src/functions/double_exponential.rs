@@ -0,0 +1,39 @@
+use nalgebra::{Matrix4, RowVector4, Vector4};
+
+use super::Differentiated;
+use crate::autodiff::Real;
+
+/// The sum of two exponential decays, a e^(-λ₁x) + b e^(-λ₂x).
+pub struct DoubleExponential;
+
+impl Differentiated<4> for DoubleExponential {
+    const PARAMETER_NAMES: [&'static str; 4] = ["a", "λ₁", "b", "λ₂"];
+    const NAME: &'static str = "double_exponential";
+
+    fn generic_f<T: Real>(x: T, params: &Vector4<T>) -> T {
+        let (a, l1, b, l2) = (params.x, params.y, params.z, params.w);
+        a * (-l1 * x).exp() + b * (-l2 * x).exp()
+    }
+
+    fn grad(x: f64, params: &Vector4<f64>) -> Vector4<f64> {
+        let (a, l1, b, l2) = (params.x, params.y, params.z, params.w);
+        let (exp1, exp2) = ((-l1 * x).exp(), (-l2 * x).exp());
+        Vector4::new(exp1, -a * x * exp1, exp2, -b * x * exp2)
+    }
+
+    fn dfdx(x: f64, params: &Vector4<f64>) -> f64 {
+        let (a, l1, b, l2) = (params.x, params.y, params.z, params.w);
+        -l1 * a * (-l1 * x).exp() - l2 * b * (-l2 * x).exp()
+    }
+
+    fn hess(x: f64, params: &Vector4<f64>) -> Matrix4<f64> {
+        let (a, l1, b, l2) = (params.x, params.y, params.z, params.w);
+        let (exp1, exp2) = ((-l1 * x).exp(), (-l2 * x).exp());
+        Matrix4::from_rows(&[
+            RowVector4::new(0.0, -x * exp1, 0.0, 0.0),
+            RowVector4::new(-x * exp1, a * x * x * exp1, 0.0, 0.0),
+            RowVector4::new(0.0, 0.0, 0.0, -x * exp2),
+            RowVector4::new(0.0, 0.0, -x * exp2, b * x * x * exp2),
+        ])
+    }
+}
@@ -0,0 +1,400 @@
+use nalgebra::{DMatrix, DVector};
+
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+};
+
+use super::DynDifferentiated;
+use crate::parameters::{ParameterSpecs, Range};
+use crate::utils::prettify_list;
+use crate::{FitSettings, OptimizinateResult};
+
+/// The expansion `Functions::Expansion` has when it is chosen by name.
+pub const DEFAULT_EXPANSION: &str = "poly:3";
+
+/// The kinds of bases an expansion can use.
+const KINDS: [&str; 4] = ["poly", "chebyshev", "legendre", "bspline"];
+
+/// Is this meant to be an expansion rather than a formula? That is the case when
+/// it starts with the kind of a basis followed by a colon, like `poly:3`.
+pub fn is_expansion(source: &str) -> bool {
+    source
+        .split_once(':')
+        .is_some_and(|(kind, _)| KINDS.contains(&kind.trim().to_lowercase().as_str()))
+}
+
+/// `numerator / denominator`, where terms with repeated knots are zero.
+fn ratio(numerator: f64, denominator: f64) -> f64 {
+    if denominator > 0.0 {
+        numerator / denominator
+    } else {
+        0.0
+    }
+}
+
+/// The B-splines of a degree at x, from the Cox-de Boor recursion. There is one
+/// B-spline less than there are knots, minus the degree.
+fn bsplines(knots: &[f64], degree: usize, x: f64) -> Vec<f64> {
+    let intervals = knots.len() - 1;
+    // the intervals are open to the right, except for the last interval that is not
+    // empty, which also has the last knot
+    let last = (0..intervals).rev().find(|i| knots[*i] < knots[i + 1]);
+    let mut values: Vec<f64> = (0..intervals)
+        .map(|i| {
+            let end = x < knots[i + 1] || (Some(i) == last && x == knots[i + 1]);
+            f64::from(knots[i] <= x && end)
+        })
+        .collect();
+    for k in 1..=degree {
+        for i in 0..intervals - k {
+            values[i] = ratio(x - knots[i], knots[i + k] - knots[i]) * values[i]
+                + ratio(knots[i + k + 1] - x, knots[i + k + 1] - knots[i + 1]) * values[i + 1];
+        }
+        values.pop();
+    }
+    values
+}
+
+/// The functions an expansion is a linear combination of.
+#[derive(Debug, Clone, PartialEq)]
+enum Basis {
+    /// The powers of x.
+    Polynomial,
+    /// Chebyshev polynomials of the first kind, where the domain is mapped to [-1, 1].
+    Chebyshev { domain: Range },
+    /// Legendre polynomials, where the domain is mapped to [-1, 1].
+    Legendre { domain: Range },
+    /// B-splines, which are zero outside the knots. The first and last knot are
+    /// repeated degree + 1 times, so that the splines can take any value there.
+    BSpline { degree: usize, knots: Vec<f64> },
+}
+
+impl Basis {
+    fn kind(&self) -> &'static str {
+        match self {
+            Self::Polynomial => "poly",
+            Self::Chebyshev { .. } => "chebyshev",
+            Self::Legendre { .. } => "legendre",
+            Self::BSpline { .. } => "bspline",
+        }
+    }
+}
+
+/// A linear combination of basis functions, like a polynomial of some degree. The
+/// expansion is linear in its parameters, which are the coefficients of the basis
+/// functions, so it is fitted exactly, see `linear::solve`.
+#[derive(Clone)]
+pub struct Expansion {
+    pub source: String,
+    /// The coefficient of each basis function, named `c0`, `c1`, ….
+    pub parameter_names: Vec<String>,
+    basis: Basis,
+}
+
+impl Expansion {
+    /// Parse an expansion, which is one of
+    /// - `poly:N`, a polynomial of degree N,
+    /// - `chebyshev:N` or `legendre:N`, a sum of Chebyshev or Legendre polynomials up
+    ///   to degree N, which can be followed by the domain that is mapped to [-1, 1],
+    ///   as in `chebyshev:5:0:10`,
+    /// - `bspline:N:knots`, B-splines of degree N with comma separated knots, as in
+    ///   `bspline:3:0,2,4,6`.
+    pub fn parse(source: &str) -> Result<Expansion, String> {
+        let malformed = |reason: &str| format!("Got malformed expansion '{}'. {}", source, reason);
+        let mut parts = source.splitn(3, ':').map(str::trim);
+        let kind = parts.next().unwrap_or_default().to_lowercase();
+        let degree = parts.next().unwrap_or_default();
+        let Ok(degree) = degree.parse::<usize>() else {
+            return Err(malformed(&format!(
+                "The degree must be a whole number, but got '{}'.",
+                degree
+            )));
+        };
+        let rest = parts.next();
+
+        let parse_domain = |rest: Option<&str>| match rest {
+            Some(domain) => Range::parse(domain).map_err(|e| malformed(&e)),
+            None => Ok(Range {
+                lower: -1.0,
+                upper: 1.0,
+            }),
+        };
+        let (basis, count) = match (kind.as_str(), rest) {
+            ("poly", None) => (Basis::Polynomial, degree + 1),
+            ("poly", Some(_)) => {
+                return Err(malformed("Polynomials only have a degree, as in 'poly:3'."));
+            }
+            ("chebyshev", _) => (
+                Basis::Chebyshev {
+                    domain: parse_domain(rest)?,
+                },
+                degree + 1,
+            ),
+            ("legendre", _) => (
+                Basis::Legendre {
+                    domain: parse_domain(rest)?,
+                },
+                degree + 1,
+            ),
+            ("bspline", Some(knots)) => {
+                let knots: Vec<f64> = knots
+                    .split(',')
+                    .map(|knot| knot.trim().parse::<f64>())
+                    .collect::<Result<_, _>>()
+                    .map_err(|e| malformed(&format!("The knots must be numbers: {}.", e)))?;
+                if knots.len() < 2
+                    || knots.iter().any(|knot| !knot.is_finite())
+                    || !knots.is_sorted_by(|a, b| a < b)
+                {
+                    return Err(malformed(
+                        "B-splines need at least two finite knots in increasing order.",
+                    ));
+                }
+                let count = knots.len() + degree - 1;
+                let (first, last) = (knots[0], knots[knots.len() - 1]);
+                let knots = std::iter::repeat_n(first, degree)
+                    .chain(knots)
+                    .chain(std::iter::repeat_n(last, degree))
+                    .collect();
+                (Basis::BSpline { degree, knots }, count)
+            }
+            ("bspline", None) => {
+                return Err(malformed(
+                    "B-splines need knots after the degree, as in 'bspline:3:0,2,4,6'.",
+                ));
+            }
+            _ => {
+                return Err(malformed(&format!(
+                    "Legal kinds of expansions are {}.",
+                    prettify_list(&KINDS)
+                )));
+            }
+        };
+
+        Ok(Expansion {
+            source: source.to_string(),
+            parameter_names: (0..count).map(|i| format!("c{}", i)).collect(),
+            basis,
+        })
+    }
+
+    pub fn parameter_count(&self) -> usize {
+        self.parameter_names.len()
+    }
+
+    /// The basis functions at x, along with their derivatives with respect to x.
+    fn basis(&self, x: f64) -> (DVector<f64>, DVector<f64>) {
+        let count = self.parameter_count();
+        let mut values = DVector::zeros(count);
+        let mut derivatives = DVector::zeros(count);
+        match &self.basis {
+            Basis::Polynomial => {
+                let mut power = 1.0;
+                for k in 0..count {
+                    values[k] = power;
+                    if k + 1 < count {
+                        derivatives[k + 1] = (k + 1) as f64 * power;
+                    }
+                    power *= x;
+                }
+            }
+            Basis::Chebyshev { domain } | Basis::Legendre { domain } => {
+                let chebyshev = matches!(self.basis, Basis::Chebyshev { .. });
+                let scale = 2.0 / domain.width();
+                let t = (x - domain.lower) * scale - 1.0;
+                // both are found by three-term recurrences, and derivatives with respect
+                // to t by differentiating the recurrences
+                values[0] = 1.0;
+                if count > 1 {
+                    values[1] = t;
+                    derivatives[1] = 1.0;
+                }
+                for k in 1..count.saturating_sub(1) {
+                    let (v, d) = (values[k], derivatives[k]);
+                    let (v_prev, d_prev) = (values[k - 1], derivatives[k - 1]);
+                    if chebyshev {
+                        values[k + 1] = 2.0 * t * v - v_prev;
+                        derivatives[k + 1] = 2.0 * (v + t * d) - d_prev;
+                    } else {
+                        let n = k as f64;
+                        values[k + 1] = ((2.0 * n + 1.0) * t * v - n * v_prev) / (n + 1.0);
+                        derivatives[k + 1] =
+                            ((2.0 * n + 1.0) * (v + t * d) - n * d_prev) / (n + 1.0);
+                    }
+                }
+                derivatives *= scale;
+            }
+            Basis::BSpline { degree, knots } => {
+                values = DVector::from_vec(bsplines(knots, *degree, x));
+                if *degree > 0 {
+                    let lower = bsplines(knots, degree - 1, x);
+                    let n = *degree as f64;
+                    for i in 0..count {
+                        derivatives[i] = n
+                            * (ratio(lower[i], knots[i + degree] - knots[i])
+                                - ratio(lower[i + 1], knots[i + degree + 1] - knots[i + 1]));
+                    }
+                }
+            }
+        }
+        (values, derivatives)
+    }
+
+    pub fn f(&self, x: f64, params: &[f64]) -> f64 {
+        self.basis(x)
+            .0
+            .as_slice()
+            .iter()
+            .zip(params)
+            .map(|(b, p)| b * p)
+            .sum()
+    }
+
+    pub fn dfdx(&self, x: f64, params: &[f64]) -> f64 {
+        self.basis(x)
+            .1
+            .as_slice()
+            .iter()
+            .zip(params)
+            .map(|(b, p)| b * p)
+            .sum()
+    }
+
+    /// The gradient is the basis, and doesn't depend on the parameters.
+    pub fn grad(&self, x: f64) -> DVector<f64> {
+        self.basis(x).0
+    }
+
+    pub fn hess(&self) -> DMatrix<f64> {
+        DMatrix::zeros(self.parameter_count(), self.parameter_count())
+    }
+}
+
+impl Default for Expansion {
+    fn default() -> Self {
+        Expansion::parse(DEFAULT_EXPANSION).expect("the default expansion should parse")
+    }
+}
+
+/// Expansions are identified by their source, like formulas.
+impl PartialEq for Expansion {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Expansion {}
+
+impl Hash for Expansion {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state);
+    }
+}
+
+impl fmt::Debug for Expansion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.source)
+    }
+}
+
+impl DynDifferentiated for Expansion {
+    fn name(&self) -> &str {
+        self.basis.kind()
+    }
+
+    fn parameter_count(&self) -> usize {
+        Expansion::parameter_count(self)
+    }
+
+    fn is_linear(&self) -> bool {
+        true
+    }
+
+    fn f(&self, x: f64, params: &DVector<f64>) -> f64 {
+        Expansion::f(self, x, params.as_slice())
+    }
+
+    fn grad(&self, x: f64, _params: &DVector<f64>) -> DVector<f64> {
+        Expansion::grad(self, x)
+    }
+
+    fn dfdx(&self, x: f64, params: &DVector<f64>) -> f64 {
+        Expansion::dfdx(self, x, params.as_slice())
+    }
+
+    fn hess(&self, _x: f64, _params: &DVector<f64>) -> DMatrix<f64> {
+        Expansion::hess(self)
+    }
+}
+
+/// Fit an expansion, see `Functions::optimizinate`.
+pub fn optimizinate(
+    expansion: &Expansion,
+    datafile: &PathBuf,
+    initial_parameter_opt: Option<&[f64]>,
+    specs: &ParameterSpecs,
+    settings: &FitSettings,
+    plot_result: bool,
+) -> Result<OptimizinateResult, String> {
+    let initial_parameters = match initial_parameter_opt {
+        Some(parameters) => DVector::from_column_slice(parameters),
+        None => DVector::from_element(expansion.parameter_count(), 1.0),
+    };
+    crate::optimizinate(
+        datafile,
+        expansion.clone(),
+        initial_parameters,
+        specs,
+        settings,
+        plot_result,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_expansion() {
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-12 * a.abs().max(1.0);
+
+        let poly = Expansion::parse("poly:3").unwrap();
+        assert_eq!(poly.parameter_names, ["c0", "c1", "c2", "c3"]);
+        let params = [1.0, -2.0, 0.5, 3.0];
+        assert!(close(poly.f(2.0, &params), 1.0 - 4.0 + 2.0 + 24.0));
+        assert!(close(poly.dfdx(2.0, &params), -2.0 + 2.0 + 36.0));
+
+        // on [0, 4], x = 3 maps to t = 0.5
+        let chebyshev = Expansion::parse("chebyshev:5:0:4").unwrap();
+        let legendre = Expansion::parse("Legendre:2:0:4").unwrap();
+        let t: f64 = 0.5;
+        for (k, value) in chebyshev.grad(3.0).iter().enumerate() {
+            assert!(close(*value, (k as f64 * t.acos()).cos()), "T{}", k);
+        }
+        assert!(close(legendre.grad(3.0)[2], 0.5 * (3.0 * t * t - 1.0)));
+
+        // B-splines add up to one everywhere within the knots
+        let bspline = Expansion::parse("bspline:3:0, 1, 2.5, 4").unwrap();
+        assert_eq!(bspline.parameter_count(), 6);
+        for x in [0.0, 0.3, 1.0, 2.0, 3.9, 4.0] {
+            assert!(close(bspline.grad(x).sum(), 1.0), "at x = {}", x);
+        }
+        assert_eq!(bspline.grad(4.5).sum(), 0.0);
+        // the derivative of a constant spline is zero
+        assert!(bspline.dfdx(1.7, &[2.0; 6]).abs() < 1e-12);
+
+        assert!(is_expansion("Poly: 2") && !is_expansion("a*x + b"));
+        for (source, reason) in [
+            ("poly:-1", "The degree must be a whole number"),
+            ("poly:2:0:1", "Polynomials only have a degree"),
+            ("chebyshev:2:1:0", "invalid range"),
+            ("bspline:2", "B-splines need knots"),
+            ("bspline:2:0,2,1", "increasing order"),
+        ] {
+            let err = Expansion::parse(source).err().unwrap();
+            assert!(err.contains(reason), "{}", err);
+        }
+    }
+}
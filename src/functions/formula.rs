@@ -0,0 +1,192 @@
+use nalgebra::{DMatrix, DVector};
+
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    path::PathBuf,
+};
+
+use super::DynDifferentiated;
+use crate::expression::{self, Expression};
+use crate::parameters::ParameterSpecs;
+use crate::{FitSettings, OptimizinateResult};
+
+/// The formula `Functions::Formula` has when it is chosen by name.
+pub const DEFAULT_FORMULA: &str = "a*exp(-l*x) + c";
+
+/// A function given as a formula at runtime, along with its derivatives, which are
+/// found by symbolic differentiation.
+#[derive(Clone)]
+pub struct Formula {
+    pub source: String,
+    pub parameter_names: Vec<String>,
+    value: Expression,
+    dfdx: Expression,
+    gradient: Vec<Expression>,
+    /// The lower triangle of the hessian, row by row.
+    hessian: Vec<Vec<Expression>>,
+}
+
+impl Formula {
+    /// Parse a formula of x, like `a*exp(-l*x) + c`, where every other name is a
+    /// parameter.
+    pub fn parse(source: &str) -> Result<Formula, String> {
+        let (value, names) = expression::parse(source)
+            .map_err(|e| format!("Got malformed formula '{}'. {}", source, e))?;
+        if names.is_empty() {
+            return Err(format!(
+                "A formula must have parameters, but '{}' only depends on x.",
+                source
+            ));
+        }
+
+        let parameters: Vec<Expression> = (0..names.len()).map(Expression::Parameter).collect();
+        let gradient: Vec<Expression> = parameters.iter().map(|p| value.derivative(p)).collect();
+        let hessian = (gradient.iter().enumerate())
+            .map(|(i, g)| parameters[..=i].iter().map(|p| g.derivative(p)).collect())
+            .collect();
+        Ok(Formula {
+            source: source.to_string(),
+            parameter_names: names,
+            dfdx: value.derivative(&Expression::X),
+            value,
+            gradient,
+            hessian,
+        })
+    }
+
+    pub fn parameter_count(&self) -> usize {
+        self.parameter_names.len()
+    }
+
+    pub fn f(&self, x: f64, params: &[f64]) -> f64 {
+        self.value.evaluate(x, params)
+    }
+
+    pub fn dfdx(&self, x: f64, params: &[f64]) -> f64 {
+        self.dfdx.evaluate(x, params)
+    }
+
+    /// The gradient, one parameter at a time.
+    pub fn grad<'a>(&'a self, x: f64, params: &'a [f64]) -> impl Iterator<Item = f64> + 'a {
+        self.gradient.iter().map(move |g| g.evaluate(x, params))
+    }
+
+    /// The hessian in column-major order.
+    pub fn hess<'a>(&'a self, x: f64, params: &'a [f64]) -> impl Iterator<Item = f64> + 'a {
+        let count = self.parameter_count();
+        (0..count * count).map(move |k| {
+            let (i, j) = (k % count, k / count);
+            self.hessian[i.max(j)][i.min(j)].evaluate(x, params)
+        })
+    }
+}
+
+impl Default for Formula {
+    fn default() -> Self {
+        Formula::parse(DEFAULT_FORMULA).expect("the default formula should parse")
+    }
+}
+
+/// Formulas are identified by their source, as the rest follows from it.
+impl PartialEq for Formula {
+    fn eq(&self, other: &Self) -> bool {
+        self.source == other.source
+    }
+}
+
+impl Eq for Formula {}
+
+impl Hash for Formula {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.source.hash(state);
+    }
+}
+
+impl fmt::Debug for Formula {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.source)
+    }
+}
+
+impl DynDifferentiated for Formula {
+    fn name(&self) -> &str {
+        "formula"
+    }
+
+    fn parameter_count(&self) -> usize {
+        Formula::parameter_count(self)
+    }
+
+    fn f(&self, x: f64, params: &DVector<f64>) -> f64 {
+        Formula::f(self, x, params.as_slice())
+    }
+
+    fn grad(&self, x: f64, params: &DVector<f64>) -> DVector<f64> {
+        DVector::from_iterator(params.len(), Formula::grad(self, x, params.as_slice()))
+    }
+
+    fn dfdx(&self, x: f64, params: &DVector<f64>) -> f64 {
+        Formula::dfdx(self, x, params.as_slice())
+    }
+
+    fn hess(&self, x: f64, params: &DVector<f64>) -> DMatrix<f64> {
+        let count = params.len();
+        DMatrix::from_iterator(count, count, Formula::hess(self, x, params.as_slice()))
+    }
+}
+
+/// Fit a formula, see `Functions::optimizinate`.
+pub fn optimizinate(
+    formula: &Formula,
+    datafile: &PathBuf,
+    initial_parameter_opt: Option<&[f64]>,
+    specs: &ParameterSpecs,
+    settings: &FitSettings,
+    plot_result: bool,
+) -> Result<OptimizinateResult, String> {
+    let initial_parameters = match initial_parameter_opt {
+        Some(parameters) => DVector::from_column_slice(parameters),
+        None => DVector::from_element(formula.parameter_count(), 1.0),
+    };
+    crate::optimizinate(
+        datafile,
+        formula.clone(),
+        initial_parameters,
+        specs,
+        settings,
+        plot_result,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_formula() {
+        let formula = Formula::parse("a*exp(-l*x) + c").unwrap();
+        assert_eq!(formula.parameter_names, ["a", "l", "c"]);
+        let (x, params) = (0.5_f64, [2.0, 1.5, 1.0]);
+        let exp = (-1.5 * x).exp();
+        assert_eq!(formula.f(x, &params), 2.0 * exp + 1.0);
+        assert_eq!(formula.dfdx(x, &params), -1.5 * 2.0 * exp);
+        let grad: Vec<f64> = formula.grad(x, &params).collect();
+        assert_eq!(grad, [exp, -x * 2.0 * exp, 1.0]);
+
+        // the hessian is symmetric, and zero for the offset
+        let hess = DMatrix::from_iterator(3, 3, formula.hess(x, &params));
+        assert_eq!(hess, hess.transpose());
+        assert_eq!(hess[(0, 1)], -x * exp);
+        assert_eq!(hess.row(2).sum(), 0.0);
+
+        assert!(Formula::parse("2*x").is_err());
+        assert_eq!(
+            Formula::parse("a+b+c+d+e+f+g+h+i")
+                .unwrap()
+                .parameter_count(),
+            9
+        );
+        assert!(Formula::parse("a*").is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use nalgebra::{Matrix3, RowVector3, Vector3};
+
+use super::Differentiated;
+use crate::autodiff::Real;
+
+/// The Hill equation a xⁿ / (Kⁿ + xⁿ), which rises to a, reaching half of it at K,
+/// with the Hill coefficient n as its steepness. Only defined for positive x.
+pub struct Hill;
+
+impl Differentiated<3> for Hill {
+    const PARAMETER_NAMES: [&'static str; 3] = ["a", "K", "n"];
+    const NAME: &'static str = "hill";
+
+    fn generic_f<T: Real>(x: T, params: &Vector3<T>) -> T {
+        let (a, k, n) = (params.x, params.y, params.z);
+        let ratio = (x / k).pow(n);
+        a * ratio / (ratio + 1.0)
+    }
+
+    /// The Hill equation is a logistic function of n ln(x/K), which gives its derivatives.
+    fn grad(x: f64, params: &Vector3<f64>) -> Vector3<f64> {
+        let (a, k, n) = (params.x, params.y, params.z);
+        let log = (x / k).ln();
+        let sigmoid = 1.0 / (1.0 + (-n * log).exp());
+        let slope = sigmoid * (1.0 - sigmoid);
+        Vector3::new(sigmoid, -a * slope * n / k, a * slope * log)
+    }
+
+    fn dfdx(x: f64, params: &Vector3<f64>) -> f64 {
+        let (a, k, n) = (params.x, params.y, params.z);
+        let sigmoid = 1.0 / (1.0 + (-n * (x / k).ln()).exp());
+        a * sigmoid * (1.0 - sigmoid) * n / x
+    }
+
+    fn hess(x: f64, params: &Vector3<f64>) -> Matrix3<f64> {
+        let (a, k, n) = (params.x, params.y, params.z);
+        let log = (x / k).ln();
+        let sigmoid = 1.0 / (1.0 + (-n * log).exp());
+        let slope = sigmoid * (1.0 - sigmoid);
+        let curvature = slope * (1.0 - 2.0 * sigmoid);
+
+        let h12 = -slope * n / k;
+        let h13 = slope * log;
+        let h22 = a * n * (n * curvature + slope) / k.powi(2);
+        let h23 = -a * (curvature * n * log + slope) / k;
+        let h33 = a * curvature * log.powi(2);
+
+        Matrix3::from_rows(&[
+            RowVector3::new(0.0, h12, h13),
+            RowVector3::new(h12, h22, h23),
+            RowVector3::new(h13, h23, h33),
+        ])
+    }
+}
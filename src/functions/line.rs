@@ -1,14 +1,16 @@
 use nalgebra::{Matrix2, Vector2};
 
 use super::Differentiated;
+use crate::autodiff::Real;
 
 pub struct Line;
 
 impl Differentiated<2> for Line {
     const PARAMETER_NAMES: [&'static str; 2] = ["a", "b"];
     const NAME: &'static str = "line";
+    const IS_LINEAR: bool = true;
 
-    fn f(x: f64, params: &Vector2<f64>) -> f64 {
+    fn generic_f<T: Real>(x: T, params: &Vector2<T>) -> T {
         let (a, b) = (params.x, params.y);
         a * x + b
     }
@@ -17,6 +19,10 @@ impl Differentiated<2> for Line {
         Vector2::new(x, 1.0)
     }
 
+    fn dfdx(_x: f64, params: &Vector2<f64>) -> f64 {
+        params.x
+    }
+
     fn hess(_x: f64, _params: &Vector2<f64>) -> Matrix2<f64> {
         Matrix2::new(0.0, 0.0, 0.0, 0.0)
     }
@@ -0,0 +1,50 @@
+use nalgebra::{Matrix4, RowVector4, Vector4};
+
+use super::Differentiated;
+use crate::autodiff::Real;
+
+/// A sigmoid that rises by a around x₀ with steepness k, on top of b.
+pub struct Logistic;
+
+impl Differentiated<4> for Logistic {
+    const PARAMETER_NAMES: [&'static str; 4] = ["a", "k", "x₀", "b"];
+    const NAME: &'static str = "logistic";
+
+    fn generic_f<T: Real>(x: T, params: &Vector4<T>) -> T {
+        let (a, k, x0, b) = (params.x, params.y, params.z, params.w);
+        a / ((-k * (x - x0)).exp() + 1.0) + b
+    }
+
+    fn grad(x: f64, params: &Vector4<f64>) -> Vector4<f64> {
+        let (a, k, x0, _b) = (params.x, params.y, params.z, params.w);
+        let sigmoid = 1.0 / (1.0 + (-k * (x - x0)).exp());
+        let slope = sigmoid * (1.0 - sigmoid);
+        Vector4::new(sigmoid, a * slope * (x - x0), -a * slope * k, 1.0)
+    }
+
+    /// The function depends on x - x₀, so the derivative is minus the one with respect to x₀.
+    fn dfdx(x: f64, params: &Vector4<f64>) -> f64 {
+        -Self::grad(x, params).z
+    }
+
+    fn hess(x: f64, params: &Vector4<f64>) -> Matrix4<f64> {
+        let (a, k, x0, _b) = (params.x, params.y, params.z, params.w);
+        let shift = x - x0;
+        let sigmoid = 1.0 / (1.0 + (-k * shift).exp());
+        let slope = sigmoid * (1.0 - sigmoid);
+        let curvature = slope * (1.0 - 2.0 * sigmoid);
+
+        let h12 = slope * shift;
+        let h13 = -slope * k;
+        let h22 = a * curvature * shift.powi(2);
+        let h23 = -a * (curvature * k * shift + slope);
+        let h33 = a * curvature * k.powi(2);
+
+        Matrix4::from_rows(&[
+            RowVector4::new(0.0, h12, h13, 0.0),
+            RowVector4::new(h12, h22, h23, 0.0),
+            RowVector4::new(h13, h23, h33, 0.0),
+            RowVector4::new(0.0, 0.0, 0.0, 0.0),
+        ])
+    }
+}
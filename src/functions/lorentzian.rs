@@ -0,0 +1,51 @@
+use nalgebra::{Matrix3, RowVector3, Vector3};
+
+use super::Differentiated;
+use crate::autodiff::Real;
+
+/// A Cauchy-Lorentz peak of height a at μ, with half width at half maximum γ.
+pub struct Lorentzian;
+
+impl Differentiated<3> for Lorentzian {
+    const PARAMETER_NAMES: [&'static str; 3] = ["a", "μ", "γ"];
+    const NAME: &'static str = "lorentzian";
+
+    fn generic_f<T: Real>(x: T, params: &Vector3<T>) -> T {
+        let (a, x0, gamma) = (params.x, params.y, params.z);
+        a / (((x - x0) / gamma).powi(2) + 1.0)
+    }
+
+    fn grad(x: f64, params: &Vector3<f64>) -> Vector3<f64> {
+        let (a, x0, gamma) = (params.x, params.y, params.z);
+        let core = (x - x0) / gamma;
+        let inverse = 1.0 / (1.0 + core.powi(2));
+        let h = 2.0 * a * core * inverse.powi(2) / gamma;
+        Vector3::new(inverse, h, h * core)
+    }
+
+    /// The function depends on x - μ, so the derivative is minus the one with respect to μ.
+    fn dfdx(x: f64, params: &Vector3<f64>) -> f64 {
+        -Self::grad(x, params).y
+    }
+
+    fn hess(x: f64, params: &Vector3<f64>) -> Matrix3<f64> {
+        let (a, x0, gamma) = (params.x, params.y, params.z);
+        let core = (x - x0) / gamma;
+        let core2 = core.powi(2);
+        let inverse = 1.0 / (1.0 + core2);
+
+        let h12 = 2.0 * core * inverse.powi(2) / gamma;
+        let h13 = h12 * core;
+
+        let scale = 2.0 * a * inverse.powi(3) / gamma.powi(2);
+        let h22 = scale * (3.0 * core2 - 1.0);
+        let h23 = scale * 2.0 * core * (core2 - 1.0);
+        let h33 = scale * core2 * (core2 - 3.0);
+
+        Matrix3::from_rows(&[
+            RowVector3::new(0.0, h12, h13),
+            RowVector3::new(h12, h22, h23),
+            RowVector3::new(h13, h23, h33),
+        ])
+    }
+}
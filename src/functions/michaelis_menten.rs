@@ -0,0 +1,36 @@
+use nalgebra::{Matrix2, Vector2};
+
+use super::Differentiated;
+use crate::autodiff::Real;
+
+/// The Michaelis-Menten rate V x / (K + x), which saturates at V and reaches half of
+/// it at K.
+pub struct MichaelisMenten;
+
+impl Differentiated<2> for MichaelisMenten {
+    const PARAMETER_NAMES: [&'static str; 2] = ["V", "K"];
+    const NAME: &'static str = "michaelis_menten";
+
+    fn generic_f<T: Real>(x: T, params: &Vector2<T>) -> T {
+        let (v, k) = (params.x, params.y);
+        v * x / (k + x)
+    }
+
+    fn grad(x: f64, params: &Vector2<f64>) -> Vector2<f64> {
+        let (v, k) = (params.x, params.y);
+        let inverse = 1.0 / (k + x);
+        Vector2::new(x * inverse, -v * x * inverse.powi(2))
+    }
+
+    fn dfdx(x: f64, params: &Vector2<f64>) -> f64 {
+        let (v, k) = (params.x, params.y);
+        v * k / (k + x).powi(2)
+    }
+
+    fn hess(x: f64, params: &Vector2<f64>) -> Matrix2<f64> {
+        let (v, k) = (params.x, params.y);
+        let inverse = 1.0 / (k + x);
+        let h12 = -x * inverse.powi(2);
+        Matrix2::new(0.0, h12, h12, 2.0 * v * x * inverse.powi(3))
+    }
+}
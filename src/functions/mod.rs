@@ -1,60 +1,341 @@
-#[cfg(test)]
-use nalgebra::{DMatrix, DVector};
-use nalgebra::{SMatrix, SVector};
+use nalgebra::allocator::Allocator;
+use nalgebra::{
+    Const, DMatrix, DVector, DefaultAllocator, Dim, Dyn, OMatrix, OVector, SMatrix, SVector, U1,
+};
 use strum::VariantNames;
 use strum_macros::{EnumIter, EnumString, VariantNames};
 
-use std::{path::PathBuf, str::FromStr};
+use std::{path::PathBuf, str::FromStr, sync::Arc};
 
+use crate::autodiff::{Dual, HyperDual, Real};
+use crate::parameters::ParameterSpecs;
 use crate::utils::prettify_list;
-use crate::{OptimizinateResult, optimizinate};
+use crate::{FitSettings, OptimizinateResult, optimizinate};
+use composite::{Composite, is_composite};
+use expansion::{Expansion, is_expansion};
+use formula::Formula;
+
+/// The step used for central differences with respect to x, relative to |x|.
+const X_STEP: f64 = 6e-6;
 
 pub trait Differentiated<const D: usize> {
     const PARAMETER_NAMES: [&'static str; D];
     const NAME: &'static str;
 
-    fn f(x: f64, params: &SVector<f64, D>) -> f64;
+    /// Functions that are only used with gradient-based minimizers can set this
+    /// to false, in which case they don't have to implement `hess`.
+    const HAS_HESSIAN: bool = true;
+
+    /// Functions that are linear in their parameters, like a line, can set this to
+    /// true. Their gradient is then the same for any parameters, and least squares
+    /// fits are solved exactly instead of by a minimizer.
+    const IS_LINEAR: bool = false;
+
+    /// The function for any number type, which gives `f` and exact derivatives from
+    /// automatic differentiation, so functions only need to write the function once.
+    /// They can still implement the derivatives by hand if that is faster, which the
+    /// tests then check against automatic differentiation.
+    fn generic_f<T: Real>(x: T, params: &SVector<T, D>) -> T;
+
+    fn f(x: f64, params: &SVector<f64, D>) -> f64 {
+        Self::generic_f(x, params)
+    }
+
+    fn grad(x: f64, params: &SVector<f64, D>) -> SVector<f64, D> {
+        Self::generic_f(Dual::constant(x), &Dual::variables(params)).gradient
+    }
+
+    /// The derivative with respect to x, which is needed when x has uncertainties.
+    /// Defaults to automatic differentiation, but functions can often get it from `grad`.
+    fn dfdx(x: f64, params: &SVector<f64, D>) -> f64 {
+        let params_dual = params.map(Dual::<1>::constant);
+        Self::generic_f(Dual::variable(x, 0), &params_dual)
+            .gradient
+            .x
+    }
+
+    fn hess(x: f64, params: &SVector<f64, D>) -> SMatrix<f64, D, D> {
+        Self::generic_f(HyperDual::constant(x), &HyperDual::variables(params)).hessian
+    }
+
+    /// The value, gradient and hessian at once, where the hessian is zero if the
+    /// function has none. Functions can override this to share work between them,
+    /// like exponentials that appear in all three.
+    fn evaluate_all(
+        x: f64,
+        params: &SVector<f64, D>,
+    ) -> (f64, SVector<f64, D>, SMatrix<f64, D, D>) {
+        let hess = if Self::HAS_HESSIAN {
+            Self::hess(x, params)
+        } else {
+            SMatrix::zeros()
+        };
+        (Self::f(x, params), Self::grad(x, params), hess)
+    }
+}
+
+/// The counterpart of `Differentiated` for functions whose number of parameters is
+/// only known at runtime, like formulas. Their parameters are `DVector`s on the heap,
+/// so functions with a fixed number of parameters should implement `Differentiated`.
+pub trait DynDifferentiated {
+    fn name(&self) -> &str;
+    fn parameter_count(&self) -> usize;
+
+    /// Functions that are only used with gradient-based minimizers can return false,
+    /// in which case they don't have to implement `hess`.
+    fn has_hessian(&self) -> bool {
+        true
+    }
+
+    /// Functions that are linear in their parameters can return true, see
+    /// `Differentiated::IS_LINEAR`.
+    fn is_linear(&self) -> bool {
+        false
+    }
+
+    fn f(&self, x: f64, params: &DVector<f64>) -> f64;
+    fn grad(&self, x: f64, params: &DVector<f64>) -> DVector<f64>;
+
+    /// The derivative with respect to x, which is needed when x has uncertainties.
+    /// Defaults to a central difference.
+    fn dfdx(&self, x: f64, params: &DVector<f64>) -> f64 {
+        let h = X_STEP * x.abs().max(1.0);
+        (self.f(x + h, params) - self.f(x - h, params)) / (2.0 * h)
+    }
+
+    fn hess(&self, _x: f64, _params: &DVector<f64>) -> DMatrix<f64> {
+        panic!("{} does not have a hessian!", self.name());
+    }
+
+    /// The value, gradient and hessian at once, where the hessian is zero if the
+    /// function has none.
+    fn evaluate_all(&self, x: f64, params: &DVector<f64>) -> (f64, DVector<f64>, DMatrix<f64>) {
+        let hess = if self.has_hessian() {
+            self.hess(x, params)
+        } else {
+            DMatrix::zeros(params.len(), params.len())
+        };
+        (self.f(x, params), self.grad(x, params), hess)
+    }
+}
 
-    fn grad(x: f64, params: &SVector<f64, D>) -> SVector<f64, D>;
+/// What nalgebra needs to allocate the parameters and hessians of functions with D
+/// parameters, which must also be shareable between threads. Both `Const<D>` and
+/// `Dyn` have this, and bounding `DefaultAllocator` by it saves spelling out every
+/// allocation that is needed.
+pub trait ParameterAllocator<D: Dim>:
+    Allocator<D, Buffer<f64>: Send + Sync>
+    + Allocator<D, D, Buffer<f64>: Send + Sync>
+    + Allocator<U1, D, Buffer<f64>: Send + Sync>
+{
+}
+
+impl<D: Dim> ParameterAllocator<D> for DefaultAllocator where
+    DefaultAllocator: Allocator<D, Buffer<f64>: Send + Sync>
+        + Allocator<D, D, Buffer<f64>: Send + Sync>
+        + Allocator<U1, D, Buffer<f64>: Send + Sync>
+{
+}
 
-    fn hess(x: f64, params: &SVector<f64, D>) -> SMatrix<f64, D, D>;
+/// A function as the error function and the statistics see it, with parameters of
+/// dimension D. Functions that implement `Differentiated` are models with `Const`
+/// dimensions, which keep their parameters on the stack, and functions that implement
+/// `DynDifferentiated` are models with `Dyn` dimensions.
+pub trait Model<D: Dim>: Sync
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    fn name(&self) -> &str;
+    /// The number of parameters.
+    fn dimension(&self) -> D;
+    fn has_hessian(&self) -> bool;
+    /// Is the function linear in its parameters? The gradient is then the same for
+    /// any parameters.
+    fn is_linear(&self) -> bool;
+    fn f(&self, x: f64, params: &OVector<f64, D>) -> f64;
+    fn grad(&self, x: f64, params: &OVector<f64, D>) -> OVector<f64, D>;
+    fn dfdx(&self, x: f64, params: &OVector<f64, D>) -> f64;
+    fn evaluate_all(
+        &self,
+        x: f64,
+        params: &OVector<f64, D>,
+    ) -> (f64, OVector<f64, D>, OMatrix<f64, D, D>);
 }
 
+impl<const D: usize, F: Differentiated<D> + Sync> Model<Const<D>> for F {
+    fn name(&self) -> &str {
+        F::NAME
+    }
+
+    fn dimension(&self) -> Const<D> {
+        Const
+    }
+
+    fn has_hessian(&self) -> bool {
+        F::HAS_HESSIAN
+    }
+
+    fn is_linear(&self) -> bool {
+        F::IS_LINEAR
+    }
+
+    fn f(&self, x: f64, params: &SVector<f64, D>) -> f64 {
+        <F as Differentiated<D>>::f(x, params)
+    }
+
+    fn grad(&self, x: f64, params: &SVector<f64, D>) -> SVector<f64, D> {
+        <F as Differentiated<D>>::grad(x, params)
+    }
+
+    fn dfdx(&self, x: f64, params: &SVector<f64, D>) -> f64 {
+        <F as Differentiated<D>>::dfdx(x, params)
+    }
+
+    fn evaluate_all(
+        &self,
+        x: f64,
+        params: &SVector<f64, D>,
+    ) -> (f64, SVector<f64, D>, SMatrix<f64, D, D>) {
+        <F as Differentiated<D>>::evaluate_all(x, params)
+    }
+}
+
+impl<F: DynDifferentiated + Sync> Model<Dyn> for F {
+    fn name(&self) -> &str {
+        DynDifferentiated::name(self)
+    }
+
+    fn dimension(&self) -> Dyn {
+        Dyn(self.parameter_count())
+    }
+
+    fn has_hessian(&self) -> bool {
+        DynDifferentiated::has_hessian(self)
+    }
+
+    fn is_linear(&self) -> bool {
+        DynDifferentiated::is_linear(self)
+    }
+
+    fn f(&self, x: f64, params: &DVector<f64>) -> f64 {
+        DynDifferentiated::f(self, x, params)
+    }
+
+    fn grad(&self, x: f64, params: &DVector<f64>) -> DVector<f64> {
+        DynDifferentiated::grad(self, x, params)
+    }
+
+    fn dfdx(&self, x: f64, params: &DVector<f64>) -> f64 {
+        DynDifferentiated::dfdx(self, x, params)
+    }
+
+    fn evaluate_all(&self, x: f64, params: &DVector<f64>) -> (f64, DVector<f64>, DMatrix<f64>) {
+        DynDifferentiated::evaluate_all(self, x, params)
+    }
+}
+
+pub mod composite;
+pub mod expansion;
+pub mod formula;
+
 macro_rules! create_function_enum {
     ($($file:ident::$typename:ident<$D:literal>),*,) => {
         $(pub mod $file);*;
 
-        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, EnumString, EnumIter, VariantNames)]
+        #[derive(Debug, Clone, PartialEq, Eq, Hash, EnumString, EnumIter, VariantNames)]
         #[strum(serialize_all = "snake_case")]
         pub enum Functions {
-            $($typename),*
+            $($typename),*,
+            /// A formula given at runtime, which is the default formula when the
+            /// function is chosen by name.
+            Formula(Arc<Formula>),
+            /// A sum of products of built-in functions given at runtime, which is
+            /// the default composite model when the function is chosen by name.
+            Composite(Arc<Composite>),
+            /// A basis expansion given at runtime, like a polynomial of some degree,
+            /// which is the default expansion when the function is chosen by name.
+            Expansion(Arc<Expansion>),
         }
 
         impl Functions {
             /// Tries to create a function from a function name, returns a string with
-            /// a descriptive error message if the function name is invalid.
+            /// a descriptive error message if the function name is invalid. Expansions
+            /// like `poly:3` become a `Functions::Expansion`, sums and
+            /// products of function names become a `Functions::Composite`,
+            /// and anything else that is not a name is parsed as a formula.
             pub fn descriptive_from_str(s: &str) -> Result<Functions, String> {
-                Self::from_str(&s.to_lowercase()).or_else(|_| Err(format!(
-                    "Got malformed function name '{}'. Legal \
-                    function names are {}.", s, prettify_list(Self::VARIANTS)
-                )))
+                if let Ok(function) = Self::from_str(&s.to_lowercase()) {
+                    return Ok(function);
+                }
+                if s.chars().all(|c| c.is_alphanumeric() || c == '_') {
+                    return Err(format!(
+                        "Got malformed function name '{}'. Legal function names are {}, \
+                        a sum or product of them, like '{}', an expansion, like '{}', or \
+                        a formula of x, like '{}'.",
+                        s,
+                        prettify_list(Self::VARIANTS),
+                        composite::DEFAULT_COMPOSITE,
+                        expansion::DEFAULT_EXPANSION,
+                        formula::DEFAULT_FORMULA
+                    ));
+                }
+                if is_expansion(s) {
+                    return Ok(Self::Expansion(Arc::new(Expansion::parse(s)?)));
+                }
+                if is_composite(s) {
+                    return Ok(Self::Composite(Arc::new(Composite::parse(s)?)));
+                }
+                Ok(Self::Formula(Arc::new(Formula::parse(s)?)))
+            }
+
+            /// Is this one of the built-in functions, rather than a function given at
+            /// runtime?
+            pub fn is_built_in(&self) -> bool {
+                !matches!(self, Self::Formula(_) | Self::Composite(_) | Self::Expansion(_))
             }
 
             pub fn parameter_count(&self) -> usize {
                 match self {
-                    $(Self::$typename => $D),*
+                    $(Self::$typename => $D),*,
+                    Self::Formula(formula) => formula.parameter_count(),
+                    Self::Composite(composite) => composite.parameter_count(),
+                    Self::Expansion(expansion) => expansion.parameter_count(),
                 }
             }
 
-            pub fn parameter_names(&self) -> Vec<&'static str> {
+            pub fn parameter_names(&self) -> Vec<&str> {
                 match self {
-                    $(Self::$typename => $file::$typename::PARAMETER_NAMES.to_vec()),*
+                    $(Self::$typename => $file::$typename::PARAMETER_NAMES.to_vec()),*,
+                    Self::Formula(formula) => formula.parameter_names.iter().map(String::as_str).collect(),
+                    Self::Composite(composite) => composite.parameter_names.iter().map(String::as_str).collect(),
+                    Self::Expansion(expansion) => expansion.parameter_names.iter().map(String::as_str).collect(),
+                }
+            }
+
+            pub fn has_hessian(&self) -> bool {
+                match self {
+                    $(Self::$typename => $file::$typename::HAS_HESSIAN),*,
+                    Self::Formula(_) => true,
+                    Self::Composite(composite) => composite.has_hessian(),
+                    Self::Expansion(_) => true,
+                }
+            }
+
+            pub fn is_linear(&self) -> bool {
+                match self {
+                    $(Self::$typename => $file::$typename::IS_LINEAR),*,
+                    Self::Formula(_) => false,
+                    Self::Composite(composite) => composite.is_linear(),
+                    Self::Expansion(_) => true,
                 }
             }
 
             pub fn name(&self) -> &'static str {
                 match self {
-                    $(Self::$typename => $file::$typename::NAME),*
+                    $(Self::$typename => $file::$typename::NAME),*,
+                    Self::Formula(_) => "formula",
+                    Self::Composite(_) => "composite",
+                    Self::Expansion(_) => "expansion",
                 }
             }
 
@@ -62,28 +343,100 @@ macro_rules! create_function_enum {
                 match self {
                     $(Self::$typename => {
                         let params = SVector::<f64, $D>::from_column_slice(params);
-                        $file::$typename::f(x, &params)
-                    }),*
+                        $file::$typename.f(x, &params)
+                    }),*,
+                    Self::Formula(formula) => formula.f(x, params),
+                    Self::Composite(composite) => composite.f(x, params),
+                    Self::Expansion(expansion) => expansion.f(x, params),
                 }
             }
 
-            #[cfg(test)]
-            fn grad(&self, x: f64, params: &[f64]) -> DVector<f64> {
+            pub fn dfdx(&self, x: f64, params: &[f64]) -> f64 {
+                match self {
+                    $(Self::$typename => {
+                        let params = SVector::<f64, $D>::from_column_slice(params);
+                        $file::$typename.dfdx(x, &params)
+                    }),*,
+                    Self::Formula(formula) => formula.dfdx(x, params),
+                    Self::Composite(composite) => composite.dfdx(x, params),
+                    Self::Expansion(expansion) => expansion.dfdx(x, params),
+                }
+            }
+
+            pub fn grad(&self, x: f64, params: &[f64]) -> DVector<f64> {
+                match self {
+                    $(Self::$typename => {
+                        let params = SVector::<f64, $D>::from_column_slice(params);
+                        DVector::from_column_slice($file::$typename.grad(x, &params).data.as_slice())
+                    }),*,
+                    Self::Formula(formula) => {
+                        let params = &params[..formula.parameter_count()];
+                        DVector::from_iterator(params.len(), formula.grad(x, params))
+                    }
+                    Self::Composite(composite) => composite.grad(x, params),
+                    Self::Expansion(expansion) => expansion.grad(x),
+                }
+            }
+
+            pub fn hess(&self, x: f64, params: &[f64]) -> DMatrix<f64> {
+                match self {
+                    $(Self::$typename => {
+                        let params = SVector::<f64, $D>::from_column_slice(params);
+                        DMatrix::from_row_slice($D, $D, <$file::$typename as Differentiated<$D>>::hess(x, &params).data.as_slice())
+                    }),*,
+                    Self::Formula(formula) => {
+                        let params = &params[..formula.parameter_count()];
+                        DMatrix::from_iterator(params.len(), params.len(), formula.hess(x, params))
+                    }
+                    Self::Composite(composite) => composite.hess(x, params),
+                    Self::Expansion(expansion) => expansion.hess(),
+                }
+            }
+
+            pub fn evaluate_all(&self, x: f64, params: &[f64]) -> (f64, DVector<f64>, DMatrix<f64>) {
                 match self {
                     $(Self::$typename => {
                         let params = SVector::<f64, $D>::from_column_slice(params);
-                        DVector::from_column_slice($file::$typename::grad(x, &params).data.as_slice())
-                    }),*
+                        let (f, grad, hess) = $file::$typename.evaluate_all(x, &params);
+                        (
+                            f,
+                            DVector::from_column_slice(grad.data.as_slice()),
+                            DMatrix::from_row_slice($D, $D, hess.data.as_slice()),
+                        )
+                    }),*,
+                    Self::Formula(_) => (self.f(x, params), self.grad(x, params), self.hess(x, params)),
+                    Self::Composite(composite) => composite.evaluate_all(x, params),
+                    Self::Expansion(_) => (self.f(x, params), self.grad(x, params), self.hess(x, params)),
                 }
             }
 
+            /// The value, dfdx, gradient and hessian from automatic differentiation,
+            /// if the function is one of the built-in functions.
             #[cfg(test)]
-            fn hess(&self, x: f64, params: &[f64]) -> DMatrix<f64> {
+            fn autodiff(
+                &self,
+                x: f64,
+                params: &[f64],
+            ) -> Option<(f64, f64, DVector<f64>, DMatrix<f64>)> {
                 match self {
                     $(Self::$typename => {
                         let params = SVector::<f64, $D>::from_column_slice(params);
-                        DMatrix::from_row_slice($D, $D, $file::$typename::hess(x, &params).data.as_slice())
-                    }),*
+                        let hyper_dual = $file::$typename::generic_f(
+                            HyperDual::constant(x),
+                            &HyperDual::variables(&params),
+                        );
+                        let dual = $file::$typename::generic_f(
+                            Dual::<1>::variable(x, 0),
+                            &params.map(Dual::<1>::constant),
+                        );
+                        Some((
+                            hyper_dual.value,
+                            dual.gradient.x,
+                            DVector::from_column_slice(hyper_dual.gradient.data.as_slice()),
+                            DMatrix::from_row_slice($D, $D, hyper_dual.hessian.data.as_slice()),
+                        ))
+                    }),*,
+                    Self::Formula(_) | Self::Composite(_) | Self::Expansion(_) => None,
                 }
             }
 
@@ -91,8 +444,10 @@ macro_rules! create_function_enum {
                 &self,
                 datafile: &PathBuf,
                 initial_parameter_opt: Option<&[f64]>,
+                specs: &ParameterSpecs,
+                settings: &FitSettings,
                 plot_result: bool,
-            ) -> OptimizinateResult {
+            ) -> Result<OptimizinateResult, String> {
                 match self {
                     $(Self::$typename => {
                         let initial_parameters = if let Some(parameters) = initial_parameter_opt {
@@ -100,10 +455,24 @@ macro_rules! create_function_enum {
                         } else {
                             SVector::<f64, $D>::from_element(1.0)
                         };
-                        optimizinate::<$D, $file::$typename>(
-                            datafile, initial_parameters, plot_result
+                        optimizinate(
+                            datafile,
+                            $file::$typename,
+                            initial_parameters,
+                            specs,
+                            settings,
+                            plot_result,
                         )
-                    }),*
+                    }),*,
+                    Self::Formula(formula) => formula::optimizinate(
+                        formula, datafile, initial_parameter_opt, specs, settings, plot_result
+                    ),
+                    Self::Composite(composite) => composite::optimizinate(
+                        composite, datafile, initial_parameter_opt, specs, settings, plot_result
+                    ),
+                    Self::Expansion(expansion) => expansion::optimizinate(
+                        expansion, datafile, initial_parameter_opt, specs, settings, plot_result
+                    ),
                 }
             }
         }
@@ -117,6 +486,16 @@ create_function_enum!(
     normal::Normal<3>,
     decay::Decay<2>,
     mort_func::MortFunc<4>,
+    lorentzian::Lorentzian<3>,
+    pseudo_voigt::PseudoVoigt<4>,
+    logistic::Logistic<4>,
+    hill::Hill<3>,
+    michaelis_menten::MichaelisMenten<2>,
+    power_law::PowerLaw<2>,
+    double_exponential::DoubleExponential<4>,
+    stretched_exponential::StretchedExponential<3>,
+    damped_sine::DampedSine<5>,
+    arrhenius::Arrhenius<2>,
 );
 
 #[cfg(test)]
@@ -138,6 +517,35 @@ mod tests {
         let err = Functions::descriptive_from_str("does_not_exist");
         assert!(err.is_err());
         assert!(err.err().unwrap().contains("Legal function names are"));
+
+        // the formula is part of the function, so the formula chosen by name is still
+        // the default one
+        let function = Functions::descriptive_from_str("b*x^2 + a").unwrap();
+        assert!(matches!(function, Functions::Formula(_)));
+        assert_eq!(function.parameter_names(), ["b", "a"]);
+        assert_ne!(function, Functions::from_str("formula").unwrap());
+        let function = Functions::from_str("formula").unwrap();
+        assert_eq!(function.parameter_names(), ["a", "l", "c"]);
+        let err = Functions::descriptive_from_str("a*exp(");
+        assert!(err.err().unwrap().contains("malformed formula"));
+
+        // the same goes for composite models
+        let function = Functions::descriptive_from_str("decay * line").unwrap();
+        assert!(matches!(function, Functions::Composite(_)));
+        assert_eq!(function.parameter_count(), 4);
+        let function = Functions::from_str("composite").unwrap();
+        assert_eq!(function.parameter_count(), 8);
+        let function = Functions::descriptive_from_str("normal + c").unwrap();
+        assert!(matches!(function, Functions::Formula(_)));
+
+        // and expansions
+        let function = Functions::descriptive_from_str("chebyshev:5").unwrap();
+        assert!(matches!(function, Functions::Expansion(_)));
+        assert_eq!(function.parameter_count(), 6);
+        let function = Functions::from_str("expansion").unwrap();
+        assert_eq!(function.parameter_count(), 4);
+        let err = Functions::descriptive_from_str("poly:x");
+        assert!(err.err().unwrap().contains("malformed expansion"));
     }
 
     #[test]
@@ -150,6 +558,101 @@ mod tests {
         test_derivative(Mode::Hessian);
     }
 
+    #[test]
+    fn test_x_derivatives() {
+        let mut rng = StdRng::seed_from_u64(80085);
+        let uniform = Uniform::new(0.5, 1.5).unwrap();
+
+        for name in Functions::VARIANTS {
+            let function = Functions::from_str(name).unwrap();
+            let x = uniform.sample(&mut rng);
+            let parameters: Vec<f64> = uniform.sample_iter(&mut rng).take(10).collect();
+            let parameters = &parameters[..function.parameter_count()];
+
+            let h = 1e-5;
+            let numeric =
+                (function.f(x + h, parameters) - function.f(x - h, parameters)) / (2.0 * h);
+            let derivative = function.dfdx(x, parameters);
+            assert!(
+                (derivative - numeric).abs() < 1e-7 * numeric.abs().max(1.0),
+                "dfdx of {} is {}, expected {}",
+                name,
+                derivative,
+                numeric
+            );
+        }
+    }
+
+    #[test]
+    fn test_evaluate_all() {
+        let mut rng = StdRng::seed_from_u64(80085);
+        let uniform = Uniform::new(0.5, 1.5).unwrap();
+
+        for name in Functions::VARIANTS {
+            let function = Functions::from_str(name).unwrap();
+            let x = uniform.sample(&mut rng);
+            let parameters: Vec<f64> = uniform.sample_iter(&mut rng).take(10).collect();
+            let parameters = &parameters[..function.parameter_count()];
+
+            let (f, grad, hess) = function.evaluate_all(x, parameters);
+            assert_eq!(f, function.f(x, parameters), "{}", name);
+            assert_eq!(grad, function.grad(x, parameters), "{}", name);
+            if function.has_hessian() {
+                assert_eq!(hess, function.hess(x, parameters), "{}", name);
+            }
+        }
+    }
+
+    #[test]
+    fn test_autodiff() {
+        let mut rng = StdRng::seed_from_u64(80085);
+        let uniform = Uniform::new(0.5, 1.5).unwrap();
+
+        for name in Functions::VARIANTS {
+            let function = Functions::from_str(name).unwrap();
+            let x = uniform.sample(&mut rng);
+            let parameters: Vec<f64> = uniform.sample_iter(&mut rng).take(10).collect();
+            let parameters = &parameters[..function.parameter_count()];
+            let Some((f, dfdx, grad, hess)) = function.autodiff(x, parameters) else {
+                continue;
+            };
+
+            // hand-written derivatives must agree with automatic differentiation
+            let close = |a: f64, b: f64| (a - b).abs() <= 1e-10 * a.abs().max(1.0);
+            assert!(close(function.f(x, parameters), f), "f of {}", name);
+            assert!(
+                close(function.dfdx(x, parameters), dfdx),
+                "dfdx of {}",
+                name
+            );
+            let hand_grad = function.grad(x, parameters);
+            for i in 0..grad.len() {
+                assert!(
+                    close(hand_grad[i], grad[i]),
+                    "Gradient of {} at index {} is {}, automatic differentiation gives {}",
+                    name,
+                    i,
+                    hand_grad[i],
+                    grad[i]
+                );
+            }
+            if function.has_hessian() {
+                let hand_hess = function.hess(x, parameters);
+                for (i, j) in itertools::iproduct!(0..grad.len(), 0..grad.len()) {
+                    assert!(
+                        close(hand_hess[(i, j)], hess[(i, j)]),
+                        "Hessian of {} at index ({}, {}) is {}, automatic differentiation gives {}",
+                        name,
+                        i,
+                        j,
+                        hand_hess[(i, j)],
+                        hess[(i, j)]
+                    );
+                }
+            }
+        }
+    }
+
     fn test_derivative(mode: Mode) {
         let mut rng = StdRng::seed_from_u64(80085);
         let uniform = Uniform::new(0.0, 1.0).unwrap();
@@ -159,6 +662,9 @@ mod tests {
             let x = uniform.sample(&mut rng);
             let parameters: Vec<f64> = uniform.sample_iter(&mut rng).take(10).collect();
             let function = Functions::from_str(name).unwrap();
+            if matches!(mode, Mode::Hessian) && !function.has_hessian() {
+                continue;
+            }
 
             let (derivative, size) = match mode {
                 Mode::Gradient => {
@@ -1,6 +1,7 @@
 use nalgebra::{Matrix3, RowVector3, Vector3};
 
 use super::Differentiated;
+use crate::autodiff::Real;
 
 pub struct Normal;
 
@@ -8,9 +9,9 @@ impl Differentiated<3> for Normal {
     const PARAMETER_NAMES: [&'static str; 3] = ["a", "μ", "σ"];
     const NAME: &'static str = "normal";
 
-    fn f(x: f64, params: &Vector3<f64>) -> f64 {
+    fn generic_f<T: Real>(x: T, params: &Vector3<T>) -> T {
         let (a, x0, sigma) = (params.x, params.y, params.z);
-        a * (-0.5 * (((x - x0) / sigma).powi(2))).exp()
+        a * (((x - x0) / sigma).powi(2) * -0.5).exp()
     }
 
     fn grad(x: f64, params: &Vector3<f64>) -> Vector3<f64> {
@@ -20,6 +21,11 @@ impl Differentiated<3> for Normal {
         Vector3::new(exp, a * exp * core / sigma, a * exp * core.powi(2) / sigma)
     }
 
+    /// The function depends on x - μ, so the derivative is minus the one with respect to μ.
+    fn dfdx(x: f64, params: &Vector3<f64>) -> f64 {
+        -Self::grad(x, params).y
+    }
+
     fn hess(x: f64, params: &Vector3<f64>) -> Matrix3<f64> {
         let (a, x0, sigma) = (params.x, params.y, params.z);
         let core = (x - x0) / sigma;
@@ -41,4 +47,24 @@ impl Differentiated<3> for Normal {
             RowVector3::new(h13, h23, h33),
         ])
     }
+
+    fn evaluate_all(x: f64, params: &Vector3<f64>) -> (f64, Vector3<f64>, Matrix3<f64>) {
+        let (a, x0, sigma) = (params.x, params.y, params.z);
+        let core = (x - x0) / sigma;
+        let core2 = core.powi(2);
+        let exp = (-0.5 * core2).exp();
+        let grad = Vector3::new(exp, a * exp * core / sigma, a * exp * core2 / sigma);
+
+        let h12 = exp * core / sigma;
+        let h13 = exp * core2 / sigma;
+        let h22 = a * (core2 - 1.0) * exp / sigma.powi(2);
+        let h23 = a * core * (core2 - 2.0) * exp / sigma.powi(2);
+        let h33 = a * core2 * (core2 - 3.0) * exp / sigma.powi(2);
+        let hess = Matrix3::from_rows(&[
+            RowVector3::new(0.0, h12, h13),
+            RowVector3::new(h12, h22, h23),
+            RowVector3::new(h13, h23, h33),
+        ]);
+        (a * exp, grad, hess)
+    }
 }
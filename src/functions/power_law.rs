@@ -0,0 +1,34 @@
+use nalgebra::{Matrix2, Vector2};
+
+use super::Differentiated;
+use crate::autodiff::Real;
+
+/// The power law a xᵏ, which is only defined for positive x.
+pub struct PowerLaw;
+
+impl Differentiated<2> for PowerLaw {
+    const PARAMETER_NAMES: [&'static str; 2] = ["a", "k"];
+    const NAME: &'static str = "power_law";
+
+    fn generic_f<T: Real>(x: T, params: &Vector2<T>) -> T {
+        let (a, k) = (params.x, params.y);
+        a * x.pow(k)
+    }
+
+    fn grad(x: f64, params: &Vector2<f64>) -> Vector2<f64> {
+        let (a, k) = (params.x, params.y);
+        let power = x.powf(k);
+        Vector2::new(power, a * power * x.ln())
+    }
+
+    fn dfdx(x: f64, params: &Vector2<f64>) -> f64 {
+        let (a, k) = (params.x, params.y);
+        a * k * x.powf(k - 1.0)
+    }
+
+    fn hess(x: f64, params: &Vector2<f64>) -> Matrix2<f64> {
+        let (a, k) = (params.x, params.y);
+        let (power, log) = (x.powf(k), x.ln());
+        Matrix2::new(0.0, power * log, power * log, a * power * log.powi(2))
+    }
+}
@@ -0,0 +1,89 @@
+use nalgebra::{Matrix4, RowVector4, Vector4};
+
+use super::Differentiated;
+use crate::autodiff::Real;
+
+/// The pseudo-Voigt approximation of a Voigt peak, which mixes a Lorentzian and a
+/// Gaussian peak of height a at μ with the same half width at half maximum γ. The
+/// fraction of the Lorentzian is η.
+pub struct PseudoVoigt;
+
+/// The Lorentzian and Gaussian peaks of unit height at `core` half widths from the
+/// center, along with their first and second derivatives with respect to `core`.
+fn shapes(core: f64) -> ([f64; 3], [f64; 3]) {
+    let core2 = core.powi(2);
+    let inverse = 1.0 / (1.0 + core2);
+    let lorentzian = [
+        inverse,
+        -2.0 * core * inverse.powi(2),
+        (6.0 * core2 - 2.0) * inverse.powi(3),
+    ];
+
+    let ln2 = std::f64::consts::LN_2;
+    let gaussian = (-ln2 * core2).exp();
+    let gaussian = [
+        gaussian,
+        -2.0 * ln2 * core * gaussian,
+        (4.0 * ln2 * ln2 * core2 - 2.0 * ln2) * gaussian,
+    ];
+    (lorentzian, gaussian)
+}
+
+impl Differentiated<4> for PseudoVoigt {
+    const PARAMETER_NAMES: [&'static str; 4] = ["a", "μ", "γ", "η"];
+    const NAME: &'static str = "pseudo_voigt";
+
+    fn generic_f<T: Real>(x: T, params: &Vector4<T>) -> T {
+        let (a, x0, gamma, eta) = (params.x, params.y, params.z, params.w);
+        let core2 = ((x - x0) / gamma).powi(2);
+        let lorentzian = T::constant(1.0) / (core2 + 1.0);
+        let gaussian = (core2 * -std::f64::consts::LN_2).exp();
+        a * (eta * lorentzian + (-eta + 1.0) * gaussian)
+    }
+
+    fn grad(x: f64, params: &Vector4<f64>) -> Vector4<f64> {
+        let (a, x0, gamma, eta) = (params.x, params.y, params.z, params.w);
+        let core = (x - x0) / gamma;
+        let (lorentzian, gaussian) = shapes(core);
+        let value = eta * lorentzian[0] + (1.0 - eta) * gaussian[0];
+        let slope = eta * lorentzian[1] + (1.0 - eta) * gaussian[1];
+        Vector4::new(
+            value,
+            -a * slope / gamma,
+            -a * core * slope / gamma,
+            a * (lorentzian[0] - gaussian[0]),
+        )
+    }
+
+    /// The function depends on x - μ, so the derivative is minus the one with respect to μ.
+    fn dfdx(x: f64, params: &Vector4<f64>) -> f64 {
+        -Self::grad(x, params).y
+    }
+
+    fn hess(x: f64, params: &Vector4<f64>) -> Matrix4<f64> {
+        let (a, x0, gamma, eta) = (params.x, params.y, params.z, params.w);
+        let core = (x - x0) / gamma;
+        let (lorentzian, gaussian) = shapes(core);
+        let slope = eta * lorentzian[1] + (1.0 - eta) * gaussian[1];
+        let curvature = eta * lorentzian[2] + (1.0 - eta) * gaussian[2];
+        let slope_difference = lorentzian[1] - gaussian[1];
+
+        let h12 = -slope / gamma;
+        let h13 = -core * slope / gamma;
+        let h14 = lorentzian[0] - gaussian[0];
+
+        let h22 = a * curvature / gamma.powi(2);
+        let h23 = a * (core * curvature + slope) / gamma.powi(2);
+        let h24 = -a * slope_difference / gamma;
+
+        let h33 = a * core * (2.0 * slope + core * curvature) / gamma.powi(2);
+        let h34 = -a * core * slope_difference / gamma;
+
+        Matrix4::from_rows(&[
+            RowVector4::new(0.0, h12, h13, h14),
+            RowVector4::new(h12, h22, h23, h24),
+            RowVector4::new(h13, h23, h33, h34),
+            RowVector4::new(h14, h24, h34, 0.0),
+        ])
+    }
+}
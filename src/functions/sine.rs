@@ -1,6 +1,7 @@
 use nalgebra::{Matrix4, RowVector4, Vector4};
 
 use super::Differentiated;
+use crate::autodiff::Real;
 
 pub struct Sine;
 
@@ -8,7 +9,7 @@ impl Differentiated<4> for Sine {
     const PARAMETER_NAMES: [&'static str; 4] = ["ω", "φ", "a", "b"];
     const NAME: &'static str = "sine";
 
-    fn f(t: f64, params: &Vector4<f64>) -> f64 {
+    fn generic_f<T: Real>(t: T, params: &Vector4<T>) -> T {
         let (omega, phi, a, b) = (params.x, params.y, params.z, params.w);
         a * (omega * t + phi).sin() + b
     }
@@ -19,6 +20,11 @@ impl Differentiated<4> for Sine {
         Vector4::new(a * t * cos, a * cos, sin, 1.0)
     }
 
+    /// The phase enters as ωt + φ, so the derivative is ω times the one with respect to φ.
+    fn dfdx(t: f64, params: &Vector4<f64>) -> f64 {
+        params.x * Self::grad(t, params).y
+    }
+
     fn hess(t: f64, params: &Vector4<f64>) -> Matrix4<f64> {
         let (omega, phi, a, _b) = (params.x, params.y, params.z, params.w);
         let (sin, cos) = (omega * t + phi).sin_cos();
@@ -29,4 +35,16 @@ impl Differentiated<4> for Sine {
             RowVector4::new(0.0, 0.0, 0.0, 0.0),
         ])
     }
+
+    fn evaluate_all(t: f64, params: &Vector4<f64>) -> (f64, Vector4<f64>, Matrix4<f64>) {
+        let (omega, phi, a, b) = (params.x, params.y, params.z, params.w);
+        let (sin, cos) = (omega * t + phi).sin_cos();
+        let hess = Matrix4::from_rows(&[
+            RowVector4::new(-a * t * t * sin, -a * t * sin, t * cos, 0.0),
+            RowVector4::new(-a * t * sin, -a * sin, cos, 0.0),
+            RowVector4::new(t * cos, cos, 0.0, 0.0),
+            RowVector4::new(0.0, 0.0, 0.0, 0.0),
+        ]);
+        (a * sin + b, Vector4::new(a * t * cos, a * cos, sin, 1.0), hess)
+    }
 }
@@ -1,6 +1,7 @@
 use nalgebra::{Matrix4, RowVector4, Vector4};
 
 use super::Differentiated;
+use crate::autodiff::Real;
 
 pub struct Sqrt;
 
@@ -8,7 +9,7 @@ impl Differentiated<4> for Sqrt {
     const PARAMETER_NAMES: [&'static str; 4] = ["a", "b", "c", "d"];
     const NAME: &'static str = "sqrt";
 
-    fn f(x: f64, params: &Vector4<f64>) -> f64 {
+    fn generic_f<T: Real>(x: T, params: &Vector4<T>) -> T {
         let (a, b, c, d) = (params.x, params.y, params.z, params.w);
         a * (b * x + c).abs().sqrt() + d
     }
@@ -21,6 +22,11 @@ impl Differentiated<4> for Sqrt {
         Vector4::new(sqrt, a * x * hisqrt, a * hisqrt, 1.0)
     }
 
+    /// The function depends on bx + c, so the derivative is b times the one with respect to c.
+    fn dfdx(x: f64, params: &Vector4<f64>) -> f64 {
+        params.y * Self::grad(x, params).z
+    }
+
     fn hess(x: f64, params: &Vector4<f64>) -> Matrix4<f64> {
         let (a, b, c, _d) = (params.x, params.y, params.z, params.w);
         let sign = (b * x + c).signum();
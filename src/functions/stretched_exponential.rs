@@ -0,0 +1,51 @@
+use nalgebra::{Matrix3, RowVector3, Vector3};
+
+use super::Differentiated;
+use crate::autodiff::Real;
+
+/// The stretched exponential, or Kohlrausch function, a e^(-(x/τ)^β), which is only
+/// defined for positive x.
+pub struct StretchedExponential;
+
+impl Differentiated<3> for StretchedExponential {
+    const PARAMETER_NAMES: [&'static str; 3] = ["a", "τ", "β"];
+    const NAME: &'static str = "stretched_exponential";
+
+    fn generic_f<T: Real>(x: T, params: &Vector3<T>) -> T {
+        let (a, tau, beta) = (params.x, params.y, params.z);
+        a * (-(x / tau).pow(beta)).exp()
+    }
+
+    fn grad(x: f64, params: &Vector3<f64>) -> Vector3<f64> {
+        let (a, tau, beta) = (params.x, params.y, params.z);
+        let log = (x / tau).ln();
+        let power = (beta * log).exp();
+        let exp = (-power).exp();
+        Vector3::new(exp, a * exp * beta * power / tau, -a * exp * power * log)
+    }
+
+    fn dfdx(x: f64, params: &Vector3<f64>) -> f64 {
+        let (a, tau, beta) = (params.x, params.y, params.z);
+        let power = (beta * (x / tau).ln()).exp();
+        -a * (-power).exp() * beta * power / x
+    }
+
+    fn hess(x: f64, params: &Vector3<f64>) -> Matrix3<f64> {
+        let (a, tau, beta) = (params.x, params.y, params.z);
+        let log = (x / tau).ln();
+        let power = (beta * log).exp();
+        let exp = (-power).exp();
+
+        let h12 = exp * beta * power / tau;
+        let h13 = -exp * power * log;
+        let h22 = -a * exp * beta * power * (beta * (1.0 - power) + 1.0) / tau.powi(2);
+        let h23 = a * exp * power * (beta * log * (1.0 - power) + 1.0) / tau;
+        let h33 = -a * exp * power * log.powi(2) * (1.0 - power);
+
+        Matrix3::from_rows(&[
+            RowVector3::new(0.0, h12, h13),
+            RowVector3::new(h12, h22, h23),
+            RowVector3::new(h13, h23, h33),
+        ])
+    }
+}
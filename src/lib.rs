@@ -0,0 +1,220 @@
+pub mod autodiff;
+pub mod error_functions;
+pub mod expression;
+pub mod functions;
+pub mod linear;
+pub mod loss;
+pub mod minimizers;
+pub mod parameter_gui;
+pub mod parameters;
+pub mod plotting;
+pub mod statistics;
+pub mod utils;
+
+use log::{info, warn};
+use nalgebra::{DefaultAllocator, Dim, OVector};
+use std::{path::PathBuf, time::Instant};
+
+use error_functions::{ErrorFunction, HessianMode, Objective};
+use functions::{Model, ParameterAllocator};
+use loss::Loss;
+use minimizers::{GlobalSearch, MinimizerConfig, MinimizerReport, Minimizers, RefinementStage};
+use parameters::{ParameterSpecs, ParameterStatus};
+use plotting::plotter::plot_static;
+use statistics::{degrees_of_freedom, get_uncertainties};
+
+/// Everything that controls how a function is fitted, except for the initial parameters
+/// and what the user has specified about each parameter.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct FitSettings {
+    pub global: GlobalSearch,
+    pub minimizer: Minimizers,
+    pub refinement: RefinementStage,
+    pub config: MinimizerConfig,
+    pub objective: Objective,
+    pub hessian_mode: HessianMode,
+    pub loss: Loss,
+    /// Treat the uncertainties of the data as relative weights instead of the actual
+    /// standard deviations of the errors, so the parameter uncertainties are scaled
+    /// by the variance estimated from the residuals.
+    pub relative_sigma: bool,
+}
+
+impl FitSettings {
+    /// Check that the settings can be used together.
+    pub fn validate(&self) -> Result<(), String> {
+        if self.objective == Objective::Poisson && self.loss.is_robust() {
+            return Err(
+                "The Poisson deviance can't be used with a robust loss, as the loss only \
+                applies to least squares."
+                    .into(),
+            );
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct OptimizinateResult {
+    pub parameters: Vec<f64>,
+    pub uncertainties: Vec<f64>,
+    pub statuses: Vec<ParameterStatus>,
+    pub error: f64,
+    /// χ² divided by the degrees of freedom, for data with uncertainties fitted with
+    /// the squared loss.
+    pub reduced_chi_squared: Option<f64>,
+    /// The weight the loss gives each data point relative to the squared loss.
+    pub loss_weights: Vec<f64>,
+    pub report: MinimizerReport,
+}
+
+/// Fit the model to the data in `datafile`. Returns a descriptive error message if
+/// the data can't be loaded, or can't be fitted with the settings.
+fn optimizinate<D: Dim, M: Model<D>>(
+    datafile: &PathBuf,
+    model: M,
+    initial_parameters: OVector<f64, D>,
+    specs: &ParameterSpecs,
+    settings: &FitSettings,
+    plot_result: bool,
+) -> Result<OptimizinateResult, String>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let data = utils::load_txt(datafile)?;
+    let error_function = ErrorFunction::from_data(model, &data)
+        .with_bounds(&specs.bounds)
+        .with_fixed(&specs.fixed)
+        .with_ties(&specs.ties)
+        .with_loss(settings.loss)
+        .with_objective(settings.objective)
+        .with_hessian_mode(settings.hessian_mode)
+        .with_threads(settings.config.global.threads);
+    let mut initial_parameters = initial_parameters;
+    specs.apply_fixed(initial_parameters.as_mut_slice());
+    if settings.objective == Objective::Poisson {
+        if data.y.iter().any(|y| *y < 0.0) {
+            return Err("The Poisson deviance needs counts that are not negative.".into());
+        }
+        if data.sigma.is_some() {
+            warn!("The Poisson deviance ignores the uncertainties of the data.");
+        }
+        let nonpositive = error_function.nonpositive_points(&initial_parameters);
+        if !nonpositive.is_empty() {
+            return Err(format!(
+                "The function is not positive at x = {} for the initial parameters, but the \
+                Poisson deviance needs a positive expected count at every point. Choose \
+                initial parameters where the function is positive.",
+                utils::format_vector(&nonpositive, 5)
+            ));
+        }
+    }
+
+    let start = Instant::now();
+    let poisson = settings.objective == Objective::Poisson;
+    let absolute_sigma = poisson || (!settings.relative_sigma && data.sigma.is_some());
+    // least squares fits of functions that are linear in their parameters have an
+    // exact solution, unless it is outside the bounds
+    let linear = error_function.model().is_linear()
+        && !poisson
+        && !settings.loss.is_robust()
+        && data.x_sigma.is_none();
+    let exact = if linear {
+        linear::solve(error_function.model(), &data, specs, absolute_sigma)
+    } else {
+        None
+    };
+
+    let (optimal_internal, report, exact_covariance) = match exact {
+        Some((parameters, covariance)) => {
+            if settings.global != GlobalSearch::None {
+                info!("The fit is solved exactly, so the global search is skipped.");
+            }
+            let internal = error_function.internal(&parameters);
+            let report = MinimizerReport::solved(&error_function, &internal, &settings.config);
+            (internal, report, Some(covariance))
+        }
+        None => {
+            let ranges = specs.search_ranges(initial_parameters.as_slice());
+            let (internal, report) = settings.global.minimize(
+                &error_function,
+                &error_function.internal(&initial_parameters),
+                &ranges,
+                settings.minimizer,
+                settings.refinement,
+                &settings.config,
+            );
+            (internal, report, None)
+        }
+    };
+    if !report.termination.is_success() {
+        warn!("Minimizer did not converge: {}", report.termination);
+    }
+
+    let optimal_parameters = error_function.external(&optimal_internal);
+    let statuses = specs.statuses(optimal_parameters.as_slice());
+    let parameter_uncertainties = match exact_covariance {
+        Some(covariance) => covariance.diagonal().map(f64::sqrt),
+        None => {
+            // with uncertainties in x, a robust loss or the Poisson deviance, the
+            // uncertainties come from the corrected data
+            let corrected_data;
+            let uncertainty_data = if data.x_sigma.is_some() || settings.loss.is_robust() || poisson
+            {
+                corrected_data = error_function.corrected_data(&optimal_parameters);
+                &corrected_data
+            } else {
+                &data
+            };
+            get_uncertainties(
+                error_function.model(),
+                uncertainty_data,
+                &optimal_parameters,
+                &statuses,
+                absolute_sigma,
+            )
+        }
+    };
+    let error = error_function.f(&optimal_internal);
+    let loss_weights = error_function.loss_weights(&optimal_parameters);
+    let free = statuses.iter().filter(|s| s.is_free()).count();
+    let degrees_of_freedom = degrees_of_freedom(data.x.len(), free);
+    if degrees_of_freedom.is_none() {
+        warn!(
+            "The fit has {} free parameters and only {} data points, so there are no \
+            degrees of freedom to estimate the variance of the errors from.",
+            free,
+            data.x.len()
+        );
+    }
+    let reduced_chi_squared = (data.sigma.is_some() && !settings.loss.is_robust() && !poisson)
+        .then_some(degrees_of_freedom)
+        .flatten()
+        .map(|dof| error * data.x.len() as f64 / dof as f64);
+    info!("Fit took {}", utils::format_duration(start.elapsed()));
+
+    if plot_result {
+        let data_name = datafile.file_stem().unwrap().to_string_lossy();
+        let model = error_function.model();
+        let figure_name = format!("figures/{}-{}.png", data_name, model.name());
+
+        plot_static(
+            &data.x,
+            &data.y,
+            |x, params| model.f(x, params),
+            &optimal_parameters,
+            &parameter_uncertainties,
+            &figure_name,
+        );
+    }
+
+    Ok(OptimizinateResult {
+        parameters: optimal_parameters.as_slice().to_vec(),
+        uncertainties: parameter_uncertainties.as_slice().to_vec(),
+        statuses,
+        error,
+        reduced_chi_squared,
+        loss_weights,
+        report,
+    })
+}
@@ -0,0 +1,248 @@
+use itertools::izip;
+use nalgebra::{DMatrix, DVector, DefaultAllocator, Dim, OMatrix, OVector, U1};
+
+use crate::functions::{Model, ParameterAllocator};
+use crate::parameters::ParameterSpecs;
+use crate::statistics::degrees_of_freedom;
+use crate::utils::Dataset;
+
+/// Singular values, or diagonal values of R, below this value relative to the
+/// largest one are taken to be zero.
+const RANK_TOLERANCE: f64 = 1e-12;
+
+/// The parameters of a fit along with their covariance.
+type LinearFit<D> = (OVector<f64, D>, OMatrix<f64, D, D>);
+
+/// The derivatives of the parameters with respect to the free parameters, along with
+/// the parameters when every free parameter is zero. Fixed parameters are constant,
+/// and tied parameters follow the parameter they are tied to.
+fn free_parameter_map(count: usize, specs: &ParameterSpecs) -> (DMatrix<f64>, DVector<f64>) {
+    let free: Vec<usize> = (0..count)
+        .filter(|i| specs.fixed[*i].is_none() && specs.ties[*i].is_none())
+        .collect();
+    let mut jacobian = DMatrix::zeros(count, free.len());
+    let mut constant = DVector::zeros(count);
+    for (column, i) in free.iter().enumerate() {
+        jacobian[(*i, column)] = 1.0;
+    }
+    for i in 0..count {
+        if let Some(value) = specs.fixed[i] {
+            constant[i] = value;
+        }
+    }
+    for (i, tie) in specs.ties.iter().enumerate() {
+        let Some(tie) = tie else {
+            continue;
+        };
+        match free.iter().position(|j| *j == tie.to) {
+            Some(column) => {
+                jacobian[(i, column)] = tie.factor;
+                constant[i] = tie.offset;
+            }
+            None => constant[i] = tie.factor * constant[tie.to] + tie.offset,
+        }
+    }
+    (jacobian, constant)
+}
+
+/// Solves the least squares problem of minimizing |Aq - b|², where A has full
+/// column rank, by a QR decomposition. Returns the solution along with (AᵀA)⁻¹,
+/// which is R⁻¹R⁻ᵀ.
+fn solve_qr(design: DMatrix<f64>, target: &DVector<f64>) -> Option<(DVector<f64>, DMatrix<f64>)> {
+    let columns = design.ncols();
+    if design.nrows() < columns {
+        return None;
+    }
+    let qr = design.qr();
+    let r = qr.r();
+    let largest = r.diagonal().amax();
+    if r.diagonal()
+        .iter()
+        .any(|d| d.abs() <= RANK_TOLERANCE * largest)
+    {
+        return None;
+    }
+    let mut rotated = target.clone();
+    qr.q_tr_mul(&mut rotated);
+    let solution = r.solve_upper_triangular(&rotated.rows(0, columns).into_owned())?;
+    let r_inverse = r.solve_upper_triangular(&DMatrix::identity(columns, columns))?;
+    let inverse = &r_inverse * r_inverse.transpose();
+    Some((solution, inverse))
+}
+
+/// Solves the least squares problem of minimizing |Aq - b|² by an SVD, which also
+/// works when the columns of A are linearly dependent. Returns the solution with
+/// the smallest norm along with the pseudo inverse of AᵀA, which is VΣ⁻²Vᵀ.
+fn solve_svd(design: DMatrix<f64>, target: &DVector<f64>) -> (DVector<f64>, DMatrix<f64>) {
+    let svd = design.svd(true, true);
+    let tolerance = RANK_TOLERANCE * svd.singular_values.max();
+    let solution = svd
+        .solve(target, tolerance)
+        .expect("the SVD has both U and V");
+    let pseudo_inverse = svd
+        .pseudo_inverse(tolerance)
+        .expect("the SVD has both U and V");
+    let inverse = &pseudo_inverse * pseudo_inverse.transpose();
+    (solution, inverse)
+}
+
+/// Fit a function that is linear in its parameters by least squares, which has an
+/// exact solution. The gradient of such a function doesn't depend on the parameters,
+/// so the gradients at the data points, weighted by 1/σ, give the design matrix A.
+/// Fixed parameters are subtracted from the data, and tied parameters add their
+/// column to the column of the parameter they follow. The least squares problem is
+/// then solved by a QR decomposition, or by an SVD if the columns of A are linearly
+/// dependent.
+///
+/// Returns the parameters along with their covariance, which is (AᵀA)⁻¹ from the
+/// factorization, scaled by the variance like in `statistics`. Returns None if a
+/// parameter is outside its bounds, as the fit then needs a minimizer.
+pub fn solve<D: Dim>(
+    model: &impl Model<D>,
+    data: &Dataset,
+    specs: &ParameterSpecs,
+    absolute_sigma: bool,
+) -> Option<LinearFit<D>>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let dimension = model.dimension();
+    let count = dimension.value();
+    let (jacobian, constant) = free_parameter_map(count, specs);
+    let free = jacobian.ncols();
+
+    // the parameters don't matter, as the gradient is the same everywhere
+    let zero = OVector::zeros_generic(dimension, U1);
+    let n = data.x.len();
+    let mut design = DMatrix::zeros(n, free);
+    let mut target = DVector::zeros(n);
+    for (i, (x, y)) in izip!(&data.x, &data.y).enumerate() {
+        let basis = DVector::from_column_slice(model.grad(*x, &zero).as_slice());
+        let weight = data.sigma.as_ref().map_or(1.0, |sigma| 1.0 / sigma[i]);
+        design.set_row(i, &(jacobian.tr_mul(&basis) * weight).transpose());
+        target[i] = (y - basis.dot(&constant)) * weight;
+    }
+
+    let (solution, inverse) = if free == 0 {
+        (DVector::zeros(0), DMatrix::zeros(0, 0))
+    } else {
+        match solve_qr(design.clone(), &target) {
+            Some(solved) => solved,
+            None => solve_svd(design.clone(), &target),
+        }
+    };
+    let parameters = &jacobian * &solution + constant;
+    let in_bounds = izip!(&specs.bounds, parameters.iter())
+        .all(|(bounds, value)| bounds.lower <= *value && *value <= bounds.upper);
+    if !in_bounds {
+        return None;
+    }
+
+    let variance = if absolute_sigma && data.sigma.is_some() {
+        1.0
+    } else {
+        // without degrees of freedom, the uncertainties can't be estimated
+        degrees_of_freedom(n, free).map_or(f64::NAN, |dof| {
+            (&design * &solution - &target).norm_squared() / dof as f64
+        })
+    };
+    let covariance = &jacobian * inverse * jacobian.transpose() * variance;
+    Some((
+        OVector::from_column_slice_generic(dimension, U1, parameters.as_slice()),
+        OMatrix::from_column_slice_generic(dimension, dimension, covariance.as_slice()),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::functions::expansion::Expansion;
+    use crate::functions::line::Line;
+    use crate::parameters::Bounds;
+    use crate::statistics::get_uncertainties;
+    use nalgebra::Vector2;
+
+    #[test]
+    fn test_solve() {
+        let x: Vec<f64> = (0..20).map(|i| 0.25 * i as f64).collect();
+        // the errors alternate in sign, so the fit is not perfect
+        let y: Vec<f64> = (x.iter().enumerate())
+            .map(|(i, x)| 2.0 * x - 1.0 + if i % 2 == 0 { 0.1 } else { -0.1 })
+            .collect();
+        let sigma: Vec<f64> = x.iter().map(|x| 0.1 + 0.02 * x).collect();
+        let data = Dataset {
+            x,
+            y,
+            sigma: Some(sigma),
+            x_sigma: None,
+        };
+        let close = |a: f64, b: f64| (a - b).abs() <= 1e-10 * a.abs().max(1.0);
+
+        for absolute_sigma in [true, false] {
+            let specs = ParameterSpecs::new(2);
+            let (parameters, covariance) = solve(&Line, &data, &specs, absolute_sigma).unwrap();
+            // the weighted residuals are orthogonal to the basis at the minimum
+            let mut gradient = Vector2::zeros();
+            for (x, y, s) in izip!(&data.x, &data.y, data.sigma.as_ref().unwrap()) {
+                gradient += (y - Line.f(*x, &parameters)) / (s * s) * Vector2::new(*x, 1.0);
+            }
+            assert!(gradient.norm() < 1e-9, "gradient is {}", gradient);
+
+            // the covariance agrees with the one from the gradients
+            let statuses = specs.statuses(parameters.as_slice());
+            let uncertainties =
+                get_uncertainties(&Line, &data, &parameters, &statuses, absolute_sigma);
+            for i in 0..2 {
+                assert!(close(covariance[(i, i)].sqrt(), uncertainties[i]));
+            }
+        }
+
+        // a polynomial is recovered exactly from points on it
+        let x = vec![-1.0, 0.0, 1.0, 2.0, 3.0];
+        let y: Vec<f64> = x.iter().map(|x| 1.0 - x + 0.5 * x * x).collect();
+        let data = Dataset {
+            x,
+            y,
+            sigma: None,
+            x_sigma: None,
+        };
+        let poly = Expansion::parse("poly:2").unwrap();
+        let (parameters, _) = solve(&poly, &data, &ParameterSpecs::new(3), false).unwrap();
+        for (value, expected) in parameters.iter().zip([1.0, -1.0, 0.5]) {
+            assert!(close(*value, expected));
+        }
+
+        // a tie the solution already satisfies doesn't change it, and the tied
+        // parameter follows the uncertainty of the other
+        let mut specs = ParameterSpecs::new(3);
+        let tie = ("c1".to_string(), "-2*c2".to_string());
+        specs.set_ties(&["c0", "c1", "c2"], &[tie]).unwrap();
+        let (tied, covariance) = solve(&poly, &data, &specs, false).unwrap();
+        for (value, expected) in tied.iter().zip(parameters.iter()) {
+            assert!(close(*value, *expected));
+        }
+        assert!(close(covariance[(1, 1)], 4.0 * covariance[(2, 2)]));
+
+        // with more parameters than points, the columns of the design matrix are
+        // linearly dependent, and the SVD still finds parameters that fit every point
+        let poly = Expansion::parse("poly:6").unwrap();
+        let (parameters, _) = solve(&poly, &data, &ParameterSpecs::new(7), false).unwrap();
+        for (x, y) in izip!(&data.x, &data.y) {
+            assert!(close(poly.f(*x, parameters.as_slice()), *y));
+        }
+
+        // a fixed parameter is kept, and has no uncertainty
+        let mut specs = ParameterSpecs::new(2);
+        specs.fixed[1] = Some(0.0);
+        let (parameters, covariance) = solve(&Line, &data, &specs, false).unwrap();
+        assert!(close(parameters.x, 0.5));
+        assert_eq!(parameters.y, 0.0);
+        assert_eq!(covariance[(1, 1)], 0.0);
+
+        // parameters outside their bounds need a minimizer
+        let mut specs = ParameterSpecs::new(2);
+        specs.bounds[0] = Bounds::parse("1:2").unwrap();
+        assert!(solve(&Line, &data, &specs, false).is_none());
+    }
+}
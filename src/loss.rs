@@ -0,0 +1,158 @@
+use strum::VariantNames;
+use strum_macros::{EnumIter, EnumString, VariantNames};
+
+use std::str::FromStr;
+
+use crate::utils::prettify_list;
+
+/// Points whose weight in the fit is below this fraction are reported as down-weighted.
+pub const DOWN_WEIGHT_THRESHOLD: f64 = 0.5;
+
+/// A function ρ of the squared residual z, in units of the scale of the loss.
+/// Every loss is z for small residuals, but grows slower than z for large residuals,
+/// so that outliers pull less on the fit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, EnumIter, VariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum LossFunction {
+    /// The usual least squares, ρ(z) = z.
+    #[default]
+    Squared,
+    /// Squared for residuals below the scale, and linear above it.
+    Huber,
+    /// A smooth version of Huber, ρ(z) = 2(√(1 + z) - 1).
+    SoftL1,
+    /// ρ(z) = ln(1 + z), which grows so slowly that far outliers barely matter.
+    Cauchy,
+    /// Tukey's biweight, which ignores residuals beyond the scale completely.
+    Tukey,
+}
+
+impl LossFunction {
+    /// Tries to create a loss function from a name, returns a string with
+    /// a descriptive error message if the name is invalid.
+    pub fn descriptive_from_str(s: &str) -> Result<LossFunction, String> {
+        Self::from_str(&s.to_lowercase()).map_err(|_| {
+            format!(
+                "Got malformed loss '{}'. Legal losses are {}.",
+                s,
+                prettify_list(Self::VARIANTS)
+            )
+        })
+    }
+
+    /// ρ(z) along with its first and second derivatives. The first derivative is one
+    /// for small residuals, and is the weight of the point in the fit.
+    fn rho(&self, z: f64) -> (f64, f64, f64) {
+        match self {
+            Self::Squared => (z, 1.0, 0.0),
+            Self::Huber if z <= 1.0 => (z, 1.0, 0.0),
+            Self::Huber => (2.0 * z.sqrt() - 1.0, z.powf(-0.5), -0.5 * z.powf(-1.5)),
+            Self::SoftL1 => {
+                let t = 1.0 + z;
+                (2.0 * (t.sqrt() - 1.0), t.powf(-0.5), -0.5 * t.powf(-1.5))
+            }
+            Self::Cauchy => (z.ln_1p(), 1.0 / (1.0 + z), -(1.0 + z).powi(-2)),
+            Self::Tukey if z < 1.0 => {
+                let u = 1.0 - z;
+                ((1.0 - u.powi(3)) / 3.0, u.powi(2), -2.0 * u)
+            }
+            Self::Tukey => (1.0 / 3.0, 0.0, 0.0),
+        }
+    }
+}
+
+/// A loss function and the scale of the residuals where it starts to deviate from
+/// the squared loss. For data with uncertainties, residuals are measured in σ, so
+/// the scale is a number of standard deviations.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Loss {
+    pub function: LossFunction,
+    pub scale: f64,
+}
+
+impl Default for Loss {
+    fn default() -> Self {
+        Self {
+            function: LossFunction::Squared,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Loss {
+    /// Is the loss anything else than the squared loss?
+    pub fn is_robust(&self) -> bool {
+        self.function != LossFunction::Squared
+    }
+
+    /// The loss of the weighted squared residual z, along with its first and second
+    /// derivatives with respect to z.
+    pub fn evaluate(&self, z: f64) -> (f64, f64, f64) {
+        let scale_squared = self.scale * self.scale;
+        let (rho, d, d2) = self.function.rho(z / scale_squared);
+        (scale_squared * rho, d, d2 / scale_squared)
+    }
+
+    /// The weight of a point with weighted squared residual z relative to the squared
+    /// loss, which is between zero and one.
+    pub fn weight(&self, z: f64) -> f64 {
+        self.evaluate(z).1
+    }
+}
+
+/// Parse the scale of a loss, which must be positive.
+pub fn parse_scale(string: &str) -> Result<f64, String> {
+    match string.parse::<f64>() {
+        Ok(scale) if scale > 0.0 && scale.is_finite() => Ok(scale),
+        Ok(scale) => Err(format!(
+            "The scale of a loss must be positive, got {}",
+            scale
+        )),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn test_derivatives() {
+        let h = 1e-6;
+        for function in LossFunction::iter() {
+            let loss = Loss {
+                function,
+                scale: 2.0,
+            };
+            for z in [0.1, 1.0, 3.0, 3.9, 4.5, 20.0] {
+                let (value, d, d2) = loss.evaluate(z);
+                let (below, d_below, _) = loss.evaluate(z - h);
+                let (above, d_above, _) = loss.evaluate(z + h);
+                assert!(value <= z + 1e-12, "{:?} grows faster than z", function);
+                assert!((0.0..=1.0).contains(&d), "{:?} has weight {}", function, d);
+                assert!(
+                    ((above - below) / (2.0 * h) - d).abs() < 1e-6,
+                    "{:?} has the wrong derivative at {}",
+                    function,
+                    z
+                );
+                assert!(
+                    ((d_above - d_below) / (2.0 * h) - d2).abs() < 1e-6,
+                    "{:?} has the wrong second derivative at {}",
+                    function,
+                    z
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_scale() {
+        assert_eq!(parse_scale("1.5"), Ok(1.5));
+        assert!(parse_scale("0").is_err());
+        assert!(parse_scale("-1").is_err());
+        assert!(parse_scale("a").is_err());
+    }
+}
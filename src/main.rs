@@ -1,30 +1,24 @@
-mod error_functions;
-mod functions;
-mod minimizers;
-mod parameter_gui;
-mod plotting;
-mod statistics;
-mod utils;
-
 use clap::Parser;
-use log::{LevelFilter, info, warn};
-use nalgebra::SVector;
-use std::{env, path::PathBuf, time::Instant};
+use itertools::izip;
+use log::LevelFilter;
+use std::{env, path::PathBuf, time::Duration};
 use strum::VariantNames;
 
-use error_functions::ErrorFunction;
-use functions::{Differentiated, Functions};
-use minimizers::{MinimizerMessage, combined_descent};
-use parameter_gui::create_gui;
-use plotting::plotter::plot_static;
-use statistics::get_uncertainties;
-
-#[derive(Debug, Clone)]
-struct OptimizinateResult {
-    parameters: Vec<f64>,
-    uncertainties: Vec<f64>,
-    error: f64,
-}
+use omega_optimizer::error_functions::{HessianMode, Objective};
+use omega_optimizer::functions::Functions;
+use omega_optimizer::functions::composite::DEFAULT_COMPOSITE;
+use omega_optimizer::functions::expansion::DEFAULT_EXPANSION;
+use omega_optimizer::functions::formula::DEFAULT_FORMULA;
+use omega_optimizer::loss::{DOWN_WEIGHT_THRESHOLD, Loss, LossFunction, parse_scale};
+use omega_optimizer::minimizers::{
+    GlobalSearch, MinimizerConfig, Minimizers, RefinementStage, parse_duration,
+};
+use omega_optimizer::parameter_gui::create_gui;
+use omega_optimizer::parameters::{
+    Bounds, ParameterSpecs, ParameterStatus, Range, parse_named_bounds, parse_named_expression,
+    parse_named_range, parse_named_value,
+};
+use omega_optimizer::{FitSettings, utils};
 
 fn parse_initial_parameters(parameter_string: &str) -> Result<f64, String> {
     if parameter_string == "None" {
@@ -37,51 +31,13 @@ fn parse_initial_parameters(parameter_string: &str) -> Result<f64, String> {
     }
 }
 
-fn optimizinate<const D: usize, F: Differentiated<D>>(
-    datafile: &PathBuf,
-    initial_parameters: SVector<f64, D>,
-    plot_result: bool,
-) -> OptimizinateResult {
-    let (x_ray, y_ray) = utils::load_txt(datafile).unwrap();
-    let error_function = ErrorFunction::<D, F>::new(&x_ray, &y_ray);
-
-    let start = Instant::now();
-    let (optimal_parameters, message) = combined_descent(&initial_parameters, &error_function);
-    if let MinimizerMessage::Error(error) = message {
-        warn!("{}", error);
-    }
-
-    let parameter_uncertainties = get_uncertainties::<D, F>(&x_ray, &y_ray, &optimal_parameters);
-    let error = error_function.f(&optimal_parameters);
-    info!("Descent took {}", utils::format_duration(start.elapsed()));
-
-    if plot_result {
-        let data_name = datafile.file_stem().unwrap().to_string_lossy();
-        let figure_name = format!("figures/{}-{}.png", data_name, F::NAME);
-
-        plot_static(
-            &x_ray,
-            &y_ray,
-            F::f,
-            &optimal_parameters,
-            &parameter_uncertainties,
-            &figure_name,
-        );
-    }
-
-    OptimizinateResult {
-        parameters: optimal_parameters.as_slice().to_vec(),
-        uncertainties: parameter_uncertainties.as_slice().to_vec(),
-        error,
-    }
-}
-
 #[derive(Parser)]
 struct Args {
     /// Path to the file containing data you want to fit a function to
     datafile: PathBuf,
-    /// Name of the function you want to fit to your data.
-    /// Use the -p flag to get a list of valid function names
+    /// Name of the function you want to fit to your data, a sum or product of names
+    /// like 'normal + normal + line', an expansion like 'poly:3', or a formula of x like
+    /// 'a*exp(-l*x) + c'. Use the -p flag to get a list of valid function names
     #[arg(value_parser=Functions::descriptive_from_str)]
     function: Option<Functions>,
     /// An optional space separated list of initial parameters. Number
@@ -90,6 +46,93 @@ struct Args {
     /// are set to a default value.
     #[arg(value_parser=parse_initial_parameters)]
     initial_parameters: Option<Vec<f64>>,
+    /// Search for the global minimum before minimizing the error locally.
+    #[arg(short, long, default_value = "none", value_parser=GlobalSearch::descriptive_from_str)]
+    global: GlobalSearch,
+    /// The range a global search samples a parameter from, given as 'name=lower:upper'.
+    /// Can be given once for each parameter. Parameters without a range are sampled
+    /// around their initial value.
+    #[arg(long = "range", value_parser=parse_named_range)]
+    ranges: Vec<(String, Range)>,
+    /// Bounds a parameter must stay within, given as 'name=lower:upper', where either
+    /// value can be left out, as in 'σ=0:'. Can be given once for each parameter.
+    #[arg(long = "bound", value_parser=parse_named_bounds)]
+    bounds: Vec<(String, Bounds)>,
+    /// Keep a parameter at a value instead of fitting it, given as 'name=value'.
+    /// Can be given once for each parameter.
+    #[arg(long = "fix", value_parser=parse_named_value)]
+    fixed: Vec<(String, f64)>,
+    /// Tie a parameter to another parameter, given as 'name=expression', where the
+    /// expression is of the form 'factor*other + offset', like 'b=2*a' or 'σ₂=σ₁'.
+    /// Can be given once for each parameter.
+    #[arg(long = "tie", value_parser=parse_named_expression)]
+    ties: Vec<(String, String)>,
+    /// Path to a session file with settings for the parameters, with one
+    /// 'range name = lower:upper', 'bound name = lower:upper', 'fix name = value' or
+    /// 'tie name = expression' per line. Flags for the parameters add to these settings.
+    #[arg(short, long)]
+    session: Option<PathBuf>,
+    /// Name of the algorithm used to minimize the error.
+    #[arg(short, long, default_value = "combined", value_parser=Minimizers::descriptive_from_str)]
+    minimizer: Minimizers,
+    /// Local minimizer used by the combined minimizer to refine the result
+    /// of backtracking.
+    #[arg(
+        short,
+        long,
+        default_value = "trust_region",
+        value_parser=RefinementStage::descriptive_from_str
+    )]
+    refinement: RefinementStage,
+    /// Path to a file with minimizer settings, with one 'key = value' pair per line.
+    /// Settings given as flags override the values in this file.
+    #[arg(short, long)]
+    config: Option<PathBuf>,
+    /// Minimizers stop when the norm of the gradient is below this value.
+    #[arg(long)]
+    gradient_tolerance: Option<f64>,
+    /// Minimizers stop when a step changes the error by less than this value
+    /// relative to the error.
+    #[arg(long)]
+    function_tolerance: Option<f64>,
+    /// Minimizers stop when a step changes the parameters by less than this value
+    /// relative to the parameters.
+    #[arg(long)]
+    step_tolerance: Option<f64>,
+    /// The maximum number of iterations a minimizer can use.
+    #[arg(long)]
+    max_iterations: Option<usize>,
+    /// The maximum number of times the error can be evaluated.
+    #[arg(long)]
+    max_evaluations: Option<usize>,
+    /// The maximum number of seconds a fit can take.
+    #[arg(long, value_parser=parse_duration)]
+    time_limit: Option<Duration>,
+    /// What the fit minimizes. The Poisson deviance is meant for data that are counts,
+    /// and ignores the uncertainties of the data.
+    #[arg(long, default_value = "least_squares", value_parser=Objective::descriptive_from_str)]
+    objective: Objective,
+    /// How the hessian is calculated for the minimizers that use it. The Gauss-Newton
+    /// approximation only needs the gradient of the function, and is always positive
+    /// semi-definite.
+    #[arg(long = "hessian", default_value = "exact", value_parser=HessianMode::descriptive_from_str)]
+    hessian_mode: HessianMode,
+    /// The loss applied to the squared residuals. Robust losses reduce the influence
+    /// of outliers.
+    #[arg(short, long, default_value = "squared", value_parser=LossFunction::descriptive_from_str)]
+    loss: LossFunction,
+    /// The residual where a robust loss starts to down-weight points, in units of σ
+    /// for data with uncertainties.
+    #[arg(long, default_value_t = 1.0, value_parser=parse_scale)]
+    loss_scale: f64,
+    /// Treat the uncertainties in the third column of the data as relative weights
+    /// instead of absolute standard deviations, which scales the parameter
+    /// uncertainties by the reduced χ² of the fit.
+    #[arg(long)]
+    relative_sigma: bool,
+    /// Record and print the error after every iteration of the minimizer.
+    #[arg(long)]
+    history: bool,
     /// Run program without a gui.
     #[arg(short, long)]
     fast: bool,
@@ -98,12 +141,93 @@ struct Args {
     print_function_names: bool,
 }
 
+impl Args {
+    fn fit_settings(&self) -> FitSettings {
+        let mut config = match &self.config {
+            Some(path) => MinimizerConfig::load(path).unwrap_or_else(|e| panic!("{}", e)),
+            None => MinimizerConfig::default(),
+        };
+        if let Some(tolerance) = self.gradient_tolerance {
+            config.gradient_tolerance = tolerance;
+        }
+        if let Some(tolerance) = self.function_tolerance {
+            config.function_tolerance = tolerance;
+        }
+        if let Some(tolerance) = self.step_tolerance {
+            config.step_tolerance = tolerance;
+        }
+        if let Some(max_iterations) = self.max_iterations {
+            config.max_iterations = max_iterations;
+        }
+        if let Some(max_evaluations) = self.max_evaluations {
+            config.max_evaluations = max_evaluations;
+        }
+        if let Some(time_limit) = self.time_limit {
+            config.time_limit = Some(time_limit);
+        }
+        if self.history {
+            config.record_history = true;
+        }
+
+        FitSettings {
+            global: self.global,
+            minimizer: self.minimizer,
+            refinement: self.refinement,
+            config,
+            objective: self.objective,
+            hessian_mode: self.hessian_mode,
+            loss: Loss {
+                function: self.loss,
+                scale: self.loss_scale,
+            },
+            relative_sigma: self.relative_sigma,
+        }
+    }
+
+    /// The session file and the ranges, bounds, fixed values and ties given for the parameters of `function`.
+    fn parameter_specs(&self, function: &Functions) -> Result<ParameterSpecs, String> {
+        let names = function.parameter_names();
+        let mut specs = match &self.session {
+            Some(path) => ParameterSpecs::load(path, &names)?,
+            None => ParameterSpecs::new(names.len()),
+        };
+        specs.set_ranges(&names, &self.ranges)?;
+        specs.set_bounds(&names, &self.bounds)?;
+        specs.set_fixed(&names, &self.fixed)?;
+        specs.set_ties(&names, &self.ties)?;
+        Ok(specs)
+    }
+}
+
 fn main() {
     let args = Args::parse();
     if args.print_function_names {
         println!(
-            "Valid function names are {}.",
-            utils::prettify_list(Functions::VARIANTS)
+            "Valid function names are {}. Functions can be added and multiplied, as in \
+            '{}', and a formula of x, like '{}', can also be given instead of a name, \
+            where every other name is a parameter. Polynomials and other basis \
+            expansions are given as '{}', 'chebyshev:N', 'legendre:N' or \
+            'bspline:N:knots', and are fitted exactly.",
+            utils::prettify_list(Functions::VARIANTS),
+            DEFAULT_COMPOSITE,
+            DEFAULT_FORMULA,
+            DEFAULT_EXPANSION
+        );
+        println!(
+            "Valid minimizer names are {}.",
+            utils::prettify_list(Minimizers::VARIANTS)
+        );
+        println!(
+            "Valid global searches are {}.",
+            utils::prettify_list(GlobalSearch::VARIANTS)
+        );
+        println!(
+            "Valid objectives are {}.",
+            utils::prettify_list(Objective::VARIANTS)
+        );
+        println!(
+            "Valid losses are {}.",
+            utils::prettify_list(LossFunction::VARIANTS)
         );
     }
 
@@ -135,19 +259,94 @@ fn main() {
         }
     }
 
+    let settings = args.fit_settings();
+    if let Err(e) = settings.validate() {
+        panic!("{}", e);
+    }
+    let needs_hessian = settings.minimizer.needs_hessian(settings.refinement)
+        && settings.hessian_mode == HessianMode::Exact;
+    if let Some(function) = args
+        .function
+        .as_ref()
+        .filter(|f| needs_hessian && !f.has_hessian())
+    {
+        panic!(
+            "{:?} does not have a hessian, so it can't be used with the selected \
+            minimizer. Use a minimizer that only needs the gradient, such as bfgs, or \
+            the Gauss-Newton hessian with '--hessian gauss_newton'.",
+            function
+        );
+    }
+    let specs = match &args.function {
+        Some(function) => Some(
+            args.parameter_specs(function)
+                .unwrap_or_else(|e| panic!("{}", e)),
+        ),
+        None if !args.ranges.is_empty()
+            || !args.bounds.is_empty()
+            || !args.fixed.is_empty()
+            || !args.ties.is_empty()
+            || args.session.is_some() =>
+        {
+            panic!("You must specify a function when referring to parameters by name!");
+        }
+        None => None,
+    };
+
     if !args.fast {
-        create_gui(&args.datafile, args.function, args.initial_parameters);
+        create_gui(
+            &args.datafile,
+            args.function,
+            args.initial_parameters,
+            specs,
+            settings,
+        );
     } else {
         let Some(function) = args.function else {
             panic!("You must specify a function when running program headless!");
         };
-        let result =
-            function.optimizinate(&args.datafile, args.initial_parameters.as_deref(), true);
+        let result = function
+            .optimizinate(
+                &args.datafile,
+                args.initial_parameters.as_deref(),
+                &specs.unwrap_or_else(|| ParameterSpecs::new(function.parameter_count())),
+                &settings,
+                true,
+            )
+            .unwrap_or_else(|e| panic!("{}", e));
 
         println!(
             "Got optimal parameters: {}, which gives an error of {}",
             utils::format_with_uncertainty(&result.parameters, &result.uncertainties),
             utils::g_format(result.error, 5)
         );
+        if let Some(reduced_chi_squared) = result.reduced_chi_squared {
+            println!("Reduced χ² is {}", utils::g_format(reduced_chi_squared, 5));
+        }
+        let at_bound: Vec<&str> = izip!(function.parameter_names(), &result.statuses)
+            .filter(|(_, status)| **status == ParameterStatus::AtBound)
+            .map(|(name, _)| name)
+            .collect();
+        if !at_bound.is_empty() {
+            println!(
+                "Parameters {} ended at a bound, so their uncertainties are not estimated.",
+                utils::prettify_list(&at_bound)
+            );
+        }
+        let data = utils::load_txt(&args.datafile).unwrap();
+        let down_weighted: Vec<f64> = izip!(&data.x, &result.loss_weights)
+            .filter(|(_, weight)| **weight < DOWN_WEIGHT_THRESHOLD)
+            .map(|(x, _)| *x)
+            .collect();
+        if !down_weighted.is_empty() {
+            println!(
+                "Points at x = {} were down-weighted to less than half their weight by the loss.",
+                utils::format_vector(&down_weighted, 5)
+            );
+        }
+        println!("{}", result.report);
+        if let Some(history) = &result.report.history {
+            println!("Error history: {}", utils::format_vector(history, 5));
+        }
     }
 }
@@ -0,0 +1,94 @@
+use log::info;
+use nalgebra::{DefaultAllocator, Dim, OVector};
+
+use super::{Minimizer, MinimizerConfig, MinimizerOut, Progress, Termination};
+use crate::error_functions::ErrorFunction;
+use crate::functions::{Model, ParameterAllocator};
+
+/// Gradient descent where the step size is found using a backtracking line search.
+pub struct Backtrack;
+
+impl Minimizer for Backtrack {
+    const NAME: &'static str = "backtrack";
+
+    fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let mut progress = Progress::start(function, config);
+
+        let c = config.backtrack.c;
+        let tau = config.backtrack.tau;
+        let alpha_0 = config.backtrack.alpha_0;
+
+        let mut x = x0.clone();
+        let mut prev_alpha = alpha_0;
+        let mut prev_f = f64::INFINITY;
+
+        for _ in 0..config.max_iterations {
+            if let Some(termination) = config.budget_exhausted(function) {
+                return progress.finish(function, x, termination);
+            }
+
+            let g = function.grad(&x);
+            if config.gradient_converged(&g) {
+                info!("Backtrack converged!");
+                return progress.finish(function, x, Termination::GradientConverged);
+            }
+
+            let f_val = function.f(&x);
+            if config.function_converged(prev_f, f_val) {
+                info!("Backtrack converged!");
+                return progress.finish(function, x, Termination::FunctionConverged);
+            }
+            prev_f = f_val;
+
+            let g_norm = g.norm();
+            let t = c * g_norm.powi(2);
+            let mut alpha = prev_alpha;
+
+            let accept = |alpha: f64| f_val - function.f(&(&x - alpha * &g)) >= alpha * t;
+
+            // try to increase alpha in case previous value is too small
+            let mut increased_alpha = false;
+            while accept(alpha) {
+                increased_alpha = true;
+                alpha /= tau;
+            }
+
+            // we now know that accept(alpha) == False
+            alpha *= tau;
+
+            if !increased_alpha {
+                // decrease alpha until it is acceptable
+                while !accept(alpha) && alpha > f64::EPSILON {
+                    alpha *= tau;
+                }
+            }
+
+            prev_alpha = alpha;
+
+            if alpha <= f64::EPSILON {
+                info!("Backtrack got a step size of zero");
+                return progress.finish(function, x, Termination::Stalled);
+            }
+
+            // gradient descent using optimal step size
+            let step = -alpha * g;
+            x += &step;
+            progress.iteration(|| function.f(&x));
+
+            if config.step_converged(&step, &x) {
+                info!("Backtrack converged!");
+                return progress.finish(function, x, Termination::StepConverged);
+            }
+        }
+
+        progress.finish(function, x, Termination::MaxIterations)
+    }
+}
@@ -0,0 +1,242 @@
+use log::info;
+use nalgebra::{DefaultAllocator, Dim, OMatrix, OVector};
+
+use std::collections::VecDeque;
+
+use super::line_search::wolfe_line_search;
+use super::{Minimizer, MinimizerConfig, MinimizerOut, Progress, Termination};
+use crate::error_functions::ErrorFunction;
+use crate::functions::{Model, ParameterAllocator};
+
+/// The BFGS quasi-Newton method, which approximates the inverse hessian using the
+/// change in the gradient between steps. Only needs the function and its gradient.
+pub struct Bfgs;
+
+/// Limited-memory BFGS, which approximates the inverse hessian using only the most
+/// recent changes in the gradient, given by `lbfgs_memory` in the config.
+pub struct Lbfgs;
+
+/// An approximation of the inverse hessian.
+trait InverseHessian<D: Dim>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    /// The search direction, which is the inverse hessian times the negative gradient.
+    fn direction(&self, g: &OVector<f64, D>) -> OVector<f64, D>;
+
+    /// Update the approximation with a step `s` and the resulting change in gradient `y`.
+    fn update(&mut self, s: &OVector<f64, D>, y: &OVector<f64, D>);
+
+    /// Forget all updates, making the approximation the identity matrix.
+    fn reset(&mut self);
+
+    fn is_reset(&self) -> bool;
+}
+
+struct DenseInverse<D: Dim>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    matrix: OMatrix<f64, D, D>,
+    updated: bool,
+}
+
+impl<D: Dim> DenseInverse<D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    fn identity(&self) -> OMatrix<f64, D, D> {
+        let (dimension, _) = self.matrix.shape_generic();
+        OMatrix::identity_generic(dimension, dimension)
+    }
+}
+
+impl<D: Dim> InverseHessian<D> for DenseInverse<D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    fn direction(&self, g: &OVector<f64, D>) -> OVector<f64, D> {
+        -(&self.matrix * g)
+    }
+
+    fn update(&mut self, s: &OVector<f64, D>, y: &OVector<f64, D>) {
+        let sy = s.dot(y);
+        if sy <= f64::EPSILON * s.norm() * y.norm() {
+            return;
+        }
+
+        if !self.updated {
+            // scale the initial approximation to match the curvature along the step
+            self.matrix = self.identity() * (sy / y.norm_squared());
+            self.updated = true;
+        }
+
+        let rho = 1.0 / sy;
+        let left = self.identity() - rho * s * y.transpose();
+        self.matrix = &left * &self.matrix * left.transpose() + rho * s * s.transpose();
+    }
+
+    fn reset(&mut self) {
+        self.matrix = self.identity();
+        self.updated = false;
+    }
+
+    fn is_reset(&self) -> bool {
+        !self.updated
+    }
+}
+
+/// A step, the resulting change in gradient, and one over their dot product.
+type Update<D> = (OVector<f64, D>, OVector<f64, D>, f64);
+
+struct LimitedInverse<D: Dim>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    memory: usize,
+    updates: VecDeque<Update<D>>,
+}
+
+impl<D: Dim> InverseHessian<D> for LimitedInverse<D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    /// Computes the direction using the L-BFGS two-loop recursion.
+    fn direction(&self, g: &OVector<f64, D>) -> OVector<f64, D> {
+        let mut q = -g;
+        let mut alphas = Vec::with_capacity(self.updates.len());
+        for (s, y, rho) in self.updates.iter().rev() {
+            let alpha = rho * s.dot(&q);
+            q -= alpha * y;
+            alphas.push(alpha);
+        }
+
+        if let Some((s, y, _)) = self.updates.back() {
+            q *= s.dot(y) / y.norm_squared();
+        }
+
+        for ((s, y, rho), alpha) in self.updates.iter().zip(alphas.iter().rev()) {
+            let beta = rho * y.dot(&q);
+            q += (alpha - beta) * s;
+        }
+        q
+    }
+
+    fn update(&mut self, s: &OVector<f64, D>, y: &OVector<f64, D>) {
+        let sy = s.dot(y);
+        if sy <= f64::EPSILON * s.norm() * y.norm() || self.memory == 0 {
+            return;
+        }
+
+        if self.updates.len() == self.memory {
+            self.updates.pop_front();
+        }
+        self.updates.push_back((s.clone(), y.clone(), 1.0 / sy));
+    }
+
+    fn reset(&mut self) {
+        self.updates.clear();
+    }
+
+    fn is_reset(&self) -> bool {
+        self.updates.is_empty()
+    }
+}
+
+/// Minimizes the function using a quasi-Newton method with the given inverse hessian
+/// approximation, where steps are found using a Wolfe line search.
+fn quasi_newton<D: Dim, M: Model<D>>(
+    function: &ErrorFunction<D, M>,
+    x0: &OVector<f64, D>,
+    config: &MinimizerConfig,
+    inverse_hessian: &mut impl InverseHessian<D>,
+) -> MinimizerOut<D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let mut progress = Progress::start(function, config);
+
+    let mut x = x0.clone();
+    let mut f_val = function.f(&x);
+    let mut g = function.grad(&x);
+
+    for _ in 0..config.max_iterations {
+        if let Some(termination) = config.budget_exhausted(function) {
+            return progress.finish(function, x, termination);
+        }
+
+        if config.gradient_converged(&g) {
+            info!("Quasi-Newton converged!");
+            return progress.finish(function, x, Termination::GradientConverged);
+        }
+
+        let direction = inverse_hessian.direction(&g);
+        let Some(point) = wolfe_line_search(function, &x, f_val, &g, &direction) else {
+            if inverse_hessian.is_reset() {
+                info!("Quasi-Newton line search failed");
+                return progress.finish(function, x, Termination::Stalled);
+            }
+            // the approximation might be bad, so try again with gradient descent
+            inverse_hessian.reset();
+            continue;
+        };
+
+        let step = &point.x - &x;
+        inverse_hessian.update(&step, &(&point.g - &g));
+        x = point.x;
+        g = point.g;
+        progress.iteration(|| point.f);
+
+        if config.function_converged(f_val, point.f) {
+            info!("Quasi-Newton converged!");
+            return progress.finish(function, x, Termination::FunctionConverged);
+        }
+        if config.step_converged(&step, &x) {
+            info!("Quasi-Newton converged!");
+            return progress.finish(function, x, Termination::StepConverged);
+        }
+        f_val = point.f;
+    }
+
+    progress.finish(function, x, Termination::MaxIterations)
+}
+
+impl Minimizer for Bfgs {
+    const NAME: &'static str = "bfgs";
+
+    fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let mut inverse_hessian = DenseInverse {
+            matrix: OMatrix::identity_generic(function.dimension(), function.dimension()),
+            updated: false,
+        };
+        quasi_newton(function, x0, config, &mut inverse_hessian)
+    }
+}
+
+impl Minimizer for Lbfgs {
+    const NAME: &'static str = "lbfgs";
+
+    fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let mut inverse_hessian = LimitedInverse {
+            memory: config.lbfgs_memory,
+            updates: VecDeque::with_capacity(config.lbfgs_memory),
+        };
+        quasi_newton(function, x0, config, &mut inverse_hessian)
+    }
+}
@@ -0,0 +1,210 @@
+use log::{debug, info};
+use nalgebra::{DefaultAllocator, Dim, OVector};
+use strum::VariantNames;
+use strum_macros::{EnumIter, EnumString, VariantNames};
+
+use std::str::FromStr;
+
+use super::{
+    Backtrack, Bfgs, Lbfgs, LevenbergMarquardt, Minimizer, MinimizerConfig, MinimizerOut,
+    NelderMead, Newton, Progress, Termination, TrustRegion,
+};
+use crate::error_functions::ErrorFunction;
+use crate::functions::{Model, ParameterAllocator};
+use crate::utils::prettify_list;
+
+/// The local minimizer used by `Combined` to refine the approximate minimum
+/// found by backtracking.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, EnumIter, VariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum RefinementStage {
+    Newton,
+    LevenbergMarquardt,
+    #[default]
+    TrustRegion,
+    Bfgs,
+    Lbfgs,
+}
+
+impl RefinementStage {
+    /// Tries to create a refinement stage from a name, returns a string with
+    /// a descriptive error message if the name is invalid.
+    pub fn descriptive_from_str(s: &str) -> Result<RefinementStage, String> {
+        Self::from_str(&s.to_lowercase()).map_err(|_| {
+            format!(
+                "Got malformed refinement stage '{}'. Legal \
+                refinement stages are {}.",
+                s,
+                prettify_list(Self::VARIANTS)
+            )
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::Newton => Newton::NAME,
+            Self::LevenbergMarquardt => LevenbergMarquardt::NAME,
+            Self::TrustRegion => TrustRegion::NAME,
+            Self::Bfgs => Bfgs::NAME,
+            Self::Lbfgs => Lbfgs::NAME,
+        }
+    }
+
+    pub fn needs_hessian(&self) -> bool {
+        match self {
+            Self::Newton => Newton::NEEDS_HESSIAN,
+            Self::LevenbergMarquardt => LevenbergMarquardt::NEEDS_HESSIAN,
+            Self::TrustRegion => TrustRegion::NEEDS_HESSIAN,
+            Self::Bfgs => Bfgs::NEEDS_HESSIAN,
+            Self::Lbfgs => Lbfgs::NEEDS_HESSIAN,
+        }
+    }
+
+    fn refine<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        match self {
+            Self::Newton => Newton.minimize(function, x0, config),
+            Self::LevenbergMarquardt => LevenbergMarquardt.minimize(function, x0, config),
+            Self::TrustRegion => TrustRegion.minimize(function, x0, config),
+            Self::Bfgs => Bfgs.minimize(function, x0, config),
+            Self::Lbfgs => Lbfgs.minimize(function, x0, config),
+        }
+    }
+}
+
+/// The gradient-based stages can't handle a gradient that is not finite, so in that
+/// case we fall back to the Nelder-Mead minimizer, which only uses function values.
+fn fall_back<D: Dim, M: Model<D>>(
+    function: &ErrorFunction<D, M>,
+    x0: &OVector<f64, D>,
+    config: &MinimizerConfig,
+    mut progress: Progress,
+) -> MinimizerOut<D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    info!("Gradient is not finite, falling back to Nelder-Mead");
+    let (x, report) = NelderMead.minimize(function, x0, config);
+    progress.stage(NelderMead::NAME, &report);
+    progress.finish(function, x, report.termination)
+}
+
+/// Alternates between backtracking with an increasing number of steps and a refinement
+/// stage, which converges quickly once backtracking has found an approximate minimum.
+pub struct Combined {
+    pub refinement: RefinementStage,
+}
+
+impl Minimizer for Combined {
+    const NAME: &'static str = "combined";
+
+    fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let mut progress = Progress::start(function, config);
+        if self.refinement.needs_hessian() && !function.has_hessian() {
+            return progress.finish(
+                function,
+                x0.clone(),
+                Termination::Error("Refinement stage needs a hessian, but function has none!"),
+            );
+        }
+
+        let step_counts = config.step_counts();
+        let min_steps = step_counts[0];
+
+        let mut best_params = x0.clone();
+        let mut best_f = function.f(&best_params);
+
+        for step_count in step_counts {
+            if let Some(termination) = config.budget_exhausted(function) {
+                return progress.finish(function, best_params, termination);
+            }
+            debug!("Trying {} iterations...", step_count);
+
+            // try a quick backtrack minimization to find approximate minimum
+            let (backtrack_out, report) = Backtrack.minimize(
+                function,
+                &best_params,
+                &config.with_max_iterations(step_count),
+            );
+            progress.stage(Backtrack::NAME, &report);
+            if !report.gradient_norm.is_finite() {
+                let start = if function.f(&backtrack_out) <= best_f {
+                    backtrack_out
+                } else {
+                    best_params
+                };
+                return fall_back(function, &start, config, progress);
+            }
+            match report.termination {
+                Termination::MaxIterations => {}
+                Termination::Error(_) => {
+                    return progress.finish(
+                        function,
+                        best_params,
+                        Termination::Error("Backtrack can't improve"),
+                    );
+                }
+                termination => return progress.finish(function, backtrack_out, termination),
+            }
+            let f_backtrack = function.f(&backtrack_out);
+            if f_backtrack > best_f {
+                return progress.finish(
+                    function,
+                    best_params,
+                    Termination::Error("Backtrack increased error?!"),
+                );
+            }
+
+            // try to use the refinement stage to improve result
+            let (refined_out, report) = self.refinement.refine(
+                function,
+                &backtrack_out,
+                &config.with_max_iterations(min_steps),
+            );
+            progress.stage(self.refinement.name(), &report);
+            if !report.gradient_norm.is_finite() {
+                return fall_back(function, &backtrack_out, config, progress);
+            }
+            if function.f(&refined_out) < f_backtrack {
+                match report.termination {
+                    Termination::MaxIterations => {
+                        let (refined_out, report) =
+                            self.refinement.refine(function, &refined_out, config);
+                        progress.stage(self.refinement.name(), &report);
+                        return progress.finish(function, refined_out, report.termination);
+                    }
+                    Termination::Error(_) => {
+                        best_f = function.f(&refined_out);
+                        best_params = refined_out;
+                        continue;
+                    }
+                    termination => return progress.finish(function, refined_out, termination),
+                }
+            }
+
+            best_params = backtrack_out;
+            best_f = f_backtrack;
+        }
+
+        progress.finish(
+            function,
+            best_params,
+            Termination::Error("Combined descent never converged"),
+        )
+    }
+}
@@ -0,0 +1,468 @@
+use nalgebra::{DefaultAllocator, Dim, OVector};
+
+use std::{
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+
+use super::Termination;
+use crate::error_functions::ErrorFunction;
+use crate::functions::{Model, ParameterAllocator};
+use crate::utils::prettify_list;
+
+/// Parameters of the backtracking line search.
+#[derive(Debug, Clone, Copy)]
+pub struct BacktrackArgs {
+    /// Fraction of the decrease predicted by the gradient a step must achieve.
+    pub c: f64,
+    /// Factor the step size is multiplied by while searching.
+    pub tau: f64,
+    /// The initial step size.
+    pub alpha_0: f64,
+}
+
+impl Default for BacktrackArgs {
+    fn default() -> Self {
+        Self {
+            c: 0.5,
+            tau: 0.5,
+            alpha_0: 1.0,
+        }
+    }
+}
+
+/// Parameters of the Nelder-Mead simplex minimizer.
+#[derive(Debug, Clone, Copy)]
+pub struct NelderMeadArgs {
+    /// The size of the initial simplex relative to the initial parameters.
+    pub initial_size: f64,
+    /// The simplex has converged when its size is below this value relative to
+    /// the parameters.
+    pub x_tolerance: f64,
+    /// The simplex has converged when the spread of the function values is below
+    /// this value relative to the function value. Zero disables this criterion.
+    pub f_tolerance: f64,
+}
+
+impl Default for NelderMeadArgs {
+    fn default() -> Self {
+        Self {
+            initial_size: 0.1,
+            x_tolerance: 1e-10,
+            f_tolerance: 0.0,
+        }
+    }
+}
+
+/// Parameters of the global searches.
+#[derive(Debug, Clone, Copy)]
+pub struct GlobalArgs {
+    /// The number of starting points of multi-start, and the population size of
+    /// differential evolution.
+    pub samples: usize,
+    /// The number of generations of differential evolution, and the number of
+    /// hops of each basin-hopping chain.
+    pub iterations: usize,
+    /// The number of independent basin-hopping chains.
+    pub chains: usize,
+    /// A basin-hopping minimum that is worse than the current one by this amount is
+    /// accepted with a probability of 1/e. If there is no temperature, it is a
+    /// fraction of the spread of the errors of sampled parameters.
+    pub temperature: Option<f64>,
+    /// The seed of the random number generator. If there is no seed, a random
+    /// seed is used, which is logged so the search can be reproduced.
+    pub seed: Option<u64>,
+    /// The number of threads used by the searches and by evaluations of the error
+    /// function on large datasets, where zero means one per core.
+    pub threads: usize,
+}
+
+impl Default for GlobalArgs {
+    fn default() -> Self {
+        Self {
+            samples: 20,
+            iterations: 50,
+            chains: 4,
+            temperature: None,
+            seed: None,
+            threads: 0,
+        }
+    }
+}
+
+/// Convergence criteria and budgets shared by all minimizers.
+#[derive(Debug, Clone, Copy)]
+pub struct MinimizerConfig {
+    /// A minimizer has converged when the norm of the gradient is below this value.
+    pub gradient_tolerance: f64,
+    /// A minimizer has converged when a step changes the function value by less
+    /// than this value relative to the function value. Zero disables this criterion.
+    pub function_tolerance: f64,
+    /// A minimizer has converged when a step changes the parameters by less than
+    /// this value relative to the parameters. Zero disables this criterion.
+    pub step_tolerance: f64,
+    /// The maximum number of iterations a minimizer can use.
+    pub max_iterations: usize,
+    /// The maximum number of times a fit can evaluate the function.
+    pub max_evaluations: usize,
+    /// The maximum amount of time a fit can take.
+    pub time_limit: Option<Duration>,
+    pub backtrack: BacktrackArgs,
+    /// The number of previous steps L-BFGS uses to approximate the hessian.
+    pub lbfgs_memory: usize,
+    pub nelder_mead: NelderMeadArgs,
+    pub global: GlobalArgs,
+    /// The gui reruns the minimizer until the error decreases by less than this value.
+    pub restart_tolerance: f64,
+    /// Should minimizers record the error after every iteration?
+    pub record_history: bool,
+    deadline: Option<Instant>,
+}
+
+impl Default for MinimizerConfig {
+    fn default() -> Self {
+        Self {
+            gradient_tolerance: 1e-12,
+            function_tolerance: 0.0,
+            step_tolerance: 0.0,
+            max_iterations: 10_000,
+            max_evaluations: usize::MAX,
+            time_limit: None,
+            backtrack: BacktrackArgs::default(),
+            lbfgs_memory: 10,
+            nelder_mead: NelderMeadArgs::default(),
+            global: GlobalArgs::default(),
+            restart_tolerance: 1e-8,
+            record_history: false,
+            deadline: None,
+        }
+    }
+}
+
+fn parse_float(value: &str) -> Result<f64, String> {
+    value.parse::<f64>().map_err(|e| e.to_string())
+}
+
+fn parse_count(value: &str) -> Result<usize, String> {
+    value.parse::<usize>().map_err(|e| e.to_string())
+}
+
+/// Parse a count, where 'none' means that there is no limit.
+fn parse_limit(value: &str) -> Result<usize, String> {
+    if value == "none" {
+        Ok(usize::MAX)
+    } else {
+        parse_count(value)
+    }
+}
+
+/// Parse a duration in seconds, which must be finite and not negative.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    parse_float(value).and_then(|v| Duration::try_from_secs_f64(v).map_err(|e| e.to_string()))
+}
+
+/// Parse a temperature, which must be positive and finite.
+fn parse_temperature(value: &str) -> Result<f64, String> {
+    parse_float(value).and_then(|v| {
+        if v > 0.0 && v.is_finite() {
+            Ok(v)
+        } else {
+            Err("the temperature must be positive and finite".into())
+        }
+    })
+}
+
+fn parse_bool(value: &str) -> Result<bool, String> {
+    value.parse::<bool>().map_err(|e| e.to_string())
+}
+
+fn format_float(value: f64) -> String {
+    if value == 0.0 || (1e-3..1e4).contains(&value.abs()) {
+        format!("{}", value)
+    } else {
+        format!("{:e}", value)
+    }
+}
+
+fn format_limit(value: usize) -> String {
+    if value == usize::MAX {
+        "none".into()
+    } else {
+        format!("{}", value)
+    }
+}
+
+impl MinimizerConfig {
+    /// The keys used to set values in a config file.
+    pub const KEYS: [&'static str; 21] = [
+        "gradient_tolerance",
+        "function_tolerance",
+        "step_tolerance",
+        "max_iterations",
+        "max_evaluations",
+        "time_limit",
+        "backtrack_c",
+        "backtrack_tau",
+        "backtrack_alpha_0",
+        "lbfgs_memory",
+        "nelder_mead_initial_size",
+        "nelder_mead_x_tolerance",
+        "nelder_mead_f_tolerance",
+        "global_samples",
+        "global_iterations",
+        "global_chains",
+        "global_temperature",
+        "global_seed",
+        "global_threads",
+        "restart_tolerance",
+        "record_history",
+    ];
+
+    /// Get the value of a key as a string, in the format used by config files.
+    pub fn get(&self, key: &str) -> Option<String> {
+        let value = match key {
+            "gradient_tolerance" => format_float(self.gradient_tolerance),
+            "function_tolerance" => format_float(self.function_tolerance),
+            "step_tolerance" => format_float(self.step_tolerance),
+            "max_iterations" => format_limit(self.max_iterations),
+            "max_evaluations" => format_limit(self.max_evaluations),
+            "time_limit" => match self.time_limit {
+                Some(limit) => format_float(limit.as_secs_f64()),
+                None => "none".into(),
+            },
+            "backtrack_c" => format_float(self.backtrack.c),
+            "backtrack_tau" => format_float(self.backtrack.tau),
+            "backtrack_alpha_0" => format_float(self.backtrack.alpha_0),
+            "lbfgs_memory" => format!("{}", self.lbfgs_memory),
+            "nelder_mead_initial_size" => format_float(self.nelder_mead.initial_size),
+            "nelder_mead_x_tolerance" => format_float(self.nelder_mead.x_tolerance),
+            "nelder_mead_f_tolerance" => format_float(self.nelder_mead.f_tolerance),
+            "global_samples" => format!("{}", self.global.samples),
+            "global_iterations" => format!("{}", self.global.iterations),
+            "global_chains" => format!("{}", self.global.chains),
+            "global_temperature" => match self.global.temperature {
+                Some(temperature) => format_float(temperature),
+                None => "none".into(),
+            },
+            "global_seed" => match self.global.seed {
+                Some(seed) => format!("{}", seed),
+                None => "none".into(),
+            },
+            "global_threads" => format!("{}", self.global.threads),
+            "restart_tolerance" => format_float(self.restart_tolerance),
+            "record_history" => format!("{}", self.record_history),
+            _ => return None,
+        };
+        Some(value)
+    }
+
+    /// Set the value of a key from a string. Returns a descriptive error message
+    /// if the key does not exist or the value is malformed.
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let value = value.trim();
+        let result = match key {
+            "gradient_tolerance" => parse_float(value).map(|v| self.gradient_tolerance = v),
+            "function_tolerance" => parse_float(value).map(|v| self.function_tolerance = v),
+            "step_tolerance" => parse_float(value).map(|v| self.step_tolerance = v),
+            "max_iterations" => parse_limit(value).map(|v| self.max_iterations = v),
+            "max_evaluations" => parse_limit(value).map(|v| self.max_evaluations = v),
+            "time_limit" => {
+                if value == "none" {
+                    self.time_limit = None;
+                    Ok(())
+                } else {
+                    parse_duration(value).map(|limit| self.time_limit = Some(limit))
+                }
+            }
+            "backtrack_c" => parse_float(value).map(|v| self.backtrack.c = v),
+            "backtrack_tau" => parse_float(value).map(|v| self.backtrack.tau = v),
+            "backtrack_alpha_0" => parse_float(value).map(|v| self.backtrack.alpha_0 = v),
+            "lbfgs_memory" => parse_count(value).map(|v| self.lbfgs_memory = v),
+            "nelder_mead_initial_size" => {
+                parse_float(value).map(|v| self.nelder_mead.initial_size = v)
+            }
+            "nelder_mead_x_tolerance" => {
+                parse_float(value).map(|v| self.nelder_mead.x_tolerance = v)
+            }
+            "nelder_mead_f_tolerance" => {
+                parse_float(value).map(|v| self.nelder_mead.f_tolerance = v)
+            }
+            "global_samples" => parse_count(value).map(|v| self.global.samples = v),
+            "global_iterations" => parse_count(value).map(|v| self.global.iterations = v),
+            "global_chains" => parse_count(value).map(|v| self.global.chains = v),
+            "global_temperature" => {
+                if value == "none" {
+                    self.global.temperature = None;
+                    Ok(())
+                } else {
+                    parse_temperature(value).map(|v| self.global.temperature = Some(v))
+                }
+            }
+            "global_seed" => {
+                if value == "none" {
+                    self.global.seed = None;
+                    Ok(())
+                } else {
+                    value
+                        .parse::<u64>()
+                        .map(|v| self.global.seed = Some(v))
+                        .map_err(|e| e.to_string())
+                }
+            }
+            "global_threads" => parse_count(value).map(|v| self.global.threads = v),
+            "restart_tolerance" => parse_float(value).map(|v| self.restart_tolerance = v),
+            "record_history" => parse_bool(value).map(|v| self.record_history = v),
+            _ => {
+                return Err(format!(
+                    "Got unknown config key '{}'. Legal keys are {}.",
+                    key,
+                    prettify_list(&Self::KEYS)
+                ));
+            }
+        };
+        result.map_err(|e| format!("Got malformed value '{}' for {}: {}", value, key, e))
+    }
+
+    /// Read a config from a file with one 'key = value' pair per line. Keys that are
+    /// not in the file keep their default value, and '#' starts a comment.
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Got error when opening {:?}: {}", path, e))?;
+
+        let mut config = Self::default();
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(format!(
+                    "Got malformed config line: {}. Lines must be of the form 'key = value'",
+                    line
+                ));
+            };
+            config.set(key.trim(), value)?;
+        }
+
+        Ok(config)
+    }
+
+    /// A copy of this config with a different iteration limit.
+    pub fn with_max_iterations(&self, max_iterations: usize) -> Self {
+        let mut config = *self;
+        config.max_iterations = max_iterations;
+        config
+    }
+
+    /// A copy of this config where the time limit is counted from now. If the clock
+    /// is already running, the deadline is left unchanged.
+    pub fn start_clock(&self) -> Self {
+        let mut config = *self;
+        if config.deadline.is_none() {
+            config.deadline = self.time_limit.map(|limit| Instant::now() + limit);
+        }
+        config
+    }
+
+    /// The increasing iteration counts used by the combined minimizer, where each
+    /// count is ten times the previous one, ending at `max_iterations`. Without an
+    /// iteration limit, the counts end where they would overflow.
+    pub fn step_counts(&self) -> Vec<usize> {
+        let mut step_counts = Vec::new();
+        let mut step_count = Some(10);
+        while let Some(count) = step_count.filter(|count| *count < self.max_iterations) {
+            step_counts.push(count);
+            step_count = count.checked_mul(10);
+        }
+        step_counts.push(self.max_iterations);
+        step_counts
+    }
+
+    pub fn gradient_converged<D: Dim>(&self, gradient: &OVector<f64, D>) -> bool
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        gradient.norm() < self.gradient_tolerance
+    }
+
+    pub fn function_converged(&self, previous: f64, current: f64) -> bool {
+        previous.is_finite()
+            && (previous - current).abs()
+                <= self.function_tolerance * previous.abs().max(current.abs())
+    }
+
+    pub fn step_converged<D: Dim>(&self, step: &OVector<f64, D>, x: &OVector<f64, D>) -> bool
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        step.norm() <= self.step_tolerance * (x.norm() + self.step_tolerance)
+    }
+
+    /// Returns the reason for stopping if the fit has used up its function
+    /// evaluations or its time.
+    pub fn budget_exhausted<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+    ) -> Option<Termination>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        if function.evaluation_counts().function >= self.max_evaluations {
+            Some(Termination::MaxEvaluations)
+        } else if self
+            .deadline
+            .is_some_and(|deadline| Instant::now() >= deadline)
+        {
+            Some(Termination::TimeLimit)
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_get_and_set() {
+        let mut config = MinimizerConfig::default();
+        for key in MinimizerConfig::KEYS {
+            let value = config.get(key).unwrap();
+            assert!(config.set(key, &value).is_ok(), "could not set {}", key);
+        }
+
+        assert!(config.set("max_evaluations", "500").is_ok());
+        assert_eq!(config.max_evaluations, 500);
+        assert!(config.set("time_limit", "1.5").is_ok());
+        assert_eq!(config.time_limit, Some(Duration::from_millis(1500)));
+        assert!(config.set("time_limit", "-1").is_err());
+        assert!(config.set("time_limit", "NaN").is_err());
+        assert!(config.set("global_temperature", "0.5").is_ok());
+        assert_eq!(config.global.temperature, Some(0.5));
+        assert!(config.set("global_temperature", "0").is_err());
+        assert!(config.set("global_temperature", "inf").is_err());
+
+        assert!(config.set("max_iterations", "-3").is_err());
+        assert!(config.set("does_not_exist", "1").is_err());
+    }
+
+    #[test]
+    fn test_step_counts() {
+        let config = MinimizerConfig::default();
+        assert_eq!(config.step_counts(), vec![10, 100, 1000, 10_000]);
+
+        let config = config.with_max_iterations(500);
+        assert_eq!(config.step_counts(), vec![10, 100, 500]);
+
+        let config = config.with_max_iterations(5);
+        assert_eq!(config.step_counts(), vec![5]);
+
+        let mut config = config;
+        config.set("max_iterations", "none").unwrap();
+        let step_counts = config.step_counts();
+        assert_eq!(step_counts.len(), usize::MAX.ilog10() as usize + 1);
+        assert_eq!(step_counts.last(), Some(&usize::MAX));
+    }
+}
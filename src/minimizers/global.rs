@@ -0,0 +1,420 @@
+use log::info;
+use nalgebra::{DefaultAllocator, Dim, OVector, U1};
+use rand::prelude::{Rng, SeedableRng, SliceRandom, StdRng};
+use strum::VariantNames;
+use strum_macros::{EnumIter, EnumString, VariantNames};
+
+use std::str::FromStr;
+
+use super::{
+    Combined, Minimizer, MinimizerConfig, MinimizerOut, Minimizers, Progress, RefinementStage,
+    Termination,
+};
+use crate::error_functions::ErrorFunction;
+use crate::functions::{Model, ParameterAllocator};
+use crate::parameters::Range;
+use crate::utils::{parallel_map, prettify_list};
+
+/// The differential weight of differential evolution.
+const DIFFERENTIAL_WEIGHT: f64 = 0.8;
+/// The probability that differential evolution uses a parameter from the mutant.
+const CROSSOVER_PROBABILITY: f64 = 0.9;
+/// The size of basin-hopping perturbations relative to the width of the parameter ranges.
+const HOP_SIZE: f64 = 0.25;
+/// The default basin-hopping temperature relative to the spread of the errors of
+/// sampled parameters.
+const TEMPERATURE_FRACTION: f64 = 0.1;
+
+/// A search for the global minimum, which is done before a local minimizer polishes
+/// the best candidate.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, EnumIter, VariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum GlobalSearch {
+    /// Only use the local minimizer.
+    #[default]
+    None,
+    /// Run the local minimizer from Latin-hypercube samples of the parameter ranges.
+    Multistart,
+    DifferentialEvolution,
+    /// Repeatedly perturb a local minimum and minimize again, keeping the new
+    /// minimum if it is better, or with some probability if it is worse.
+    BasinHopping,
+}
+
+impl GlobalSearch {
+    /// Tries to create a global search from a name, returns a string with
+    /// a descriptive error message if the name is invalid.
+    pub fn descriptive_from_str(s: &str) -> Result<GlobalSearch, String> {
+        Self::from_str(&s.to_lowercase()).map_err(|_| {
+            format!(
+                "Got malformed global search '{}'. Legal \
+                global searches are {}.",
+                s,
+                prettify_list(Self::VARIANTS)
+            )
+        })
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Self::None => "none",
+            Self::Multistart => "multistart",
+            Self::DifferentialEvolution => "differential_evolution",
+            Self::BasinHopping => "basin_hopping",
+        }
+    }
+
+    /// Search for the global minimum of `function` within `ranges`, which are ranges of
+    /// the function's parameters rather than the internal ones, and polish the best
+    /// candidate with the combined minimizer. `minimizer` is used for the local
+    /// minimizations of the search, or on its own if there is no global search.
+    ///
+    /// This is where a fit starts, so the result is checked for being a saddle point
+    /// or maximum here, see `MinimizerReport::classify`.
+    pub fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        ranges: &[Range],
+        minimizer: Minimizers,
+        refinement: RefinementStage,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let (x, report) = if *self == Self::None {
+            minimizer.minimize(function, x0, config, refinement)
+        } else {
+            self.search(function, x0, ranges, minimizer, refinement, config)
+        };
+        let report = report.classify(function, &x);
+        (x, report)
+    }
+
+    /// Run the global search, and polish the best candidate.
+    fn search<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        ranges: &[Range],
+        minimizer: Minimizers,
+        refinement: RefinementStage,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let config = &config.start_clock();
+        let seed = config.global.seed.unwrap_or_else(|| {
+            let seed = rand::rng().random();
+            info!("Global search is using the seed {}", seed);
+            seed
+        });
+        let search = Search {
+            function,
+            ranges,
+            config,
+            minimizer,
+            refinement,
+            rng: StdRng::seed_from_u64(seed),
+        };
+
+        let mut progress = Progress::start(function, config);
+        let (candidate, report) = match self {
+            Self::None => unreachable!(),
+            Self::Multistart => search.multistart(x0),
+            Self::DifferentialEvolution => search.differential_evolution(x0),
+            Self::BasinHopping => search.basin_hopping(x0),
+        };
+        progress.stage(self.name(), &report);
+
+        let (x, report) = Combined { refinement }.minimize(function, &candidate, config);
+        progress.stage(Combined::NAME, &report);
+        progress.finish(function, x, report.termination)
+    }
+}
+
+/// The state shared by the global searches.
+struct Search<'a, D: Dim, M: Model<D>>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    function: &'a ErrorFunction<D, M>,
+    ranges: &'a [Range],
+    config: &'a MinimizerConfig,
+    minimizer: Minimizers,
+    refinement: RefinementStage,
+    rng: StdRng,
+}
+
+impl<D: Dim, M: Model<D>> Search<'_, D, M>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    /// The function value, where NaN is treated as infinitely bad.
+    fn f(&self, x: &OVector<f64, D>) -> f64 {
+        let f_val = self.function.f(x);
+        if f_val.is_nan() { f64::INFINITY } else { f_val }
+    }
+
+    /// Minimize the function locally, returning the minimum and its function value.
+    fn local(&self, x0: &OVector<f64, D>) -> (OVector<f64, D>, f64) {
+        let (x, _) = self
+            .minimizer
+            .minimize(self.function, x0, self.config, self.refinement);
+        let f_val = self.f(&x);
+        (x, f_val)
+    }
+
+    /// The reason to stop the search, where a search that is within budget
+    /// has tried every candidate.
+    fn termination(&self) -> Termination {
+        self.config
+            .budget_exhausted(self.function)
+            .unwrap_or(Termination::SearchCompleted)
+    }
+
+    /// Sample `n` points from the parameter ranges, such that every parameter is
+    /// sampled once from each of `n` equally sized intervals of its range. The
+    /// samples are returned as internal parameters.
+    fn latin_hypercube(&mut self, n: usize) -> Vec<OVector<f64, D>> {
+        let dimension = self.function.dimension();
+        let mut samples = vec![OVector::zeros_generic(dimension, U1); n];
+        for (i, range) in self.ranges.iter().enumerate() {
+            let mut intervals: Vec<usize> = (0..n).collect();
+            intervals.shuffle(&mut self.rng);
+            for (sample, interval) in samples.iter_mut().zip(intervals) {
+                sample[i] = range.lerp((interval as f64 + self.rng.random::<f64>()) / n as f64);
+            }
+        }
+        samples.iter().map(|x| self.function.internal(x)).collect()
+    }
+
+    fn multistart(mut self, x0: &OVector<f64, D>) -> MinimizerOut<D> {
+        let mut progress = Progress::start(self.function, self.config);
+
+        let mut starts = vec![x0.clone()];
+        starts.extend(self.latin_hypercube(self.config.global.samples));
+        let minima = parallel_map(&starts, self.config.global.threads, |x| self.local(x));
+
+        let mut best = minima[0].clone();
+        for minimum in minima {
+            if minimum.1 < best.1 {
+                best = minimum;
+            }
+            progress.iteration(|| best.1);
+        }
+
+        progress.finish(self.function, best.0, self.termination())
+    }
+
+    fn differential_evolution(mut self, x0: &OVector<f64, D>) -> MinimizerOut<D> {
+        let mut progress = Progress::start(self.function, self.config);
+        let threads = self.config.global.threads;
+        let n = self.config.global.samples.max(4);
+
+        let mut population = vec![x0.clone()];
+        population.extend(self.latin_hypercube(n - 1));
+        let mut values = parallel_map(&population, threads, |x| self.f(x));
+
+        for _ in 0..self.config.global.iterations {
+            if self.config.budget_exhausted(self.function).is_some() {
+                break;
+            }
+
+            let mut trials = Vec::with_capacity(n);
+            for (i, x) in population.iter().enumerate() {
+                // pick three distinct members that are not the current one
+                let mut picks = [i; 3];
+                for k in 0..3 {
+                    while picks[k] == i || picks[..k].contains(&picks[k]) {
+                        picks[k] = self.rng.random_range(0..n);
+                    }
+                }
+                let [a, b, c] = picks.map(|k| &population[k]);
+                let mutant = a + DIFFERENTIAL_WEIGHT * (b - c);
+
+                let forced = self.rng.random_range(0..x.len());
+                let trial = OVector::from_fn_generic(x.shape_generic().0, U1, |j, _| {
+                    if j == forced || self.rng.random::<f64>() < CROSSOVER_PROBABILITY {
+                        mutant[j]
+                    } else {
+                        x[j]
+                    }
+                });
+                trials.push(trial);
+            }
+
+            let trial_values = parallel_map(&trials, threads, |x| self.f(x));
+            for (i, (trial, value)) in trials.into_iter().zip(trial_values).enumerate() {
+                if value <= values[i] {
+                    population[i] = trial;
+                    values[i] = value;
+                }
+            }
+            progress.iteration(|| values.iter().copied().fold(f64::INFINITY, f64::min));
+        }
+
+        let best = (0..n)
+            .min_by(|&i, &j| values[i].total_cmp(&values[j]))
+            .unwrap();
+        let best = population.swap_remove(best);
+        progress.finish(self.function, best, self.termination())
+    }
+
+    /// The default basin-hopping temperature, which is a fraction of the interquartile
+    /// range of the errors of Latin-hypercube samples of the parameter ranges, so it
+    /// follows the scale of the error.
+    fn default_temperature(&mut self) -> f64 {
+        let samples = self.latin_hypercube(self.config.global.samples.max(4));
+        let mut values: Vec<f64> =
+            parallel_map(&samples, self.config.global.threads, |x| self.f(x))
+                .into_iter()
+                .filter(|v| v.is_finite())
+                .collect();
+        if values.is_empty() {
+            return 0.0;
+        }
+        values.sort_by(f64::total_cmp);
+        let last = values.len() - 1;
+        TEMPERATURE_FRACTION * (values[3 * last / 4] - values[last / 4])
+    }
+
+    /// Run a basin-hopping chain with its own random number generator, and return
+    /// the best minimum found after each hop. A minimum that is worse than the
+    /// current one by `temperature` is accepted with a probability of 1/e.
+    fn hop(
+        &self,
+        x0: &OVector<f64, D>,
+        temperature: f64,
+        seed: u64,
+    ) -> Vec<(OVector<f64, D>, f64)> {
+        let mut rng = StdRng::seed_from_u64(seed);
+        // without a usable temperature, only better minima are accepted
+        let temperature = Some(temperature).filter(|t| *t > 0.0 && t.is_finite());
+
+        let mut current = self.local(x0);
+        let mut best = vec![current.clone()];
+        for _ in 0..self.config.global.iterations {
+            if self.config.budget_exhausted(self.function).is_some() {
+                break;
+            }
+
+            // perturb the function's parameters, so the hop size does not depend on bounds
+            let perturbed = self.function.external(&current.0)
+                + OVector::<f64, D>::from_fn_generic(current.0.shape_generic().0, U1, |i, _| {
+                    HOP_SIZE * self.ranges[i].width() * rng.random_range(-1.0..1.0)
+                });
+            let next = self.local(&self.function.internal(&perturbed));
+            let accepted = next.1 < current.1
+                || temperature.is_some_and(|temperature| {
+                    rng.random::<f64>() < (-(next.1 - current.1) / temperature).exp()
+                });
+            if accepted {
+                current = next;
+            }
+
+            let previous_best = &best[best.len() - 1];
+            best.push(if current.1 < previous_best.1 {
+                current.clone()
+            } else {
+                previous_best.clone()
+            });
+        }
+        best
+    }
+
+    fn basin_hopping(mut self, x0: &OVector<f64, D>) -> MinimizerOut<D> {
+        let mut progress = Progress::start(self.function, self.config);
+
+        let temperature = match self.config.global.temperature {
+            Some(temperature) => temperature,
+            None => self.default_temperature(),
+        };
+        let seeds: Vec<u64> = (0..self.config.global.chains.max(1))
+            .map(|_| self.rng.random())
+            .collect();
+        let chains = parallel_map(&seeds, self.config.global.threads, |seed| {
+            self.hop(x0, temperature, *seed)
+        });
+
+        let hops = chains.iter().map(Vec::len).max().unwrap_or(0);
+        let mut best = &chains[0][0];
+        for i in 0..hops {
+            for chain in chains.iter() {
+                // chains that stopped early keep their last minimum
+                let minimum = chain.get(i).unwrap_or(&chain[chain.len() - 1]);
+                if minimum.1 < best.1 {
+                    best = minimum;
+                }
+            }
+            progress.iteration(|| best.1);
+        }
+
+        progress.finish(self.function, best.0.clone(), self.termination())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::functions::sine::Sine;
+    use nalgebra::{Const, Vector4};
+
+    /// Create an error function for a sine with no noise, where the default initial
+    /// parameters lead local minimizers to a local minimum.
+    fn sine_error_function() -> ErrorFunction<Const<4>, Sine> {
+        let parameters = Vector4::new(3.0, 0.5, 2.0, 1.0);
+        let x_ray: Vec<f64> = (0..200).map(|i| i as f64 / 20.0).collect();
+        let y_ray: Vec<f64> = x_ray.iter().map(|x| Sine.f(*x, &parameters)).collect();
+        ErrorFunction::new(Sine, &x_ray, &y_ray)
+    }
+
+    fn search(global: GlobalSearch) -> MinimizerOut<Const<4>> {
+        let ranges = ["0:5", "-3.2:3.2", "0:4", "-2:2"].map(|s| Range::parse(s).unwrap());
+        let mut config = MinimizerConfig::default();
+        config.global.seed = Some(1234);
+        config.global.threads = 3;
+        config.global.iterations = 20;
+
+        global.minimize(
+            &sine_error_function(),
+            &Vector4::from_element(1.0),
+            &ranges,
+            Minimizers::Combined,
+            RefinementStage::Newton,
+            &config,
+        )
+    }
+
+    #[test]
+    fn test_local_minimum() {
+        let (x, _) = search(GlobalSearch::None);
+        assert!(sine_error_function().f(&x) > 1e-3);
+    }
+
+    #[test]
+    fn test_global_searches() {
+        for global in [
+            GlobalSearch::Multistart,
+            GlobalSearch::DifferentialEvolution,
+            GlobalSearch::BasinHopping,
+        ] {
+            let (x, report) = search(global);
+            assert!(report.termination.is_success(), "{:?}", global);
+            assert_eq!(report.stages[0].name, global.name());
+            let error = sine_error_function().f(&x);
+            assert!(error < 1e-20, "{:?} got error {}", global, error);
+        }
+    }
+
+    #[test]
+    fn test_seed() {
+        let (x1, report1) = search(GlobalSearch::DifferentialEvolution);
+        let (x2, report2) = search(GlobalSearch::DifferentialEvolution);
+        assert_eq!(x1, x2);
+        assert_eq!(report1.evaluations, report2.evaluations);
+    }
+}
@@ -0,0 +1,94 @@
+use log::info;
+use nalgebra::{DefaultAllocator, Dim, OMatrix, OVector};
+
+use super::{Minimizer, MinimizerConfig, MinimizerOut, Progress, Termination};
+use crate::error_functions::ErrorFunction;
+use crate::functions::{Model, ParameterAllocator};
+
+/// The Levenberg-Marquardt algorithm, which interpolates between Gauss-Newton
+/// and gradient descent using an adaptive damping factor.
+pub struct LevenbergMarquardt;
+
+impl Minimizer for LevenbergMarquardt {
+    const NAME: &'static str = "levenberg_marquardt";
+
+    fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let mut progress = Progress::start(function, config);
+
+        let mut x = x0.clone();
+        let mut f_val = function.f(&x);
+        let mut damping = 1e-3;
+        let mut nu = 2.0;
+
+        for _ in 0..config.max_iterations {
+            if let Some(termination) = config.budget_exhausted(function) {
+                return progress.finish(function, x, termination);
+            }
+
+            // the gradient comes from the same pass over the data as the hessian
+            let jtj = function.gauss_newton_hess(&x);
+            let g = function.grad(&x);
+            if config.gradient_converged(&g) {
+                info!("Levenberg-Marquardt converged!");
+                return progress.finish(function, x, Termination::GradientConverged);
+            }
+
+            // Marquardt's scaling makes the damping independent of the parameter scales
+            let max_diagonal = jtj.diagonal().max().max(1.0);
+            let scale = jtj.diagonal().map(|v| v.max(f64::EPSILON * max_diagonal));
+
+            // increase damping until the step decreases the function value
+            loop {
+                let damped = &jtj + OMatrix::from_diagonal(&(damping * &scale));
+                if let Some(cholesky) = damped.cholesky() {
+                    let step = -cholesky.solve(&g);
+                    if step.norm() <= f64::EPSILON * (x.norm() + f64::EPSILON) {
+                        info!("Levenberg-Marquardt got a step size of zero");
+                        return progress.finish(function, x, Termination::Stalled);
+                    }
+
+                    let next_x = &x + &step;
+                    let next_f = function.f(&next_x);
+
+                    // ratio between actual and predicted reduction of the error
+                    let predicted = -(g.dot(&step) + 0.5 * step.dot(&(&jtj * &step)));
+                    let rho = (f_val - next_f) / predicted;
+                    if predicted > 0.0 && rho > 0.0 {
+                        x = next_x;
+                        progress.iteration(|| next_f);
+                        if config.function_converged(f_val, next_f) {
+                            info!("Levenberg-Marquardt converged!");
+                            return progress.finish(function, x, Termination::FunctionConverged);
+                        }
+                        if config.step_converged(&step, &x) {
+                            info!("Levenberg-Marquardt converged!");
+                            return progress.finish(function, x, Termination::StepConverged);
+                        }
+                        f_val = next_f;
+                        damping *= (1.0 / 3.0_f64).max(1.0 - (2.0 * rho - 1.0).powi(3));
+                        nu = 2.0;
+                        break;
+                    }
+                }
+
+                damping *= nu;
+                nu *= 2.0;
+
+                if !damping.is_finite() {
+                    info!("Levenberg-Marquardt got an infinite damping factor");
+                    return progress.finish(function, x, Termination::Stalled);
+                }
+            }
+        }
+
+        progress.finish(function, x, Termination::MaxIterations)
+    }
+}
@@ -0,0 +1,137 @@
+use nalgebra::{DefaultAllocator, Dim, OVector};
+
+use crate::error_functions::ErrorFunction;
+use crate::functions::{Model, ParameterAllocator};
+
+/// Fraction of the decrease predicted by the gradient a step must achieve.
+const C1: f64 = 1e-4;
+/// Fraction the directional derivative must be reduced by.
+const C2: f64 = 0.9;
+const MAX_EXPANSIONS: usize = 30;
+const MAX_ZOOMS: usize = 30;
+
+/// A point found by the line search, along with its function value and gradient.
+pub struct LinePoint<D: Dim>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    pub x: OVector<f64, D>,
+    pub f: f64,
+    pub g: OVector<f64, D>,
+}
+
+/// The function restricted to the line `x + alpha * direction`.
+struct Line<'a, D: Dim, M: Model<D>>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    function: &'a ErrorFunction<D, M>,
+    x: &'a OVector<f64, D>,
+    direction: &'a OVector<f64, D>,
+}
+
+impl<D: Dim, M: Model<D>> Line<'_, D, M>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    fn point(&self, alpha: f64) -> LinePoint<D> {
+        let x = self.x + alpha * self.direction;
+        LinePoint {
+            f: self.function.f(&x),
+            g: self.function.grad(&x),
+            x,
+        }
+    }
+
+    fn slope(&self, point: &LinePoint<D>) -> f64 {
+        point.g.dot(self.direction)
+    }
+}
+
+/// Finds a step along `direction` that satisfies the strong Wolfe conditions, that is,
+/// the step sufficiently decreases the function value and the directional derivative.
+/// Returns `None` if `direction` is not a descent direction or no step is found.
+pub fn wolfe_line_search<D: Dim, M: Model<D>>(
+    function: &ErrorFunction<D, M>,
+    x: &OVector<f64, D>,
+    f_val: f64,
+    g: &OVector<f64, D>,
+    direction: &OVector<f64, D>,
+) -> Option<LinePoint<D>>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let slope_0 = g.dot(direction);
+    if slope_0 >= 0.0 || !slope_0.is_finite() {
+        return None;
+    }
+
+    let line = Line {
+        function,
+        x,
+        direction,
+    };
+    let sufficient_decrease = |alpha: f64, f: f64| f <= f_val + C1 * alpha * slope_0;
+    let curvature_condition = |slope: f64| slope.abs() <= -C2 * slope_0;
+
+    // expand the step until it brackets a point satisfying the conditions
+    let (mut alpha_prev, mut f_prev, mut slope_prev) = (0.0, f_val, slope_0);
+    let mut alpha = 1.0;
+    let (mut lo, mut hi) = 'bracket: {
+        for i in 0..MAX_EXPANSIONS {
+            let point = line.point(alpha);
+            if !sufficient_decrease(alpha, point.f) || (i > 0 && point.f >= f_prev) {
+                break 'bracket ((alpha_prev, f_prev, slope_prev), (alpha, point.f));
+            }
+
+            let slope = line.slope(&point);
+            if curvature_condition(slope) {
+                return Some(point);
+            }
+            if slope >= 0.0 {
+                break 'bracket ((alpha, point.f, slope), (alpha_prev, f_prev));
+            }
+
+            (alpha_prev, f_prev, slope_prev) = (alpha, point.f, slope);
+            alpha *= 2.0;
+        }
+        return None;
+    };
+
+    // shrink the bracket [lo, hi], where lo is the best point satisfying sufficient decrease
+    for _ in 0..MAX_ZOOMS {
+        let (alpha_lo, f_lo, slope_lo) = lo;
+        let (alpha_hi, f_hi) = hi;
+        let width = alpha_hi - alpha_lo;
+
+        // minimum of the quadratic interpolating f_lo, slope_lo and f_hi
+        let curvature = f_hi - f_lo - slope_lo * width;
+        let mut alpha = alpha_lo - slope_lo * width.powi(2) / (2.0 * curvature);
+        let (min_alpha, max_alpha) = (
+            alpha_lo.min(alpha_hi) + 0.1 * width.abs(),
+            alpha_lo.max(alpha_hi) - 0.1 * width.abs(),
+        );
+        if !alpha.is_finite() || alpha < min_alpha || alpha > max_alpha {
+            alpha = alpha_lo + 0.5 * width;
+        }
+
+        let point = line.point(alpha);
+        if !sufficient_decrease(alpha, point.f) || point.f >= f_lo {
+            hi = (alpha, point.f);
+        } else {
+            let slope = line.slope(&point);
+            if curvature_condition(slope) {
+                return Some(point);
+            }
+            if slope * width >= 0.0 {
+                hi = (alpha_lo, f_lo);
+            }
+            lo = (alpha, point.f, slope);
+        }
+    }
+
+    // the curvature condition can fail when the bracket is tiny, so we settle
+    // for a point that decreases the function value
+    let (alpha_lo, _, _) = lo;
+    (alpha_lo > 0.0).then(|| line.point(alpha_lo))
+}
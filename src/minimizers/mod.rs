@@ -0,0 +1,627 @@
+mod backtrack;
+mod bfgs;
+mod combined;
+mod config;
+mod global;
+mod levenberg_marquardt;
+mod line_search;
+mod nelder_mead;
+mod newton;
+mod report;
+mod trust_region;
+
+use nalgebra::{DMatrix, DefaultAllocator, Dim, OMatrix, OVector, U1};
+use strum::VariantNames;
+use strum_macros::{EnumIter, EnumString, VariantNames};
+
+use std::str::FromStr;
+
+use crate::error_functions::ErrorFunction;
+use crate::functions::{Model, ParameterAllocator};
+use crate::utils::prettify_list;
+
+pub use backtrack::Backtrack;
+pub use bfgs::{Bfgs, Lbfgs};
+pub use combined::{Combined, RefinementStage};
+pub use config::{MinimizerConfig, parse_duration};
+pub use global::GlobalSearch;
+pub use levenberg_marquardt::LevenbergMarquardt;
+pub use nelder_mead::NelderMead;
+pub use newton::Newton;
+pub use report::{MinimizerReport, Termination};
+pub use trust_region::TrustRegion;
+
+use report::Progress;
+
+pub type MinimizerOut<D> = (OVector<f64, D>, MinimizerReport);
+
+/// Eigenvalues of the hessian that are negative by less than this fraction of the
+/// largest eigenvalue magnitude are considered to be zero.
+const CURVATURE_TOLERANCE: f64 = 1e-8;
+
+/// Computes the eigenvalues and eigenvectors of a symmetric matrix, where the
+/// eigenvectors are the columns of the returned matrix.
+fn symmetric_eigen<D: Dim>(matrix: &OMatrix<f64, D, D>) -> (OVector<f64, D>, OMatrix<f64, D, D>)
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    // nalgebra can't compute eigenvalues of const generic matrices, so we use a DMatrix
+    let (dimension, _) = matrix.shape_generic();
+    let n = dimension.value();
+    let eigen = DMatrix::from_column_slice(n, n, matrix.as_slice()).symmetric_eigen();
+    (
+        OVector::from_column_slice_generic(dimension, U1, eigen.eigenvalues.as_slice()),
+        OMatrix::from_column_slice_generic(dimension, dimension, eigen.eigenvectors.as_slice()),
+    )
+}
+
+pub trait Minimizer {
+    const NAME: &'static str;
+    /// Does this minimizer use the hessian of the function?
+    const NEEDS_HESSIAN: bool = false;
+
+    /// Find the parameters that minimize `function`, starting from `x0`.
+    fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>;
+}
+
+/// The minimization algorithms that can be selected by the user.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, EnumString, EnumIter, VariantNames)]
+#[strum(serialize_all = "snake_case")]
+pub enum Minimizers {
+    #[default]
+    Combined,
+    Newton,
+    Backtrack,
+    LevenbergMarquardt,
+    TrustRegion,
+    Bfgs,
+    Lbfgs,
+    NelderMead,
+}
+
+impl Minimizers {
+    /// Tries to create a minimizer from a minimizer name, returns a string with
+    /// a descriptive error message if the minimizer name is invalid.
+    pub fn descriptive_from_str(s: &str) -> Result<Minimizers, String> {
+        Self::from_str(&s.to_lowercase()).map_err(|_| {
+            format!(
+                "Got malformed minimizer name '{}'. Legal \
+                minimizer names are {}.",
+                s,
+                prettify_list(Self::VARIANTS)
+            )
+        })
+    }
+
+    /// Does the selected algorithm use the hessian of the function?
+    pub fn needs_hessian(&self, refinement: RefinementStage) -> bool {
+        match self {
+            Self::Combined => refinement.needs_hessian(),
+            Self::Newton => Newton::NEEDS_HESSIAN,
+            Self::Backtrack => Backtrack::NEEDS_HESSIAN,
+            Self::LevenbergMarquardt => LevenbergMarquardt::NEEDS_HESSIAN,
+            Self::TrustRegion => TrustRegion::NEEDS_HESSIAN,
+            Self::Bfgs => Bfgs::NEEDS_HESSIAN,
+            Self::Lbfgs => Lbfgs::NEEDS_HESSIAN,
+            Self::NelderMead => NelderMead::NEEDS_HESSIAN,
+        }
+    }
+
+    /// Minimize `function` using the selected algorithm. The refinement stage is
+    /// only used by the combined minimizer.
+    pub fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+        refinement: RefinementStage,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let config = &config.start_clock();
+        match self {
+            Self::Combined => Combined { refinement }.minimize(function, x0, config),
+            Self::Newton => Newton.minimize(function, x0, config),
+            Self::Backtrack => Backtrack.minimize(function, x0, config),
+            Self::LevenbergMarquardt => LevenbergMarquardt.minimize(function, x0, config),
+            Self::TrustRegion => TrustRegion.minimize(function, x0, config),
+            Self::Bfgs => Bfgs.minimize(function, x0, config),
+            Self::Lbfgs => Lbfgs.minimize(function, x0, config),
+            Self::NelderMead => NelderMead.minimize(function, x0, config),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::autodiff::Real;
+    use crate::error_functions::HessianMode;
+    use crate::functions::{Differentiated, DynDifferentiated, line::Line, sine::Sine};
+    use crate::parameters::{Bounds, ParameterSpecs, ParameterStatus, Tie};
+    use core::f64::consts::{E, PI};
+    use nalgebra::{Const, DVector, Vector2, Vector4};
+    use strum::IntoEnumIterator;
+
+    #[test]
+    fn test_newton_descent() {
+        test_minimizer(Mode::Newton);
+    }
+
+    #[test]
+    fn test_backtrack_descent() {
+        test_minimizer(Mode::Backtrack);
+    }
+
+    #[test]
+    fn test_levenberg_marquardt() {
+        test_minimizer(Mode::LevenbergMarquardt);
+    }
+
+    #[test]
+    fn test_trust_region() {
+        test_minimizer(Mode::TrustRegion);
+    }
+
+    #[test]
+    fn test_bfgs() {
+        test_minimizer(Mode::Bfgs);
+        test_minimizer(Mode::Lbfgs);
+    }
+
+    #[test]
+    fn test_nelder_mead() {
+        test_minimizer(Mode::NelderMead);
+    }
+
+    #[test]
+    fn test_combined_descent() {
+        test_minimizer(Mode::Combined(RefinementStage::Newton));
+        test_minimizer(Mode::Combined(RefinementStage::LevenbergMarquardt));
+        test_minimizer(Mode::Combined(RefinementStage::TrustRegion));
+        test_minimizer(Mode::Combined(RefinementStage::Bfgs));
+        test_minimizer(Mode::Combined(RefinementStage::Lbfgs));
+    }
+
+    #[test]
+    fn test_without_hessian() {
+        /// A line that does not implement the hessian.
+        struct GradientLine;
+
+        impl Differentiated<2> for GradientLine {
+            const PARAMETER_NAMES: [&'static str; 2] = Line::PARAMETER_NAMES;
+            const NAME: &'static str = "gradient_line";
+            const HAS_HESSIAN: bool = false;
+
+            fn generic_f<T: Real>(x: T, params: &Vector2<T>) -> T {
+                Line::generic_f(x, params)
+            }
+
+            fn grad(x: f64, params: &Vector2<f64>) -> Vector2<f64> {
+                Line.grad(x, params)
+            }
+        }
+
+        let (parameters, _) = line_error_function();
+        let x_ray: Vec<f64> = (0..100).map(|i| i as f64 / 99.0).collect();
+        let y_ray: Vec<f64> = x_ray.iter().map(|x| Line.f(*x, &parameters)).collect();
+        let error_function = ErrorFunction::new(GradientLine, &x_ray, &y_ray);
+        let p0 = Vector2::from_element(1.0);
+        let config = MinimizerConfig::default();
+
+        for minimizer in Minimizers::iter() {
+            for refinement in RefinementStage::iter() {
+                let (optimal_parameters, report) =
+                    minimizer.minimize(&error_function, &p0, &config, refinement);
+                if minimizer.needs_hessian(refinement) {
+                    assert!(matches!(report.termination, Termination::Error(_)));
+                } else {
+                    assert!(report.termination.is_success(), "{:?}", minimizer);
+                    assert!((optimal_parameters - parameters).abs().max() < 1e-6);
+                }
+            }
+        }
+
+        // with the Gauss-Newton hessian, every minimizer can be used
+        let error_function = error_function.with_hessian_mode(HessianMode::GaussNewton);
+        assert!(error_function.has_hessian() && !error_function.has_exact_hessian());
+        assert_eq!(
+            error_function.hess(&p0),
+            error_function.gauss_newton_hess(&p0)
+        );
+        for minimizer in Minimizers::iter() {
+            for refinement in RefinementStage::iter() {
+                let (optimal_parameters, report) =
+                    minimizer.minimize(&error_function, &p0, &config, refinement);
+                assert!(
+                    report.termination.is_success(),
+                    "{:?} with {:?}: {}",
+                    minimizer,
+                    refinement,
+                    report.termination
+                );
+                assert!((optimal_parameters - parameters).abs().max() < 1e-6);
+            }
+        }
+    }
+
+    #[test]
+    fn test_dynamic_model() {
+        /// A line whose parameters are only known at runtime.
+        struct DynLine;
+
+        impl DynDifferentiated for DynLine {
+            fn name(&self) -> &str {
+                "dyn_line"
+            }
+
+            fn parameter_count(&self) -> usize {
+                2
+            }
+
+            fn f(&self, x: f64, params: &DVector<f64>) -> f64 {
+                Line.f(x, &Vector2::from_column_slice(params.as_slice()))
+            }
+
+            fn grad(&self, x: f64, params: &DVector<f64>) -> DVector<f64> {
+                let params = Vector2::from_column_slice(params.as_slice());
+                DVector::from_column_slice(Line.grad(x, &params).as_slice())
+            }
+
+            fn hess(&self, _x: f64, params: &DVector<f64>) -> DMatrix<f64> {
+                DMatrix::zeros(params.len(), params.len())
+            }
+        }
+
+        // the dynamic model takes the same steps as the static one
+        let (parameters, static_function) = line_error_function();
+        let x_ray: Vec<f64> = (0..100).map(|i| i as f64 / 99.0).collect();
+        let y_ray: Vec<f64> = x_ray.iter().map(|x| Line.f(*x, &parameters)).collect();
+        let dynamic_function = ErrorFunction::new(DynLine, &x_ray, &y_ray);
+        let p0 = Vector2::from_element(1.0);
+        let config = MinimizerConfig::default();
+        for minimizer in Minimizers::iter() {
+            let (static_parameters, _) =
+                minimizer.minimize(&static_function, &p0, &config, RefinementStage::Newton);
+            let (dynamic_parameters, report) = minimizer.minimize(
+                &dynamic_function,
+                &DVector::from_column_slice(p0.as_slice()),
+                &config,
+                RefinementStage::Newton,
+            );
+            assert!(report.termination.is_success(), "{:?}", minimizer);
+            assert!(
+                (dynamic_parameters - DVector::from_column_slice(static_parameters.as_slice()))
+                    .abs()
+                    .max()
+                    < 1e-10,
+                "{:?}",
+                minimizer
+            );
+        }
+    }
+
+    #[test]
+    fn test_saddle_point() {
+        // fitting a cosine with zero amplitude to a sine is a saddle point, as the
+        // gradient vanishes, but a phase shift and amplitude change decreases the error
+        const N: usize = 100;
+        let x_ray: Vec<f64> = (0..N).map(|i| 2.0 * PI * i as f64 / N as f64).collect();
+        let y_ray: Vec<f64> = x_ray.iter().map(|x| x.sin()).collect();
+        let error_function = ErrorFunction::new(Sine, &x_ray, &y_ray);
+        let p0 = Vector4::new(1.0, 0.5 * PI, 0.0, 0.0);
+        let config = MinimizerConfig::default();
+
+        // backtracking stops where the gradient vanishes, which the top-level
+        // minimizer then classifies
+        let (x, report) = Backtrack.minimize(&error_function, &p0, &config);
+        assert!(report.termination.is_success(), "{}", report.termination);
+        let report = report.classify(&error_function, &x);
+        assert_eq!(report.termination, Termination::SaddlePoint);
+        let (_, report) = GlobalSearch::None.minimize(
+            &error_function,
+            &p0,
+            &[],
+            Minimizers::Backtrack,
+            RefinementStage::Newton,
+            &config,
+        );
+        assert_eq!(report.termination, Termination::SaddlePoint);
+
+        let (optimal_parameters, report) = Newton.minimize(&error_function, &p0, &config);
+        assert!(report.termination.is_success(), "{}", report.termination);
+        assert!(error_function.f(&optimal_parameters) < 1e-20);
+
+        // the gradient vanishes at the start, so move away from the saddle point
+        let p0 = p0 + Vector4::new(0.0, 0.1, 0.1, 0.0);
+        let (optimal_parameters, report) = TrustRegion.minimize(&error_function, &p0, &config);
+        assert!(report.termination.is_success(), "{}", report.termination);
+        assert!(error_function.f(&optimal_parameters) < 1e-20);
+    }
+
+    #[test]
+    fn test_nelder_mead_fallback() {
+        /// A line where the gradient is not finite.
+        struct KinkedLine;
+
+        impl Differentiated<2> for KinkedLine {
+            const PARAMETER_NAMES: [&'static str; 2] = Line::PARAMETER_NAMES;
+            const NAME: &'static str = "kinked_line";
+            const HAS_HESSIAN: bool = false;
+
+            fn generic_f<T: Real>(x: T, params: &Vector2<T>) -> T {
+                Line::generic_f(x, params)
+            }
+
+            fn grad(_x: f64, _params: &Vector2<f64>) -> Vector2<f64> {
+                Vector2::from_element(f64::NAN)
+            }
+        }
+
+        let (parameters, _) = line_error_function();
+        let x_ray: Vec<f64> = (0..100).map(|i| i as f64 / 99.0).collect();
+        let y_ray: Vec<f64> = x_ray.iter().map(|x| Line.f(*x, &parameters)).collect();
+        let error_function = ErrorFunction::new(KinkedLine, &x_ray, &y_ray);
+        let p0 = Vector2::from_element(1.0);
+
+        let combined = Combined {
+            refinement: RefinementStage::Bfgs,
+        };
+        let (optimal_parameters, report) =
+            combined.minimize(&error_function, &p0, &MinimizerConfig::default());
+        assert!(report.termination.is_success(), "{}", report.termination);
+        assert_eq!(report.stages.last().unwrap().name, NelderMead::NAME);
+        assert!((optimal_parameters - parameters).abs().max() < 1e-8);
+    }
+
+    #[test]
+    fn test_report() {
+        let (_, error_function) = line_error_function();
+        let p0 = Vector2::from_element(1.0);
+        let mut config = MinimizerConfig::default();
+        config.record_history = true;
+
+        let combined = Combined {
+            refinement: RefinementStage::Newton,
+        };
+        let (_, report) = combined.minimize(&error_function, &p0, &config);
+
+        assert!(report.termination.is_success());
+        assert!(!report.stages.is_empty());
+        assert_eq!(report.stages[0].name, Backtrack::NAME);
+        assert_eq!(
+            report.iterations,
+            report.stages.iter().map(|s| s.iterations).sum::<usize>()
+        );
+        assert_eq!(report.evaluations, error_function.evaluation_counts());
+
+        let history = report.history.unwrap();
+        assert_eq!(history.len(), report.iterations);
+        assert!(history.windows(2).all(|w| w[1] <= w[0]));
+    }
+
+    #[test]
+    fn test_bounds() {
+        let (parameters, error_function) = line_error_function();
+        let bounds = [":2", "0:5"].map(|s| Bounds::parse(s).unwrap());
+        let error_function = error_function.with_bounds(&bounds);
+
+        // the derivatives of the transformed error function
+        let q = error_function.internal(&Vector2::new(1.0, 1.0));
+        let h = 1e-6;
+        let grad = error_function.grad(&q);
+        let hess = error_function.hess(&q);
+        for i in 0..2 {
+            let step = Vector2::from_fn(|j, _| if i == j { h } else { 0.0 });
+            let numeric_grad =
+                (error_function.f(&(q + step)) - error_function.f(&(q - step))) / (2.0 * h);
+            let numeric_hess =
+                (error_function.grad(&(q + step)) - error_function.grad(&(q - step))) / (2.0 * h);
+            assert!((grad[i] - numeric_grad).abs() < 1e-6);
+            assert!((hess.column(i) - numeric_hess).abs().max() < 1e-6);
+        }
+
+        // with a ≤ 2, the best intercept makes up for the slope at the mean x
+        let expected = Vector2::new(2.0, parameters.y + 0.5 * (parameters.x - 2.0));
+        let mut specs = ParameterSpecs::new(2);
+        specs.bounds = bounds.to_vec();
+        for refinement in RefinementStage::iter() {
+            let p0 = error_function.internal(&Vector2::new(1.0, 1.0));
+            let (q, report) =
+                Combined { refinement }.minimize(&error_function, &p0, &MinimizerConfig::default());
+            assert!(
+                report.termination.is_success(),
+                "{:?}: {}",
+                refinement,
+                report.termination
+            );
+
+            let optimal_parameters = error_function.external(&q);
+            assert!(
+                (optimal_parameters - expected).abs().max() < 1e-6,
+                "{:?} got {:?}",
+                refinement,
+                optimal_parameters
+            );
+            assert_eq!(
+                specs.statuses(optimal_parameters.as_slice()),
+                vec![ParameterStatus::AtBound, ParameterStatus::Free]
+            );
+        }
+    }
+
+    #[test]
+    fn test_fixed() {
+        let (parameters, error_function) = line_error_function();
+        let error_function = error_function.with_fixed(&[None, Some(3.0)]);
+
+        // with b = 3, the best slope makes up for the intercept in the least squares sense
+        let x_ray: Vec<f64> = (0..100).map(|i| i as f64 / 99.0).collect();
+        let ratio = x_ray.iter().sum::<f64>() / x_ray.iter().map(|x| x * x).sum::<f64>();
+        let expected = parameters.x + (parameters.y - 3.0) * ratio;
+
+        let config = MinimizerConfig::default().with_max_iterations(1000);
+        for minimizer in Minimizers::iter() {
+            let p0 = Vector2::from_element(1.0);
+            let (q, report) =
+                minimizer.minimize(&error_function, &p0, &config, RefinementStage::Newton);
+            assert!(
+                report.termination.is_success(),
+                "{:?}: {}",
+                minimizer,
+                report.termination
+            );
+
+            let optimal_parameters = error_function.external(&q);
+            assert_eq!(optimal_parameters.y, 3.0);
+            assert!(
+                (optimal_parameters.x - expected).abs() < 1e-6,
+                "{:?} got {}",
+                minimizer,
+                optimal_parameters.x
+            );
+        }
+    }
+
+    #[test]
+    fn test_ties() {
+        let (parameters, error_function) = line_error_function();
+        let tie = Tie {
+            to: 0,
+            factor: 2.0,
+            offset: 1.0,
+        };
+        let error_function = error_function
+            .with_bounds(&[Bounds::parse("0:5").unwrap(), Bounds::default()])
+            .with_ties(&[None, Some(tie)]);
+
+        // the derivatives go through the tie and the bounds of the parameter it is tied to
+        let q = error_function.internal(&Vector2::new(1.0, 1.0));
+        let h = 1e-6;
+        let numeric_grad = (error_function.f(&(q + Vector2::x() * h))
+            - error_function.f(&(q - Vector2::x() * h)))
+            / (2.0 * h);
+        let numeric_hess = (error_function.grad(&(q + Vector2::x() * h))
+            - error_function.grad(&(q - Vector2::x() * h)))
+            / (2.0 * h);
+        assert!((error_function.grad(&q).x - numeric_grad).abs() < 1e-6);
+        assert!((error_function.hess(&q)[(0, 0)] - numeric_hess.x).abs() < 1e-6);
+
+        // with b = 2a + 1, the line is a(x + 2) + 1
+        let x_ray: Vec<f64> = (0..100).map(|i| i as f64 / 99.0).collect();
+        let expected = x_ray
+            .iter()
+            .map(|x| (Line.f(*x, &parameters) - 1.0) * (x + 2.0))
+            .sum::<f64>()
+            / x_ray.iter().map(|x| (x + 2.0).powi(2)).sum::<f64>();
+
+        let config = MinimizerConfig::default().with_max_iterations(1000);
+        for minimizer in Minimizers::iter() {
+            let p0 = error_function.internal(&Vector2::new(1.0, 1.0));
+            let (q, report) =
+                minimizer.minimize(&error_function, &p0, &config, RefinementStage::Newton);
+            assert!(
+                report.termination.is_success(),
+                "{:?}: {}",
+                minimizer,
+                report.termination
+            );
+
+            let optimal_parameters = error_function.external(&q);
+            assert!(
+                (optimal_parameters.x - expected).abs() < 1e-6,
+                "{:?} got {}",
+                minimizer,
+                optimal_parameters.x
+            );
+            assert_eq!(
+                optimal_parameters.y,
+                tie.value(optimal_parameters.as_slice())
+            );
+        }
+    }
+
+    /// Create an error function for a line with no noise, as we then should be
+    /// able to find the parameters exactly.
+    fn line_error_function() -> (Vector2<f64>, ErrorFunction<Const<2>, Line>) {
+        let parameters = Vector2::new(E, PI);
+
+        const N: usize = 100;
+        let mut x_ray = Vec::with_capacity(N);
+        let mut y_ray = Vec::with_capacity(N);
+        for i in 0..N {
+            let x = i as f64 / ((N - 1) as f64);
+            x_ray.push(x);
+            y_ray.push(Line.f(x, &parameters));
+        }
+
+        (parameters, ErrorFunction::new(Line, &x_ray, &y_ray))
+    }
+
+    fn test_minimizer(mode: Mode) {
+        let (parameters, error_function) = line_error_function();
+        let p0 = Vector2::from_element(1.0);
+        let with_steps = |max_steps| MinimizerConfig::default().with_max_iterations(max_steps);
+        let (optimal_parameters, report) = match mode {
+            Mode::Backtrack => Backtrack.minimize(&error_function, &p0, &with_steps(1000)),
+            Mode::Newton => Newton.minimize(&error_function, &p0, &with_steps(10)),
+            Mode::LevenbergMarquardt => {
+                LevenbergMarquardt.minimize(&error_function, &p0, &with_steps(100))
+            }
+            Mode::TrustRegion => TrustRegion.minimize(&error_function, &p0, &with_steps(100)),
+            Mode::Bfgs => Bfgs.minimize(&error_function, &p0, &with_steps(100)),
+            Mode::Lbfgs => Lbfgs.minimize(&error_function, &p0, &with_steps(100)),
+            Mode::NelderMead => NelderMead.minimize(&error_function, &p0, &with_steps(1000)),
+            Mode::Combined(refinement) => {
+                Combined { refinement }.minimize(&error_function, &p0, &MinimizerConfig::default())
+            }
+        };
+
+        if !report.termination.is_success() {
+            panic!("{:?} got error: {}", mode, report.termination);
+        }
+
+        let threshold = match mode {
+            Mode::Backtrack => 1e-10,
+            Mode::Newton => 1e-14,
+            Mode::LevenbergMarquardt => 1e-10,
+            Mode::TrustRegion => 1e-10,
+            Mode::Bfgs | Mode::Lbfgs => 1e-10,
+            Mode::NelderMead => 1e-8,
+            Mode::Combined(RefinementStage::Newton) => 0.0,
+            Mode::Combined(RefinementStage::LevenbergMarquardt) => 1e-10,
+            Mode::Combined(RefinementStage::TrustRegion) => 1e-10,
+            Mode::Combined(RefinementStage::Bfgs | RefinementStage::Lbfgs) => 1e-10,
+        };
+
+        if (optimal_parameters - parameters).abs().max() > threshold {
+            panic!(
+                "{:?} got wrong parameters! {:?} > {}",
+                mode,
+                (optimal_parameters - parameters).abs(),
+                threshold,
+            );
+        }
+    }
+
+    #[derive(Debug)]
+    enum Mode {
+        Backtrack,
+        Newton,
+        LevenbergMarquardt,
+        TrustRegion,
+        Bfgs,
+        Lbfgs,
+        NelderMead,
+        Combined(RefinementStage),
+    }
+}
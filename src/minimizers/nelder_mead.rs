@@ -0,0 +1,124 @@
+use log::info;
+use nalgebra::{DefaultAllocator, Dim, OVector, U1};
+
+use super::{Minimizer, MinimizerConfig, MinimizerOut, Progress, Termination};
+use crate::error_functions::ErrorFunction;
+use crate::functions::{Model, ParameterAllocator};
+
+/// The Nelder-Mead simplex method, which only uses function values. This makes it
+/// useful for functions where the gradient is not defined or not finite.
+pub struct NelderMead;
+
+/// A vertex of the simplex, along with its function value.
+type Vertex<D> = (OVector<f64, D>, f64);
+
+/// Evaluates the function, treating NaN as infinitely bad.
+fn evaluate<D: Dim, M: Model<D>>(function: &ErrorFunction<D, M>, x: OVector<f64, D>) -> Vertex<D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let f_val = function.f(&x);
+    (x, if f_val.is_nan() { f64::INFINITY } else { f_val })
+}
+
+impl Minimizer for NelderMead {
+    const NAME: &'static str = "nelder_mead";
+
+    fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let mut progress = Progress::start(function, config);
+        let args = config.nelder_mead;
+
+        // the initial simplex moves one parameter at a time by a fraction of its value,
+        // except for fixed and tied parameters, as moving them would not change the function
+        let n = x0.len();
+        let mut simplex: Vec<Vertex<D>> = Vec::with_capacity(n + 1);
+        simplex.push(evaluate(function, x0.clone()));
+        for i in 0..n {
+            let mut x = x0.clone();
+            if function.is_constrained(i) {
+                simplex.push(simplex[0].clone());
+                continue;
+            }
+            x[i] += if x[i] == 0.0 {
+                args.initial_size
+            } else {
+                args.initial_size * x[i]
+            };
+            simplex.push(evaluate(function, x));
+        }
+
+        for _ in 0..config.max_iterations {
+            simplex.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+            let (best, f_best) = simplex[0].clone();
+            let (worst, f_worst) = simplex[n].clone();
+            let f_second_worst = simplex[n - 1].1;
+
+            if let Some(termination) = config.budget_exhausted(function) {
+                return progress.finish(function, best, termination);
+            }
+
+            let size = simplex
+                .iter()
+                .map(|(x, _)| (x - &best).norm())
+                .fold(0.0, f64::max);
+            if size <= args.x_tolerance * (best.norm() + args.x_tolerance) {
+                info!("Nelder-Mead converged!");
+                return progress.finish(function, best, Termination::SimplexConverged);
+            }
+            if f_worst - f_best <= args.f_tolerance * f_best.abs() {
+                info!("Nelder-Mead converged!");
+                return progress.finish(function, best, Termination::FunctionConverged);
+            }
+
+            let zeros = OVector::zeros_generic(x0.shape_generic().0, U1);
+            let centroid = simplex[..n].iter().fold(zeros, |sum, (x, _)| sum + x) / n as f64;
+            let along = |t: f64| &centroid + t * (&worst - &centroid);
+
+            let reflected = evaluate(function, along(-1.0));
+            let replacement = if reflected.1 < f_best {
+                let expanded = evaluate(function, along(-2.0));
+                Some(if expanded.1 < reflected.1 {
+                    expanded
+                } else {
+                    reflected
+                })
+            } else if reflected.1 < f_second_worst {
+                Some(reflected)
+            } else if reflected.1 < f_worst {
+                let contracted = evaluate(function, along(-0.5));
+                (contracted.1 <= reflected.1).then_some(contracted)
+            } else {
+                let contracted = evaluate(function, along(0.5));
+                (contracted.1 < f_worst).then_some(contracted)
+            };
+
+            match replacement {
+                Some(vertex) => simplex[n] = vertex,
+                None => {
+                    // shrink every vertex towards the best one
+                    for vertex in simplex[1..].iter_mut() {
+                        *vertex = evaluate(function, &best + 0.5 * (&vertex.0 - &best));
+                    }
+                }
+            }
+
+            let f_best = simplex
+                .iter()
+                .map(|(_, f)| *f)
+                .fold(f64::INFINITY, f64::min);
+            progress.iteration(|| f_best);
+        }
+
+        simplex.sort_by(|(_, a), (_, b)| a.total_cmp(b));
+        let (best, _) = simplex.swap_remove(0);
+        progress.finish(function, best, Termination::MaxIterations)
+    }
+}
@@ -0,0 +1,159 @@
+use log::info;
+use nalgebra::{DefaultAllocator, Dim, OMatrix, OVector};
+
+use super::{
+    CURVATURE_TOLERANCE, Minimizer, MinimizerConfig, MinimizerOut, Progress, Termination,
+    symmetric_eigen,
+};
+use crate::error_functions::{ErrorFunction, Evaluation};
+use crate::functions::{Model, ParameterAllocator};
+
+/// Newton's method, where the eigenvalues of the hessian are shifted to be positive so
+/// that every step points downhill. Steps are damped so they decrease the function value.
+pub struct Newton;
+
+/// Inverts the hessian after replacing its eigenvalues with their absolute values,
+/// where eigenvalues that are almost zero are increased to a small positive value.
+fn modified_inverse<D: Dim>(
+    eigenvalues: &OVector<f64, D>,
+    eigenvectors: &OMatrix<f64, D, D>,
+) -> OMatrix<f64, D, D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let max_eigenvalue = eigenvalues.amax();
+    let floor = if max_eigenvalue > 0.0 {
+        CURVATURE_TOLERANCE * max_eigenvalue
+    } else {
+        1.0
+    };
+    let inverse_eigenvalues = eigenvalues.map(|v| 1.0 / v.abs().max(floor));
+
+    eigenvectors * OMatrix::from_diagonal(&inverse_eigenvalues) * eigenvectors.transpose()
+}
+
+/// At a saddle point or a maximum, the gradient vanishes even though we are not at a
+/// minimum. To escape, we search along the direction of most negative curvature.
+fn escape_negative_curvature<D: Dim, M: Model<D>>(
+    function: &ErrorFunction<D, M>,
+    x: &OVector<f64, D>,
+    eigenvalues: &OVector<f64, D>,
+    eigenvectors: &OMatrix<f64, D, D>,
+) -> Option<(OVector<f64, D>, f64)>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let (i, min_eigenvalue) = eigenvalues.argmin();
+    if min_eigenvalue >= -CURVATURE_TOLERANCE * eigenvalues.amax() {
+        return None;
+    }
+    let direction = eigenvectors.column(i).into_owned();
+
+    let f_val = function.f(x);
+    let mut alpha = x.norm().max(1.0);
+    while alpha > f64::EPSILON {
+        for next_x in [x + alpha * &direction, x - alpha * &direction] {
+            let next_f = function.f(&next_x);
+            if next_f < f_val {
+                return Some((next_x, next_f));
+            }
+        }
+        alpha *= 0.5;
+    }
+
+    None
+}
+
+impl Minimizer for Newton {
+    const NAME: &'static str = "newton";
+    const NEEDS_HESSIAN: bool = true;
+
+    fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let mut progress = Progress::start(function, config);
+        if !function.has_hessian() {
+            return progress.finish(
+                function,
+                x0.clone(),
+                Termination::Error("Function has no hessian!"),
+            );
+        }
+
+        let mut prev_f = f64::INFINITY;
+        let mut x = x0.clone();
+        for _ in 0..config.max_iterations {
+            if let Some(termination) = config.budget_exhausted(function) {
+                return progress.finish(function, x, termination);
+            }
+
+            let Evaluation {
+                gradient: g,
+                hessian: hess,
+                ..
+            } = function.evaluate_all(&x);
+            if hess.iter().any(|v| !v.is_finite()) {
+                return progress.finish(function, x, Termination::Error("Hessian is not finite!"));
+            }
+            let (eigenvalues, eigenvectors) = symmetric_eigen(&hess);
+
+            if config.gradient_converged(&g) {
+                if let Some((next_x, next_f)) =
+                    escape_negative_curvature(function, &x, &eigenvalues, &eigenvectors)
+                {
+                    info!("Newton escaped a saddle point");
+                    x = next_x;
+                    prev_f = next_f;
+                    progress.iteration(|| next_f);
+                    continue;
+                }
+                info!("Newton converged!");
+                return progress.finish(function, x, Termination::GradientConverged);
+            }
+
+            // a positive definite hessian is inverted directly, as that is more accurate
+            let inv_hess = match hess.clone().cholesky().and(hess.try_inverse()) {
+                Some(inv_hess) => inv_hess,
+                None => modified_inverse(&eigenvalues, &eigenvectors),
+            };
+
+            // ensure step decreases function value by damping step if it does not
+            let mut damping = 1.0;
+            loop {
+                let step = -damping * &inv_hess * &g;
+                let next_x = &x + &step;
+
+                let next_f = function.f(&next_x);
+                if next_f < prev_f {
+                    x = next_x;
+                    progress.iteration(|| next_f);
+                    if config.function_converged(prev_f, next_f) {
+                        info!("Newton converged!");
+                        return progress.finish(function, x, Termination::FunctionConverged);
+                    }
+                    if config.step_converged(&step, &x) {
+                        info!("Newton converged!");
+                        return progress.finish(function, x, Termination::StepConverged);
+                    }
+                    prev_f = next_f;
+                    break;
+                } else {
+                    damping *= 0.5;
+                }
+
+                if damping < f64::EPSILON {
+                    info!("Newton got a damping factor of zero");
+                    return progress.finish(function, x, Termination::Stalled);
+                }
+            }
+        }
+
+        progress.finish(function, x, Termination::MaxIterations)
+    }
+}
@@ -0,0 +1,297 @@
+use nalgebra::{DefaultAllocator, Dim, OVector};
+
+use std::fmt::{self, Display};
+
+use super::{CURVATURE_TOLERANCE, MinimizerConfig, MinimizerOut, symmetric_eigen};
+use crate::error_functions::{ErrorFunction, EvaluationCounts};
+use crate::functions::{Model, ParameterAllocator};
+use crate::utils::g_format;
+
+/// The reason a minimizer stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    /// The norm of the gradient fell below the gradient tolerance.
+    GradientConverged,
+    /// The minimizer could not decrease the error any further, at a point where the
+    /// gradient vanishes within the precision of the error, see `classify_stall`.
+    PrecisionLimit,
+    /// A step changed the error by less than the function tolerance.
+    FunctionConverged,
+    /// A step changed the parameters by less than the step tolerance.
+    StepConverged,
+    /// The simplex of the Nelder-Mead minimizer shrunk below the simplex tolerance.
+    SimplexConverged,
+    /// The minimizer could not find a step that decreases the error, at a point
+    /// where the gradient does not vanish.
+    Stalled,
+    /// The minimizer converged, but the hessian has both positive and negative
+    /// eigenvalues, so the parameters are not at a minimum.
+    SaddlePoint,
+    /// The minimizer converged, but the hessian is negative definite, so the
+    /// parameters are at a maximum.
+    Maximum,
+    /// A global search has tried all of its candidates.
+    SearchCompleted,
+    /// The function is linear in its parameters, so the least squares problem was
+    /// solved exactly instead of minimized.
+    Solved,
+    MaxIterations,
+    MaxEvaluations,
+    TimeLimit,
+    Error(&'static str),
+}
+
+impl Termination {
+    /// Did the minimizer stop because it found a minimum?
+    pub fn is_success(&self) -> bool {
+        matches!(
+            self,
+            Self::GradientConverged
+                | Self::PrecisionLimit
+                | Self::FunctionConverged
+                | Self::StepConverged
+                | Self::SimplexConverged
+                | Self::Solved
+        )
+    }
+}
+
+impl Display for Termination {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::GradientConverged => f.write_str("gradient norm is below tolerance"),
+            Self::PrecisionLimit => {
+                f.write_str("gradient vanishes within the precision of the error")
+            }
+            Self::FunctionConverged => f.write_str("error change is below tolerance"),
+            Self::StepConverged => f.write_str("step size is below tolerance"),
+            Self::SimplexConverged => f.write_str("simplex size is below tolerance"),
+            Self::Stalled => f.write_str("could not decrease the error further"),
+            Self::SaddlePoint => f.write_str("stopped at a saddle point"),
+            Self::Maximum => f.write_str("stopped at a maximum"),
+            Self::SearchCompleted => f.write_str("tried every candidate"),
+            Self::Solved => f.write_str("solved exactly by linear least squares"),
+            Self::MaxIterations => f.write_str("reached the iteration limit"),
+            Self::MaxEvaluations => f.write_str("reached the evaluation limit"),
+            Self::TimeLimit => f.write_str("reached the time limit"),
+            Self::Error(error) => write!(f, "error: {}", error),
+        }
+    }
+}
+
+/// A summary of a minimizer that was run as a stage of another minimizer.
+#[derive(Debug, Clone)]
+pub struct Stage {
+    pub name: &'static str,
+    pub iterations: usize,
+    pub termination: Termination,
+}
+
+#[derive(Debug, Clone)]
+pub struct MinimizerReport {
+    pub termination: Termination,
+    pub iterations: usize,
+    pub evaluations: EvaluationCounts,
+    /// The norm of the gradient at the returned parameters.
+    pub gradient_norm: f64,
+    /// The stages run by a minimizer that combines other minimizers, in order.
+    pub stages: Vec<Stage>,
+    /// The error after each iteration, if `record_history` is enabled.
+    pub history: Option<Vec<f64>>,
+}
+
+impl MinimizerReport {
+    /// The report of a fit that was solved exactly at x, without a minimizer.
+    pub fn solved<D: Dim, M: Model<D>>(
+        function: &ErrorFunction<D, M>,
+        x: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> Self
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let progress = Progress::start(function, config);
+        progress.finish(function, x.clone(), Termination::Solved).1
+    }
+
+    /// If the minimizer thinks it has found a minimum at x, use the hessian to check
+    /// if it is a saddle point or maximum instead, if the function has one. This
+    /// evaluates the full hessian, so it is only done for the final result, and not
+    /// for every stage of a minimizer.
+    pub fn classify<D: Dim, M: Model<D>>(
+        mut self,
+        function: &ErrorFunction<D, M>,
+        x: &OVector<f64, D>,
+    ) -> Self
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        if self.termination.is_success() {
+            self.termination = classify_stationary_point(function, x).unwrap_or(self.termination);
+        }
+        self
+    }
+}
+
+impl Display for MinimizerReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "Stopped after {} iterations: {}.",
+            self.iterations, self.termination
+        )?;
+        writeln!(
+            f,
+            "Evaluated the error {} times, the gradient {} times and the hessian {} times.",
+            self.evaluations.function, self.evaluations.gradient, self.evaluations.hessian
+        )?;
+        write!(
+            f,
+            "Final gradient norm is {}.",
+            g_format(self.gradient_norm, 3)
+        )?;
+        for stage in &self.stages {
+            write!(
+                f,
+                "\n  {}: {} iterations, {}.",
+                stage.name, stage.iterations, stage.termination
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Uses the eigenvalues of the hessian to find out if a point where the gradient
+/// vanishes is a saddle point or a maximum.
+fn classify_stationary_point<D: Dim, M: Model<D>>(
+    function: &ErrorFunction<D, M>,
+    x: &OVector<f64, D>,
+) -> Option<Termination>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    if !function.has_exact_hessian() {
+        return None;
+    }
+    let hess = function.hess(x);
+    if hess.iter().any(|v| !v.is_finite()) {
+        return None;
+    }
+
+    let (eigenvalues, _) = symmetric_eigen(&hess);
+    let tolerance = CURVATURE_TOLERANCE * eigenvalues.amax();
+    if eigenvalues.iter().all(|v| *v < -tolerance) {
+        Some(Termination::Maximum)
+    } else if eigenvalues.iter().any(|v| *v < -tolerance) {
+        Some(Termination::SaddlePoint)
+    } else {
+        None
+    }
+}
+
+/// Is a point where a minimizer stalled a minimum? That is the case when the gradient
+/// norm is below the tolerance, or when the gradient is too small to be resolved:
+/// the decrease of the error a Newton step predicts is below the rounding error of
+/// the error, so no step can decrease the error any further.
+fn classify_stall<D: Dim, M: Model<D>>(
+    function: &ErrorFunction<D, M>,
+    x: &OVector<f64, D>,
+    gradient: &OVector<f64, D>,
+    gradient_tolerance: f64,
+) -> Termination
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    if gradient.norm() < gradient_tolerance {
+        return Termination::GradientConverged;
+    }
+    let Some(cholesky) = function.hess(x).cholesky() else {
+        return Termination::Stalled;
+    };
+    let predicted = 0.5 * gradient.dot(&cholesky.solve(gradient));
+    if predicted <= f64::EPSILON * function.f(x).abs() {
+        Termination::PrecisionLimit
+    } else {
+        Termination::Stalled
+    }
+}
+
+/// Keeps track of the progress of a minimizer, and creates its report.
+pub struct Progress {
+    start_counts: EvaluationCounts,
+    gradient_tolerance: f64,
+    iterations: usize,
+    stages: Vec<Stage>,
+    history: Option<Vec<f64>>,
+}
+
+impl Progress {
+    pub fn start<D: Dim, M: Model<D>>(
+        function: &ErrorFunction<D, M>,
+        config: &MinimizerConfig,
+    ) -> Self
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        Self {
+            start_counts: function.evaluation_counts(),
+            gradient_tolerance: config.gradient_tolerance,
+            iterations: 0,
+            stages: Vec::new(),
+            history: config.record_history.then(Vec::new),
+        }
+    }
+
+    /// Record a finished iteration, where `f` gives the resulting error. `f` is
+    /// only called if the history is recorded.
+    pub fn iteration(&mut self, f: impl FnOnce() -> f64) {
+        self.iterations += 1;
+        if let Some(history) = &mut self.history {
+            history.push(f());
+        }
+    }
+
+    /// Record the report of a minimizer that was run as a stage of this minimizer.
+    pub fn stage(&mut self, name: &'static str, report: &MinimizerReport) {
+        self.iterations += report.iterations;
+        if let (Some(history), Some(stage_history)) = (&mut self.history, &report.history) {
+            history.extend(stage_history);
+        }
+        self.stages.push(Stage {
+            name,
+            iterations: report.iterations,
+            termination: report.termination,
+        });
+    }
+
+    /// Create the report of the minimizer.
+    pub fn finish<D: Dim, M: Model<D>>(
+        self,
+        function: &ErrorFunction<D, M>,
+        x: OVector<f64, D>,
+        termination: Termination,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let gradient = function.grad(&x);
+        let gradient_norm = gradient.norm();
+        // a minimizer that can't decrease the error any further has only found a
+        // minimum if the gradient vanishes there
+        let termination = match termination {
+            Termination::Stalled => {
+                classify_stall(function, &x, &gradient, self.gradient_tolerance)
+            }
+            termination => termination,
+        };
+        let report = MinimizerReport {
+            termination,
+            iterations: self.iterations,
+            evaluations: function.evaluation_counts() - self.start_counts,
+            gradient_norm,
+            stages: self.stages,
+            history: self.history,
+        };
+        (x, report)
+    }
+}
@@ -0,0 +1,150 @@
+use log::info;
+use nalgebra::{DefaultAllocator, Dim, OMatrix, OVector, U1};
+
+use super::{Minimizer, MinimizerConfig, MinimizerOut, Progress, Termination};
+use crate::error_functions::{ErrorFunction, Evaluation};
+use crate::functions::{Model, ParameterAllocator};
+
+/// Newton's method with a trust region, where every step minimizes the quadratic
+/// model of the function within a radius that adapts to how well the model predicts
+/// the actual reduction. Unlike damping, this also works where the hessian is not
+/// positive definite.
+pub struct TrustRegion;
+
+/// Finds `tau >= 0` such that `|z + tau * d|` equals the radius.
+fn to_boundary<D: Dim>(z: &OVector<f64, D>, d: &OVector<f64, D>, radius: f64) -> f64
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let a = d.norm_squared();
+    let b = 2.0 * z.dot(d);
+    let c = z.norm_squared() - radius.powi(2);
+    (-b + (b * b - 4.0 * a * c).max(0.0).sqrt()) / (2.0 * a)
+}
+
+/// Approximately minimizes the model `g·p + pᵀHp/2` subject to `|p| <= radius` using
+/// the Steihaug conjugate gradient method. If the model has negative curvature along
+/// a search direction, the step follows that direction to the edge of the region.
+fn steihaug_cg<D: Dim>(
+    g: &OVector<f64, D>,
+    hess: &OMatrix<f64, D, D>,
+    radius: f64,
+) -> OVector<f64, D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let tolerance = g.norm().sqrt().min(0.5) * g.norm();
+
+    let mut z = OVector::zeros_generic(g.shape_generic().0, U1);
+    let mut r = g.clone();
+    let mut d = -&r;
+    for _ in 0..2 * g.len() {
+        let curvature = d.dot(&(hess * &d));
+        if curvature <= 0.0 {
+            return &z + to_boundary(&z, &d, radius) * d;
+        }
+
+        let alpha = r.norm_squared() / curvature;
+        let next_z = &z + alpha * &d;
+        if next_z.norm() >= radius {
+            return &z + to_boundary(&z, &d, radius) * d;
+        }
+
+        let next_r = &r + alpha * (hess * &d);
+        if next_r.norm() < tolerance {
+            return next_z;
+        }
+
+        let beta = next_r.norm_squared() / r.norm_squared();
+        d = -&next_r + beta * d;
+        r = next_r;
+        z = next_z;
+    }
+
+    z
+}
+
+impl Minimizer for TrustRegion {
+    const NAME: &'static str = "trust_region";
+    const NEEDS_HESSIAN: bool = true;
+
+    fn minimize<D: Dim, M: Model<D>>(
+        &self,
+        function: &ErrorFunction<D, M>,
+        x0: &OVector<f64, D>,
+        config: &MinimizerConfig,
+    ) -> MinimizerOut<D>
+    where
+        DefaultAllocator: ParameterAllocator<D>,
+    {
+        let mut progress = Progress::start(function, config);
+        if !function.has_hessian() {
+            return progress.finish(
+                function,
+                x0.clone(),
+                Termination::Error("Function has no hessian!"),
+            );
+        }
+
+        let mut x = x0.clone();
+        let mut f_val = function.f(&x);
+        let mut radius = x.norm().max(1.0);
+
+        for _ in 0..config.max_iterations {
+            if let Some(termination) = config.budget_exhausted(function) {
+                return progress.finish(function, x, termination);
+            }
+
+            let Evaluation {
+                gradient: g,
+                hessian: hess,
+                ..
+            } = function.evaluate_all(&x);
+            if config.gradient_converged(&g) {
+                info!("Trust region converged!");
+                return progress.finish(function, x, Termination::GradientConverged);
+            }
+            if hess.iter().any(|v| !v.is_finite()) {
+                return progress.finish(function, x, Termination::Error("Hessian is not finite!"));
+            }
+
+            // shrink the trust region until the step decreases the function value
+            loop {
+                let step = steihaug_cg(&g, &hess, radius);
+                let next_x = &x + &step;
+                let next_f = function.f(&next_x);
+
+                // ratio between actual and predicted reduction of the error
+                let predicted = -(g.dot(&step) + 0.5 * step.dot(&(&hess * &step)));
+                let rho = (f_val - next_f) / predicted;
+                if rho < 0.25 || !rho.is_finite() {
+                    radius = 0.25 * step.norm();
+                } else if rho > 0.75 && step.norm() >= 0.99 * radius {
+                    radius *= 2.0;
+                }
+
+                if predicted > 0.0 && rho > 1e-4 {
+                    x = next_x;
+                    progress.iteration(|| next_f);
+                    if config.function_converged(f_val, next_f) {
+                        info!("Trust region converged!");
+                        return progress.finish(function, x, Termination::FunctionConverged);
+                    }
+                    if config.step_converged(&step, &x) {
+                        info!("Trust region converged!");
+                        return progress.finish(function, x, Termination::StepConverged);
+                    }
+                    f_val = next_f;
+                    break;
+                }
+
+                if radius <= f64::EPSILON * (x.norm() + f64::EPSILON) {
+                    info!("Trust region got a radius of zero");
+                    return progress.finish(function, x, Termination::Stalled);
+                }
+            }
+        }
+
+        progress.finish(function, x, Termination::MaxIterations)
+    }
+}
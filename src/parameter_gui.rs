@@ -1,27 +1,42 @@
 use eframe::egui::{self, Ui};
 use egui::{Color32, Widget};
 use egui_plot::{Line, Plot, PlotPoints, Points};
-use itertools::{Itertools, MinMaxResult, izip, repeat_n};
+use itertools::{Either, Itertools, MinMaxResult, izip, repeat_n};
 use strum::IntoEnumIterator;
 
 use std::{
     collections::HashMap,
     fmt::Display,
+    mem,
     path::{Path, PathBuf},
-    sync::mpsc::{self, TryRecvError},
+    sync::{
+        Arc,
+        mpsc::{self, TryRecvError},
+    },
     thread,
     time::Duration,
 };
 
+use crate::error_functions::{HessianMode, Objective, error};
 use crate::functions::Functions;
+use crate::functions::composite::Composite;
+use crate::functions::expansion::{DEFAULT_EXPANSION, Expansion};
+use crate::functions::formula::{DEFAULT_FORMULA, Formula};
+use crate::loss::{DOWN_WEIGHT_THRESHOLD, LossFunction};
+use crate::minimizers::{
+    GlobalSearch, MinimizerConfig, MinimizerReport, Minimizers, RefinementStage,
+};
+use crate::parameters::{Bounds, ParameterSpecs, ParameterStatus, Range};
 use crate::plotting::plotter::plot_slice;
-use crate::utils::{format_with_uncertainty, load_txt};
-use crate::{OptimizinateResult, error_functions::error};
+use crate::utils::{Dataset, format_with_uncertainty, g_format, load_txt};
+use crate::{FitSettings, OptimizinateResult};
 
 pub fn create_gui(
     datafile: &Path,
     function: Option<Functions>,
     initial_parameters: Option<Vec<f64>>,
+    specs: Option<ParameterSpecs>,
+    settings: FitSettings,
 ) {
     const SCALE: f32 = 1.25;
     const ICON: &[u8; 64 * 64 * 4] = include_bytes!("../media/icon.raw");
@@ -48,6 +63,8 @@ pub fn create_gui(
                 datafile_clone,
                 function,
                 initial_parameters,
+                specs,
+                settings,
             )))
         }),
     )
@@ -71,11 +88,66 @@ impl Display for Message {
     }
 }
 
+/// Parse a parameter range, where an empty string means that the parameter has no range.
+fn parse_optional_range(string: &str) -> Result<Option<Range>, String> {
+    let string = string.trim();
+    if string.is_empty() {
+        Ok(None)
+    } else {
+        Range::parse(string).map(Some)
+    }
+}
+
+/// Parse parameter bounds, where an empty string means that the parameter is unbounded.
+fn parse_optional_bounds(string: &str) -> Result<Bounds, String> {
+    let string = string.trim();
+    if string.is_empty() {
+        Ok(Bounds::default())
+    } else {
+        Bounds::parse(string)
+    }
+}
+
+/// The name of a function in the menus. The formula, composite model and expansion
+/// are left out, as they are edited below the menu.
+fn function_label(function: &Functions) -> String {
+    match function {
+        Functions::Formula(_) => "Formula".into(),
+        Functions::Composite(_) => "Composite".into(),
+        Functions::Expansion(_) => "Expansion".into(),
+        _ => format!("{:?}", function),
+    }
+}
+
+/// Format bounds the way they are parsed, leaving out infinite bounds.
+fn format_bounds(bounds: &Bounds) -> String {
+    if !bounds.is_bounded() {
+        return String::new();
+    }
+    let format = |bound: f64| {
+        if bound.is_finite() {
+            bound.to_string()
+        } else {
+            String::new()
+        }
+    };
+    format!("{}:{}", format(bounds.lower), format(bounds.upper))
+}
+
 struct ParameterStore {
-    names: Vec<&'static str>,
+    names: Vec<String>,
     strings: Vec<String>,
     values: Vec<Option<f64>>,
+    specs: ParameterSpecs,
+    range_strings: Vec<String>,
+    bound_strings: Vec<String>,
+    /// Which parameters are kept at their value instead of being fitted.
+    locked: Vec<bool>,
     uncertainties: Option<Vec<f64>>,
+    statuses: Option<Vec<ParameterStatus>>,
+    /// The weight the loss gave each data point in the last fit.
+    loss_weights: Option<Vec<f64>>,
+    report: Option<MinimizerReport>,
 }
 
 impl ParameterStore {
@@ -86,20 +158,44 @@ impl ParameterStore {
         (strings, values)
     }
 
-    fn new(function: &Functions, values: &Option<Vec<f64>>) -> Self {
+    fn new(function: &Functions, values: &Option<Vec<f64>>, specs: Option<ParameterSpecs>) -> Self {
         let count = function.parameter_count();
-        let values = values
+        let mut values = values
             .clone()
             .unwrap_or_else(|| repeat_n(1.0, count).collect());
+        let specs = specs.unwrap_or_else(|| ParameterSpecs::new(count));
+        specs.apply_fixed(&mut values);
 
-        let names = function.parameter_names();
+        let names = function
+            .parameter_names()
+            .into_iter()
+            .map(String::from)
+            .collect();
         let (strings, values) = Self::slice_to_values(&values);
 
+        let range_strings = specs
+            .ranges
+            .iter()
+            .map(|range| match range {
+                Some(range) => format!("{}:{}", range.lower, range.upper),
+                None => String::new(),
+            })
+            .collect();
+        let bound_strings = specs.bounds.iter().map(format_bounds).collect();
+        let locked = specs.fixed.iter().map(Option::is_some).collect();
+
         Self {
             names,
             strings,
             values,
+            specs,
+            range_strings,
+            bound_strings,
+            locked,
             uncertainties: None,
+            statuses: None,
+            loss_weights: None,
+            report: None,
         }
     }
 
@@ -111,15 +207,21 @@ impl ParameterStore {
         }
     }
 
-    fn update_values(&mut self, new_values: &[f64], uncertainties: &[f64]) {
-        (self.strings, self.values) = Self::slice_to_values(new_values);
-        self.uncertainties = Some(uncertainties.to_vec());
+    fn update_values(&mut self, result: &OptimizinateResult) {
+        (self.strings, self.values) = Self::slice_to_values(&result.parameters);
+        self.uncertainties = Some(result.uncertainties.clone());
+        self.statuses = Some(result.statuses.clone());
+        self.loss_weights = Some(result.loss_weights.clone());
+        self.report = Some(result.report.clone());
     }
 
     fn reset(&mut self) {
         let ones: Vec<f64> = repeat_n(1.0, self.names.len()).collect();
         (self.strings, self.values) = Self::slice_to_values(&ones);
         self.uncertainties = None;
+        self.statuses = None;
+        self.loss_weights = None;
+        self.report = None;
     }
 }
 
@@ -128,14 +230,19 @@ struct ParameterStoreMap {
 }
 
 impl ParameterStoreMap {
-    fn new(function: &Functions, initial_parameters: Option<Vec<f64>>) -> Self {
-        let map = HashMap::from_iter(Functions::iter().map(|f| {
-            let store = if f == *function && initial_parameters.is_some() {
-                ParameterStore::new(&f, &initial_parameters)
+    fn new(
+        functions: &[Functions],
+        function: &Functions,
+        initial_parameters: Option<Vec<f64>>,
+        specs: Option<ParameterSpecs>,
+    ) -> Self {
+        let map = HashMap::from_iter(functions.iter().map(|f| {
+            let store = if f == function {
+                ParameterStore::new(f, &initial_parameters, specs.clone())
             } else {
-                ParameterStore::new(&f, &None)
+                ParameterStore::new(f, &None, None)
             };
-            (f, store)
+            (f.clone(), store)
         }));
         Self { map }
     }
@@ -151,27 +258,90 @@ impl ParameterStoreMap {
             .get_mut(function)
             .expect("map should contain parameters for all functions")
     }
+
+    /// Replace the parameters of a function with the parameters of another function,
+    /// like an edited formula, keeping the values of the parameters that are still there.
+    fn renew(&mut self, old: &Functions, function: &Functions) {
+        let mut store = ParameterStore::new(function, &None, None);
+        if let Some(old) = self.map.remove(old) {
+            for (i, name) in store.names.iter().enumerate() {
+                if let Some(j) = old.names.iter().position(|old_name| old_name == name) {
+                    store.strings[i] = old.strings[j].clone();
+                    store.values[i] = old.values[j];
+                }
+            }
+        }
+        self.map.insert(function.clone(), store);
+    }
+}
+
+/// Text fields used to edit the minimizer config.
+struct ConfigEditor {
+    strings: Vec<String>,
+    valid: Vec<bool>,
+}
+
+impl ConfigEditor {
+    fn new(config: &MinimizerConfig) -> Self {
+        let strings = MinimizerConfig::KEYS
+            .iter()
+            .map(|key| {
+                config
+                    .get(key)
+                    .expect("KEYS should only contain valid keys")
+            })
+            .collect();
+        let valid = vec![true; MinimizerConfig::KEYS.len()];
+        Self { strings, valid }
+    }
+
+    fn show(&mut self, ui: &mut Ui, config: &mut MinimizerConfig) {
+        egui::Grid::new("config_editor").show(ui, |ui| {
+            for (i, key) in MinimizerConfig::KEYS.iter().enumerate() {
+                ui.label(key.replace('_', " "));
+                let mut text_edit = egui::TextEdit::singleline(&mut self.strings[i]);
+                if !self.valid[i] {
+                    text_edit = text_edit.text_color(Color32::RED);
+                }
+
+                if text_edit.ui(ui).changed() {
+                    self.valid[i] = config.set(key, &self.strings[i]).is_ok();
+                }
+                ui.end_row();
+            }
+        });
+    }
 }
 
 struct RunThread {
     thread: Option<thread::JoinHandle<()>>,
-    receiver: mpsc::Receiver<OptimizinateResult>,
+    receiver: mpsc::Receiver<Result<OptimizinateResult, String>>,
 }
 
 impl RunThread {
-    fn start(function: Functions, datafile: PathBuf, mut parameters: Vec<f64>) -> Self {
+    fn start(
+        function: Functions,
+        datafile: PathBuf,
+        mut parameters: Vec<f64>,
+        specs: ParameterSpecs,
+        settings: FitSettings,
+    ) -> Self {
         let (result_tx, result_rx) = mpsc::channel();
 
         let thread = thread::spawn(move || {
             let mut i = 0;
             let mut previous_error = f64::INFINITY;
             loop {
-                let result = function.optimizinate(&datafile, Some(&parameters), false);
+                let result =
+                    function.optimizinate(&datafile, Some(&parameters), &specs, &settings, false);
                 let _ = result_tx.send(result.clone());
+                let Ok(result) = result else {
+                    return;
+                };
 
                 i += 1;
 
-                if previous_error - result.error < 1e-8 {
+                if previous_error - result.error < settings.config.restart_tolerance {
                     log::debug!("Error is not decreasing, stopping after {i} iterations");
                     return;
                 }
@@ -196,11 +366,22 @@ impl Drop for RunThread {
 }
 
 struct MyApp {
-    x_ray: Vec<f64>,
-    y_ray: Vec<f64>,
+    data: Dataset,
     message: Message,
     datafile: PathBuf,
     function: Functions,
+    /// The functions that can be selected, with the formula, composite model and
+    /// expansion that were last given.
+    functions: Vec<Functions>,
+    /// The text of the formula field, which may not be a valid formula.
+    formula: String,
+    /// The text of the expansion field, which may not be a valid expansion.
+    expansion: String,
+    /// The components of the composite model, and whether each of them is added to
+    /// the components before it, rather than multiplied with them.
+    components: Vec<(bool, Functions)>,
+    settings: FitSettings,
+    config_editor: ConfigEditor,
     run_thread: Option<RunThread>,
     parameter_store_map: ParameterStoreMap,
 }
@@ -210,17 +391,47 @@ impl MyApp {
         datafile: PathBuf,
         function: Option<Functions>,
         initial_parameters: Option<Vec<f64>>,
+        specs: Option<ParameterSpecs>,
+        settings: FitSettings,
     ) -> Self {
         let function = function.unwrap_or(Functions::Line);
-        let parameter_store_map = ParameterStoreMap::new(&function, initial_parameters);
+        // a function that is given at runtime takes the place of the default one
+        let functions: Vec<Functions> = Functions::iter()
+            .map(|f| {
+                if mem::discriminant(&f) == mem::discriminant(&function) {
+                    function.clone()
+                } else {
+                    f
+                }
+            })
+            .collect();
+        let parameter_store_map =
+            ParameterStoreMap::new(&functions, &function, initial_parameters, specs);
+        let formula = match &function {
+            Functions::Formula(formula) => formula.source.clone(),
+            _ => DEFAULT_FORMULA.to_string(),
+        };
+        let expansion = match &function {
+            Functions::Expansion(expansion) => expansion.source.clone(),
+            _ => DEFAULT_EXPANSION.to_string(),
+        };
+        let components = match &function {
+            Functions::Composite(composite) => composite.components().collect(),
+            _ => Composite::default().components().collect(),
+        };
 
-        let (x_ray, y_ray) = load_txt(&datafile).unwrap();
+        let data = load_txt(&datafile).unwrap();
         Self {
-            x_ray,
-            y_ray,
+            data,
             message: Message::None,
             datafile,
             function,
+            functions,
+            formula,
+            expansion,
+            components,
+            settings,
+            config_editor: ConfigEditor::new(&settings.config),
             run_thread: None,
             parameter_store_map,
         }
@@ -228,16 +439,42 @@ impl MyApp {
 
     fn run(&mut self) -> Message {
         let parameter_store = self.parameter_store_map.get_mut(&self.function);
-        if let Some(parameters) = parameter_store.get_parameters() {
-            self.run_thread = Some(RunThread::start(
-                self.function,
-                self.datafile.clone(),
-                parameters,
-            ));
-            Message::None
-        } else {
-            Message::Error("Some parameters are malformed.".into())
+        let Some(parameters) = parameter_store.get_parameters() else {
+            return Message::Error("Some parameters are malformed.".into());
+        };
+        if let Err(e) = self.settings.validate() {
+            return Message::Error(e);
+        }
+        for (i, value) in parameters.iter().enumerate() {
+            match parse_optional_range(&parameter_store.range_strings[i]) {
+                Ok(range) => parameter_store.specs.ranges[i] = range,
+                Err(e) => return Message::Error(e),
+            }
+            match parse_optional_bounds(&parameter_store.bound_strings[i]) {
+                Ok(bounds) => parameter_store.specs.bounds[i] = bounds,
+                Err(e) => return Message::Error(e),
+            }
+            parameter_store.specs.fixed[i] = parameter_store.locked[i].then_some(*value);
+        }
+
+        self.run_thread = Some(RunThread::start(
+            self.function.clone(),
+            self.datafile.clone(),
+            parameters,
+            parameter_store.specs.clone(),
+            self.settings,
+        ));
+        Message::None
+    }
+
+    /// Replace the selected function with another function of the same kind, like an
+    /// edited formula, keeping the values of the parameters that are still there.
+    fn replace_function(&mut self, function: Functions) {
+        self.parameter_store_map.renew(&self.function, &function);
+        if let Some(selectable) = self.functions.iter_mut().find(|f| **f == self.function) {
+            *selectable = function.clone();
         }
+        self.function = function;
     }
 
     fn read_run_thread(&mut self) -> Option<Message> {
@@ -256,15 +493,23 @@ impl MyApp {
                     }
                 }
             }
-            let result = result?;
+            let result = match result? {
+                Ok(result) => result,
+                Err(e) => return Some(Message::Error(e)),
+            };
 
             self.parameter_store_map
                 .get_mut(&self.function)
-                .update_values(&result.parameters, &result.uncertainties);
-            Some(Message::Ok(format!(
-                "Got parameters {}",
-                format_with_uncertainty(&result.parameters, &result.uncertainties)
-            )))
+                .update_values(&result);
+            let parameters = format_with_uncertainty(&result.parameters, &result.uncertainties);
+            Some(Message::Ok(match result.reduced_chi_squared {
+                Some(reduced_chi_squared) => format!(
+                    "Got parameters {}, with a reduced χ² of {}",
+                    parameters,
+                    g_format(reduced_chi_squared, 5)
+                ),
+                None => format!("Got parameters {}", parameters),
+            }))
         } else {
             None
         }
@@ -276,8 +521,8 @@ impl MyApp {
             let data_name = self.datafile.file_stem().unwrap().to_string_lossy();
             let figure_name = format!("figures/{}-{}.png", data_name, self.function.name());
             plot_slice(
-                &self.x_ray,
-                &self.y_ray,
+                &self.data.x,
+                &self.data.y,
                 |x, p| self.function.f(x, p),
                 &parameters,
                 &parameter_store.uncertainties,
@@ -289,20 +534,125 @@ impl MyApp {
         }
     }
 
+    /// Shows the components of the composite model, which can be added, removed,
+    /// changed, and added or multiplied together.
+    fn show_component_builder(&mut self, ui: &mut Ui) {
+        let mut changed = false;
+        // the fit updates the parameters of the composite model, so it can't change
+        // during a fit
+        ui.add_enabled_ui(self.run_thread.is_none(), |ui| {
+            ui.horizontal_wrapped(|ui| {
+                ui.label("f(x) = ");
+                let mut removed = None;
+                let count = self.components.len();
+                for (i, (adds, function)) in self.components.iter_mut().enumerate() {
+                    if i > 0 {
+                        egui::ComboBox::from_id_salt(("operator", i))
+                            .width(20.0)
+                            .selected_text(if *adds { "+" } else { "*" })
+                            .show_ui(ui, |ui| {
+                                changed |= ui.selectable_value(adds, true, "+").changed();
+                                changed |= ui.selectable_value(adds, false, "*").changed();
+                            });
+                    }
+                    egui::ComboBox::from_id_salt(("component", i))
+                        .selected_text(format!("{:?}", function))
+                        .show_ui(ui, |ui| {
+                            for variant in Functions::iter().filter(Functions::is_built_in) {
+                                let text = format!("{:?}", variant);
+                                changed |= ui.selectable_value(function, variant, text).changed();
+                            }
+                        });
+                    if count > 1 && ui.small_button("🗙").on_hover_text("Remove").clicked() {
+                        removed = Some(i);
+                    }
+                }
+                if let Some(i) = removed {
+                    self.components.remove(i);
+                    changed = true;
+                }
+                if ui.button("Add component").clicked() {
+                    self.components.push((true, Functions::Line));
+                    changed = true;
+                }
+            });
+        });
+
+        if changed {
+            let mut source = String::new();
+            for (i, (adds, function)) in self.components.iter().enumerate() {
+                if i > 0 {
+                    source += if *adds { " + " } else { " * " };
+                }
+                source += function.name();
+            }
+            match Composite::parse(&source) {
+                Ok(composite) => {
+                    self.replace_function(Functions::Composite(Arc::new(composite)));
+                    self.message = Message::None;
+                }
+                Err(e) => self.message = Message::Error(e),
+            }
+        }
+    }
+
+    fn show_report(&self, ui: &mut Ui) {
+        let Some(report) = &self.parameter_store_map.get(&self.function).report else {
+            return;
+        };
+
+        egui::CollapsingHeader::new("Report").show(ui, |ui| {
+            ui.label(report.to_string());
+
+            if let Some(history) = &report.history {
+                let points: PlotPoints = history
+                    .iter()
+                    .enumerate()
+                    .map(|(i, error)| [i as f64, error.log10()])
+                    .collect();
+                Plot::new("history_plot")
+                    .height(120.0)
+                    .x_axis_label("iteration")
+                    .y_axis_label("log10(error)")
+                    .show(ui, |plot_ui| {
+                        plot_ui.line(
+                            Line::new("Error", points).color(Color32::from_hex("#1f77b4").unwrap()),
+                        );
+                    });
+            }
+        });
+    }
+
     fn show_figure(&self, ui: &mut Ui) {
         let parameter_store = self.parameter_store_map.get(&self.function);
-        let data: PlotPoints = izip!(&self.x_ray, &self.y_ray)
-            .map(|(x, y)| [*x, *y])
-            .collect();
-        let data_points = Points::new("Data", data)
+        // points the loss down-weighted in the last fit are shown separately
+        let down_weighted: Vec<bool> = match &parameter_store.loss_weights {
+            Some(weights) => weights.iter().map(|w| *w < DOWN_WEIGHT_THRESHOLD).collect(),
+            None => vec![false; self.data.x.len()],
+        };
+        let (down_weighted_data, data): (Vec<_>, Vec<_>) =
+            izip!(&self.data.x, &self.data.y, &down_weighted).partition_map(
+                |(x, y, down_weighted)| {
+                    if *down_weighted {
+                        Either::Left([*x, *y])
+                    } else {
+                        Either::Right([*x, *y])
+                    }
+                },
+            );
+        let data_points = Points::new("Data", PlotPoints::from(data))
             .radius(4.0)
             .color(Color32::from_hex("#1f77b4").unwrap());
+        let down_weighted_points =
+            Points::new("Down-weighted", PlotPoints::from(down_weighted_data))
+                .radius(4.0)
+                .color(Color32::from_hex("#d62728").unwrap());
 
         let line = if parameter_store.values.iter().all(Option::is_some) {
             let params: Vec<f64> = parameter_store.values.iter().filter_map(|v| *v).collect();
 
             const N: usize = 1000;
-            let (min, max) = match self.x_ray.iter().minmax() {
+            let (min, max) = match self.data.x.iter().minmax() {
                 MinMaxResult::MinMax(min, max) => (*min, *max),
                 _ => panic!("x_ray must have more than one item!"),
             };
@@ -319,6 +669,7 @@ impl MyApp {
 
         Plot::new("my_plot").show(ui, |plot_ui| {
             plot_ui.points(data_points);
+            plot_ui.points(down_weighted_points);
             if let Some(line) = line {
                 plot_ui.line(line);
             }
@@ -331,12 +682,12 @@ impl eframe::App for MyApp {
         egui::CentralPanel::default().show(ctx, |ui| {
             // Function combo box
             egui::ComboBox::from_label("Select a function")
-                .selected_text(format!("{:?}", self.function))
+                .selected_text(function_label(&self.function))
                 .show_ui(ui, |ui| {
-                    for variant in Functions::iter() {
-                        let text = format!("{:?}", variant);
+                    for variant in &self.functions {
+                        let text = function_label(variant);
                         if ui
-                            .selectable_value(&mut self.function, variant, text)
+                            .selectable_value(&mut self.function, variant.clone(), text)
                             .changed()
                         {
                             self.message = Message::None;
@@ -344,6 +695,172 @@ impl eframe::App for MyApp {
                     }
                 });
 
+            if let Functions::Formula(formula) = &self.function {
+                // valid formulas are applied as they are typed
+                let valid = formula.source == self.formula;
+                ui.horizontal(|ui| {
+                    ui.label("f(x) = ");
+                    let mut text_edit =
+                        egui::TextEdit::singleline(&mut self.formula).hint_text(DEFAULT_FORMULA);
+                    if !valid {
+                        text_edit = text_edit.text_color(Color32::RED);
+                    }
+                    // the fit updates the parameters of the formula, so it can't change
+                    // during a fit
+                    let response = ui
+                        .add_enabled(self.run_thread.is_none(), text_edit)
+                        .on_hover_text(
+                            "A formula of x, where every other name is a parameter. \
+                            Operators are +, -, *, / and ^, and functions include exp, ln, \
+                            sqrt, sin and cos.",
+                        );
+                    if response.changed() {
+                        match Formula::parse(&self.formula) {
+                            Ok(formula) => {
+                                self.replace_function(Functions::Formula(Arc::new(formula)));
+                                self.message = Message::None;
+                            }
+                            Err(e) => self.message = Message::Error(e),
+                        }
+                    }
+                });
+            }
+
+            if matches!(self.function, Functions::Composite(_)) {
+                self.show_component_builder(ui);
+            }
+
+            if let Functions::Expansion(expansion) = &self.function {
+                // valid expansions are applied as they are typed
+                let valid = expansion.source == self.expansion;
+                ui.horizontal(|ui| {
+                    ui.label("Expansion: ");
+                    let mut text_edit = egui::TextEdit::singleline(&mut self.expansion)
+                        .hint_text(DEFAULT_EXPANSION);
+                    if !valid {
+                        text_edit = text_edit.text_color(Color32::RED);
+                    }
+                    // the fit updates the parameters of the expansion, so it can't
+                    // change during a fit
+                    let response = ui
+                        .add_enabled(self.run_thread.is_none(), text_edit)
+                        .on_hover_text(
+                            "poly:N for a polynomial of degree N, chebyshev:N or \
+                            legendre:N for orthogonal polynomials, optionally followed by \
+                            the domain, as in chebyshev:5:0:10, or bspline:N:knots for \
+                            B-splines, as in bspline:3:0,2,4,6. Expansions are fitted \
+                            exactly by linear least squares.",
+                        );
+                    if response.changed() {
+                        match Expansion::parse(&self.expansion) {
+                            Ok(expansion) => {
+                                self.replace_function(Functions::Expansion(Arc::new(expansion)));
+                                self.message = Message::None;
+                            }
+                            Err(e) => self.message = Message::Error(e),
+                        }
+                    }
+                });
+            }
+
+            // Minimizer combo boxes
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Global search")
+                    .selected_text(format!("{:?}", self.settings.global))
+                    .show_ui(ui, |ui| {
+                        for variant in GlobalSearch::iter() {
+                            let text = format!("{:?}", variant);
+                            ui.selectable_value(&mut self.settings.global, variant, text);
+                        }
+                    });
+                ui.add_space(5.0);
+
+                egui::ComboBox::from_label("Select a minimizer")
+                    .selected_text(format!("{:?}", self.settings.minimizer))
+                    .show_ui(ui, |ui| {
+                        for variant in Minimizers::iter() {
+                            let text = format!("{:?}", variant);
+                            ui.selectable_value(&mut self.settings.minimizer, variant, text);
+                        }
+                    });
+
+                if self.settings.minimizer == Minimizers::Combined {
+                    ui.add_space(5.0);
+                    egui::ComboBox::from_label("Refinement stage")
+                        .selected_text(format!("{:?}", self.settings.refinement))
+                        .show_ui(ui, |ui| {
+                            for variant in RefinementStage::iter() {
+                                let text = format!("{:?}", variant);
+                                ui.selectable_value(&mut self.settings.refinement, variant, text);
+                            }
+                        });
+                }
+
+                if self
+                    .settings
+                    .minimizer
+                    .needs_hessian(self.settings.refinement)
+                {
+                    ui.add_space(5.0);
+                    egui::ComboBox::from_label("Hessian")
+                        .selected_text(format!("{:?}", self.settings.hessian_mode))
+                        .show_ui(ui, |ui| {
+                            for variant in HessianMode::iter() {
+                                let text = format!("{:?}", variant);
+                                ui.selectable_value(&mut self.settings.hessian_mode, variant, text);
+                            }
+                        });
+                }
+            });
+
+            ui.horizontal(|ui| {
+                egui::ComboBox::from_label("Objective")
+                    .selected_text(format!("{:?}", self.settings.objective))
+                    .show_ui(ui, |ui| {
+                        for variant in Objective::iter() {
+                            let text = format!("{:?}", variant);
+                            ui.selectable_value(&mut self.settings.objective, variant, text);
+                        }
+                    });
+                ui.add_space(5.0);
+
+                egui::ComboBox::from_label("Loss")
+                    .selected_text(format!("{:?}", self.settings.loss.function))
+                    .show_ui(ui, |ui| {
+                        for variant in LossFunction::iter() {
+                            let text = format!("{:?}", variant);
+                            ui.selectable_value(&mut self.settings.loss.function, variant, text);
+                        }
+                    });
+
+                if self.settings.loss.is_robust() {
+                    ui.add_space(5.0);
+                    ui.label("scale: ");
+                    ui.add(
+                        egui::DragValue::new(&mut self.settings.loss.scale)
+                            .speed(0.01)
+                            .range(1e-6..=f64::INFINITY),
+                    )
+                    .on_hover_text(
+                        "The residual where the loss starts to down-weight points, \
+                        in units of σ for data with uncertainties",
+                    );
+                }
+            });
+
+            if self.data.sigma.is_some() {
+                ui.checkbox(&mut self.settings.relative_sigma, "Relative σ")
+                    .on_hover_text(
+                        "Treat the uncertainties of the data as relative weights, \
+                        and scale the parameter uncertainties by the reduced χ²",
+                    );
+            }
+
+            // Minimizer config
+            egui::CollapsingHeader::new("Advanced").show(ui, |ui| {
+                self.config_editor.show(ui, &mut self.settings.config);
+            });
+
             ui.add_space(5.0);
 
             // Parameter selection boxes
@@ -352,6 +869,10 @@ impl eframe::App for MyApp {
                 for (i, parameter) in parameter_store.names.iter().enumerate() {
                     ui.horizontal(|ui| {
                         ui.label(format!("{}: ", parameter));
+                        let locked = &mut parameter_store.locked[i];
+                        let icon = if *locked { "🔒" } else { "🔓" };
+                        ui.toggle_value(locked, icon)
+                            .on_hover_text("Keep the parameter at its value instead of fitting it");
                         let mut text_edit =
                             egui::TextEdit::singleline(&mut parameter_store.strings[i]);
                         if parameter_store.values[i].is_none() {
@@ -365,8 +886,52 @@ impl eframe::App for MyApp {
 
                         ui.add_space(2.0);
 
-                        if let Some(uncertainties) = &parameter_store.uncertainties {
-                            ui.label(format!("Δ{}: {}", parameter, uncertainties[i]));
+                        ui.label("bounds: ");
+                        let valid =
+                            parse_optional_bounds(&parameter_store.bound_strings[i]).is_ok();
+                        let mut text_edit =
+                            egui::TextEdit::singleline(&mut parameter_store.bound_strings[i])
+                                .hint_text("lower:upper")
+                                .desired_width(80.0);
+                        if !valid {
+                            text_edit = text_edit.text_color(Color32::RED);
+                        }
+                        text_edit.ui(ui);
+                        ui.add_space(2.0);
+
+                        if self.settings.global != GlobalSearch::None {
+                            ui.label("range: ");
+                            let valid =
+                                parse_optional_range(&parameter_store.range_strings[i]).is_ok();
+                            let mut text_edit =
+                                egui::TextEdit::singleline(&mut parameter_store.range_strings[i])
+                                    .hint_text("lower:upper")
+                                    .desired_width(80.0);
+                            if !valid {
+                                text_edit = text_edit.text_color(Color32::RED);
+                            }
+                            text_edit.ui(ui);
+                            ui.add_space(2.0);
+                        }
+
+                        if let (Some(uncertainties), Some(statuses)) =
+                            (&parameter_store.uncertainties, &parameter_store.statuses)
+                        {
+                            match statuses[i] {
+                                ParameterStatus::Free => {
+                                    ui.label(format!("Δ{}: {}", parameter, uncertainties[i]))
+                                }
+                                ParameterStatus::AtBound => {
+                                    ui.label(format!("Δ{}: at bound", parameter))
+                                }
+                                ParameterStatus::Fixed => {
+                                    ui.label(format!("Δ{}: fixed", parameter))
+                                }
+                                ParameterStatus::Tied { to, .. } => ui.label(format!(
+                                    "Δ{}: {} (tied to {})",
+                                    parameter, uncertainties[i], parameter_store.names[to]
+                                )),
+                            };
                         }
                     });
                 }
@@ -397,6 +962,9 @@ impl eframe::App for MyApp {
             }
             ui.label(self.message.to_string());
 
+            // Minimizer report
+            self.show_report(ui);
+
             ui.add_space(10.0);
 
             // Approximation error
@@ -407,7 +975,13 @@ impl eframe::App for MyApp {
             {
                 format!(
                     "{}",
-                    error(&self.x_ray, &self.y_ray, &self.function, &parameters)
+                    error(
+                        &self.data,
+                        &self.function,
+                        &parameters,
+                        &self.settings.loss,
+                        self.settings.objective
+                    )
                 )
             } else {
                 "NaN".into()
@@ -0,0 +1,691 @@
+use itertools::izip;
+
+use std::{fs, path::Path};
+
+use crate::utils::prettify_list;
+
+/// An interval of parameter values.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Range {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Range {
+    /// Parse a range of the form 'lower:upper'.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let Some((lower, upper)) = s.split_once(':') else {
+            return Err(format!(
+                "Got malformed range '{}'. Ranges must be of the form 'lower:upper'.",
+                s
+            ));
+        };
+        let parse = |v: &str| {
+            v.trim()
+                .parse::<f64>()
+                .map_err(|e| format!("Got malformed range '{}': {}", s, e))
+        };
+        let (lower, upper) = (parse(lower)?, parse(upper)?);
+        if !lower.is_finite() || !upper.is_finite() || lower >= upper {
+            return Err(format!(
+                "Got invalid range '{}'. The ends must be finite, and the lower end \
+                must be smaller than the upper end.",
+                s
+            ));
+        }
+        Ok(Self { lower, upper })
+    }
+
+    /// The range used for a parameter when the user has not given one, which
+    /// is centered on the value.
+    pub fn around(value: f64) -> Self {
+        let half_width = value.abs().max(1.0);
+        Self {
+            lower: value - half_width,
+            upper: value + half_width,
+        }
+    }
+
+    pub fn width(&self) -> f64 {
+        self.upper - self.lower
+    }
+
+    /// The point a fraction `t` of the way from the lower to the upper end.
+    pub fn lerp(&self, t: f64) -> f64 {
+        self.lower + t * self.width()
+    }
+}
+
+/// How far inside its bounds a parameter is moved if it starts on or outside a bound,
+/// relative to the scale of the bounds. At a bound, the gradient of the transformed
+/// parameter vanishes, so minimizers would never move it.
+const BOUND_MARGIN: f64 = 1e-3;
+/// A parameter is at a bound if it is this close to it, relative to the bound.
+const BOUND_TOLERANCE: f64 = 1e-8;
+
+/// Lower and upper bounds of a parameter, which are infinite if the parameter is
+/// unbounded in that direction.
+///
+/// Minimizers work with an unbounded internal parameter q, which is mapped to the
+/// bounded parameter p by
+/// - p = lower + (upper - lower)(sin(q) + 1)/2 if there are two bounds,
+/// - p = lower - 1 + sqrt(q² + 1) if there only is a lower bound,
+/// - p = upper + 1 - sqrt(q² + 1) if there only is an upper bound.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bounds {
+    pub lower: f64,
+    pub upper: f64,
+}
+
+impl Default for Bounds {
+    fn default() -> Self {
+        Self {
+            lower: f64::NEG_INFINITY,
+            upper: f64::INFINITY,
+        }
+    }
+}
+
+impl Bounds {
+    /// Parse bounds of the form 'lower:upper', where a missing value means that
+    /// there is no bound in that direction, like in '0:'.
+    pub fn parse(s: &str) -> Result<Self, String> {
+        let Some((lower, upper)) = s.split_once(':') else {
+            return Err(format!(
+                "Got malformed bounds '{}'. Bounds must be of the form 'lower:upper', \
+                where either value can be left out.",
+                s
+            ));
+        };
+        let parse = |v: &str, default: f64| {
+            let v = v.trim();
+            if v.is_empty() {
+                Ok(default)
+            } else {
+                v.parse::<f64>()
+                    .map_err(|e| format!("Got malformed bounds '{}': {}", s, e))
+            }
+        };
+        let bounds = Self {
+            lower: parse(lower, f64::NEG_INFINITY)?,
+            upper: parse(upper, f64::INFINITY)?,
+        };
+        if bounds.lower.is_nan() || bounds.upper.is_nan() || bounds.lower >= bounds.upper {
+            return Err(format!(
+                "Got invalid bounds '{}'. The lower bound must be smaller than the upper bound.",
+                s
+            ));
+        }
+        Ok(bounds)
+    }
+
+    pub fn is_bounded(&self) -> bool {
+        self.lower.is_finite() || self.upper.is_finite()
+    }
+
+    /// Is the value at one of the bounds?
+    pub fn at_bound(&self, value: f64) -> bool {
+        let near = |bound: f64| {
+            bound.is_finite() && (value - bound).abs() <= BOUND_TOLERANCE * bound.abs().max(1.0)
+        };
+        near(self.lower) || near(self.upper)
+    }
+
+    /// Map an internal parameter to the bounded parameter.
+    pub fn external(&self, q: f64) -> f64 {
+        match (self.lower.is_finite(), self.upper.is_finite()) {
+            (true, true) => self.lower + 0.5 * (self.upper - self.lower) * (q.sin() + 1.0),
+            (true, false) => self.lower - 1.0 + (q * q + 1.0).sqrt(),
+            (false, true) => self.upper + 1.0 - (q * q + 1.0).sqrt(),
+            (false, false) => q,
+        }
+    }
+
+    /// Map a bounded parameter to an internal parameter. Values on or outside a
+    /// bound are first moved slightly inside the bounds.
+    pub fn internal(&self, p: f64) -> f64 {
+        let (lower, upper) = (self.lower, self.upper);
+        match (lower.is_finite(), upper.is_finite()) {
+            (true, true) => {
+                let margin = BOUND_MARGIN * (upper - lower);
+                let p = p.clamp(lower + margin, upper - margin);
+                (2.0 * (p - lower) / (upper - lower) - 1.0).asin()
+            }
+            (true, false) => {
+                let p = p.max(lower + BOUND_MARGIN * lower.abs().max(1.0));
+                ((p - lower + 1.0).powi(2) - 1.0).sqrt()
+            }
+            (false, true) => {
+                let p = p.min(upper - BOUND_MARGIN * upper.abs().max(1.0));
+                ((upper - p + 1.0).powi(2) - 1.0).sqrt()
+            }
+            (false, false) => p,
+        }
+    }
+
+    /// The first and second derivatives of the bounded parameter with respect to
+    /// the internal parameter.
+    pub fn derivatives(&self, q: f64) -> (f64, f64) {
+        match (self.lower.is_finite(), self.upper.is_finite()) {
+            (true, true) => {
+                let half_width = 0.5 * (self.upper - self.lower);
+                (half_width * q.cos(), -half_width * q.sin())
+            }
+            (true, false) => {
+                let root = (q * q + 1.0).sqrt();
+                (q / root, 1.0 / root.powi(3))
+            }
+            (false, true) => {
+                let root = (q * q + 1.0).sqrt();
+                (-q / root, -1.0 / root.powi(3))
+            }
+            (false, false) => (1.0, 0.0),
+        }
+    }
+
+    /// The range a global search samples the parameter from, which is the given
+    /// range, or the bounds if they are finite, or a range around the value.
+    /// The range is always within the bounds.
+    pub fn search_range(&self, range: Option<Range>, value: f64) -> Range {
+        let range = match range {
+            Some(range) => range,
+            None if self.lower.is_finite() && self.upper.is_finite() => Range {
+                lower: self.lower,
+                upper: self.upper,
+            },
+            None => Range::around(value.clamp(self.lower, self.upper)),
+        };
+        let (lower, upper) = (range.lower.max(self.lower), range.upper.min(self.upper));
+        if lower < upper {
+            Range { lower, upper }
+        } else {
+            // the range is outside the bounds, so we use a range at the closest bound
+            let bound = if range.upper <= self.lower {
+                self.lower
+            } else {
+                self.upper
+            };
+            let width = range.width().min(self.upper - self.lower);
+            Range {
+                lower: bound.max(range.lower.min(self.upper - width)),
+                upper: bound.min(range.upper.max(self.lower + width)),
+            }
+        }
+    }
+}
+
+/// A parameter that is tied to another parameter by p = factor·other + offset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Tie {
+    /// The index of the other parameter.
+    pub to: usize,
+    pub factor: f64,
+    pub offset: f64,
+}
+
+impl Tie {
+    /// Parse an affine expression of another parameter, like '2*a + 1', 'σ₁' or '-a/2'.
+    pub fn parse(s: &str, names: &[&str]) -> Result<Self, String> {
+        let malformed = |reason: &str| {
+            format!(
+                "Got malformed tie '{}'. {} Ties must be of the form 'factor*name + offset'.",
+                s, reason
+            )
+        };
+
+        // split the expression into signed terms, taking care not to split numbers like 1e-3
+        let chars: Vec<char> = s.chars().filter(|c| !c.is_whitespace()).collect();
+        let mut terms: Vec<(f64, String)> = vec![(1.0, String::new())];
+        for (i, c) in chars.iter().enumerate() {
+            let in_exponent = i >= 2
+                && matches!(chars[i - 1], 'e' | 'E')
+                && (chars[i - 2].is_ascii_digit() || chars[i - 2] == '.');
+            match c {
+                '+' | '-' if !in_exponent => {
+                    let sign = if *c == '-' { -1.0 } else { 1.0 };
+                    if terms.last().is_some_and(|(_, term)| term.is_empty()) {
+                        terms.last_mut().unwrap().0 *= sign;
+                    } else {
+                        terms.push((sign, String::new()));
+                    }
+                }
+                c => terms.last_mut().unwrap().1.push(*c),
+            }
+        }
+
+        let number = |v: &str| v.parse::<f64>().ok().filter(|v| v.is_finite());
+        let mut tie: Option<Tie> = None;
+        let mut offset = 0.0;
+        for (sign, term) in terms {
+            if term.is_empty() {
+                return Err(malformed("It has an empty term."));
+            }
+            if let Some(value) = number(&term) {
+                offset += sign * value;
+                continue;
+            }
+
+            // a term with a parameter, which can be multiplied or divided by a number
+            let (name, factor) = if let Some((left, right)) = term.split_once('*') {
+                match (number(left), number(right)) {
+                    (Some(factor), None) => (right, factor),
+                    (None, Some(factor)) => (left, factor),
+                    _ => return Err(malformed("Only one side of '*' can be a number.")),
+                }
+            } else if let Some((left, right)) = term.split_once('/') {
+                match number(right) {
+                    Some(divisor) if divisor != 0.0 => (left, 1.0 / divisor),
+                    _ => return Err(malformed("Parameters can only be divided by a number.")),
+                }
+            } else {
+                (term.as_str(), 1.0)
+            };
+            if tie.is_some() {
+                return Err(malformed("It refers to more than one parameter."));
+            }
+            tie = Some(Tie {
+                to: parameter_index(names, name)?,
+                factor: sign * factor,
+                offset: 0.0,
+            });
+        }
+
+        let Some(tie) = tie else {
+            return Err(malformed("It does not refer to a parameter."));
+        };
+        Ok(Tie { offset, ..tie })
+    }
+
+    /// The value of the tied parameter, given the parameters it can be tied to.
+    pub fn value(&self, parameters: &[f64]) -> f64 {
+        self.factor * parameters[self.to] + self.offset
+    }
+}
+
+/// What a fit did with a parameter, which decides if its uncertainty is estimated.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParameterStatus {
+    Free,
+    /// The parameter ended at one of its bounds, so the error is not at a minimum
+    /// with respect to it and its uncertainty can't be estimated.
+    AtBound,
+    /// The parameter was not fitted, so it has no uncertainty.
+    Fixed,
+    /// The parameter follows another parameter, so its uncertainty follows from
+    /// that parameter's uncertainty.
+    Tied {
+        to: usize,
+        factor: f64,
+    },
+}
+
+impl ParameterStatus {
+    pub fn is_free(&self) -> bool {
+        *self == Self::Free
+    }
+}
+
+/// Parse a command line argument of the form 'name=value', where the value is
+/// parsed with `parse`.
+fn parse_named<T>(s: &str, parse: fn(&str) -> Result<T, String>) -> Result<(String, T), String> {
+    let Some((name, value)) = s.split_once('=') else {
+        return Err(format!(
+            "Got malformed argument '{}'. Arguments must be of the form 'name=value'.",
+            s
+        ));
+    };
+    Ok((name.trim().to_string(), parse(value.trim())?))
+}
+
+/// Parse a command line argument of the form 'name=lower:upper'.
+pub fn parse_named_range(s: &str) -> Result<(String, Range), String> {
+    parse_named(s, Range::parse)
+}
+
+/// Parse a command line argument of the form 'name=value', where the value is a number.
+pub fn parse_named_value(s: &str) -> Result<(String, f64), String> {
+    parse_named(s, |v| {
+        v.parse::<f64>()
+            .map_err(|e| format!("Got malformed value '{}': {}", v, e))
+    })
+}
+
+/// Parse a command line argument of the form 'name=expression', where the expression
+/// is parsed once the names of the parameters are known.
+pub fn parse_named_expression(s: &str) -> Result<(String, String), String> {
+    parse_named(s, |v| Ok(v.to_string()))
+}
+
+/// Parse a command line argument of the form 'name=lower:upper', where either
+/// value can be left out.
+pub fn parse_named_bounds(s: &str) -> Result<(String, Bounds), String> {
+    parse_named(s, Bounds::parse)
+}
+
+/// Find the index of the parameter with the given name.
+fn parameter_index(names: &[&str], name: &str) -> Result<usize, String> {
+    names.iter().position(|n| *n == name).ok_or_else(|| {
+        format!(
+            "Got unknown parameter name '{}'. Legal parameter names are {}.",
+            name,
+            prettify_list(names)
+        )
+    })
+}
+
+/// What the user has specified about the parameters of a function, in addition to
+/// their initial values.
+#[derive(Debug, Clone, Default)]
+pub struct ParameterSpecs {
+    /// The range each parameter is sampled from by global searches.
+    pub ranges: Vec<Option<Range>>,
+    pub bounds: Vec<Bounds>,
+    /// The value of each parameter that is not fitted.
+    pub fixed: Vec<Option<f64>>,
+    /// The tie of each parameter that follows another parameter.
+    pub ties: Vec<Option<Tie>>,
+}
+
+impl ParameterSpecs {
+    /// Specs for a function with `count` parameters, where nothing is specified.
+    pub fn new(count: usize) -> Self {
+        Self {
+            ranges: vec![None; count],
+            bounds: vec![Bounds::default(); count],
+            fixed: vec![None; count],
+            ties: vec![None; count],
+        }
+    }
+
+    /// Load specs from a session file, where each line is one of
+    /// - 'range name = lower:upper',
+    /// - 'bound name = lower:upper', where either value can be left out,
+    /// - 'fix name = value',
+    /// - 'tie name = expression', see `Tie::parse`.
+    ///
+    /// Everything after a '#' is a comment.
+    pub fn load(path: &Path, names: &[&str]) -> Result<Self, String> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Got error when opening {:?}: {}", path, e))?;
+
+        let mut specs = Self::new(names.len());
+        for line in contents.lines() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let Some((kind, argument)) = line.split_once(char::is_whitespace) else {
+                return Err(format!(
+                    "Got malformed session line: {}. Lines must be of the form \
+                    'kind name = value'.",
+                    line
+                ));
+            };
+            match kind {
+                "range" => specs.set_ranges(names, &[parse_named_range(argument)?])?,
+                "bound" => specs.set_bounds(names, &[parse_named_bounds(argument)?])?,
+                "fix" => specs.set_fixed(names, &[parse_named_value(argument)?])?,
+                "tie" => specs.set_ties(names, &[parse_named_expression(argument)?])?,
+                _ => {
+                    return Err(format!(
+                        "Got unknown session line kind '{}'. Legal kinds are {}.",
+                        kind,
+                        prettify_list(&["range", "bound", "fix", "tie"])
+                    ));
+                }
+            }
+        }
+        Ok(specs)
+    }
+
+    /// Set ranges from command line arguments that refer to parameters by name.
+    pub fn set_ranges(&mut self, names: &[&str], ranges: &[(String, Range)]) -> Result<(), String> {
+        for (name, range) in ranges {
+            self.ranges[parameter_index(names, name)?] = Some(*range);
+        }
+        Ok(())
+    }
+
+    /// Set bounds from command line arguments that refer to parameters by name.
+    pub fn set_bounds(
+        &mut self,
+        names: &[&str],
+        bounds: &[(String, Bounds)],
+    ) -> Result<(), String> {
+        for (name, bounds) in bounds {
+            self.bounds[parameter_index(names, name)?] = *bounds;
+        }
+        Ok(())
+    }
+
+    /// Fix parameters from command line arguments that refer to parameters by name.
+    pub fn set_fixed(&mut self, names: &[&str], fixed: &[(String, f64)]) -> Result<(), String> {
+        for (name, value) in fixed {
+            self.fixed[parameter_index(names, name)?] = Some(*value);
+        }
+        Ok(())
+    }
+
+    /// Set ties from command line arguments of the form 'name=expression'. A parameter
+    /// can't be tied to itself or to a parameter that is tied to another parameter.
+    /// If the ties are invalid, the ties that were already set are left unchanged.
+    pub fn set_ties(&mut self, names: &[&str], ties: &[(String, String)]) -> Result<(), String> {
+        let mut new_ties = self.ties.clone();
+        for (name, expression) in ties {
+            new_ties[parameter_index(names, name)?] = Some(Tie::parse(expression, names)?);
+        }
+        for (i, tie) in new_ties.iter().enumerate() {
+            let Some(tie) = tie else {
+                continue;
+            };
+            if tie.to == i {
+                return Err(format!("Can't tie '{}' to itself!", names[i]));
+            }
+            if new_ties[tie.to].is_some() {
+                return Err(format!(
+                    "Can't tie '{}' to '{}', as '{}' is tied to another parameter.",
+                    names[i], names[tie.to], names[tie.to]
+                ));
+            }
+        }
+        self.ties = new_ties;
+        Ok(())
+    }
+
+    /// The initial parameters with fixed parameters set to their values.
+    pub fn apply_fixed(&self, initial_parameters: &mut [f64]) {
+        for (value, fixed) in izip!(initial_parameters, &self.fixed) {
+            if let Some(fixed) = fixed {
+                *value = *fixed;
+            }
+        }
+    }
+
+    /// The range of every parameter used by global searches, see `Bounds::search_range`.
+    pub fn search_ranges(&self, initial_parameters: &[f64]) -> Vec<Range> {
+        izip!(&self.ranges, &self.bounds, initial_parameters)
+            .map(|(range, bounds, value)| bounds.search_range(*range, *value))
+            .collect()
+    }
+
+    /// The status of each parameter after a fit.
+    pub fn statuses(&self, parameters: &[f64]) -> Vec<ParameterStatus> {
+        izip!(&self.bounds, &self.fixed, &self.ties, parameters)
+            .map(|(bounds, fixed, tie, value)| {
+                if fixed.is_some() {
+                    ParameterStatus::Fixed
+                } else if let Some(tie) = tie {
+                    ParameterStatus::Tied {
+                        to: tie.to,
+                        factor: tie.factor,
+                    }
+                } else if bounds.at_bound(*value) {
+                    ParameterStatus::AtBound
+                } else {
+                    ParameterStatus::Free
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        assert_eq!(
+            Range::parse("-1:2.5"),
+            Ok(Range {
+                lower: -1.0,
+                upper: 2.5
+            })
+        );
+        assert!(Range::parse("1").is_err());
+        assert!(Range::parse("a:2").is_err());
+        assert!(Range::parse("2:1").is_err());
+    }
+
+    #[test]
+    fn test_parse_bounds() {
+        let bounds = Bounds::parse("0:").unwrap();
+        assert_eq!(bounds.lower, 0.0);
+        assert_eq!(bounds.upper, f64::INFINITY);
+        let bounds = Bounds::parse(":-1.5").unwrap();
+        assert_eq!(bounds.lower, f64::NEG_INFINITY);
+        assert_eq!(bounds.upper, -1.5);
+        assert!(!Bounds::parse(":").unwrap().is_bounded());
+        assert!(Bounds::parse("0").is_err());
+        assert!(Bounds::parse("1:0").is_err());
+    }
+
+    #[test]
+    fn test_transform() {
+        for bounds in ["-1:3", "2:", ":-2", ":"] {
+            let bounds = Bounds::parse(bounds).unwrap();
+            for p in [-1.5, -0.5, 0.0, 0.5, 2.0, 2.5] {
+                let q = bounds.internal(p);
+                let mapped = bounds.external(q);
+                assert!(mapped > bounds.lower && mapped < bounds.upper);
+                if p > bounds.lower + 0.1 && p < bounds.upper - 0.1 {
+                    assert!(
+                        (mapped - p).abs() < 1e-12,
+                        "{:?}: {} != {}",
+                        bounds,
+                        mapped,
+                        p
+                    );
+                }
+
+                let h = 1e-5;
+                let (d, d2) = bounds.derivatives(q);
+                let numeric_d = (bounds.external(q + h) - bounds.external(q - h)) / (2.0 * h);
+                let numeric_d2 =
+                    (bounds.external(q + h) - 2.0 * mapped + bounds.external(q - h)) / h.powi(2);
+                assert!((d - numeric_d).abs() < 1e-8);
+                assert!((d2 - numeric_d2).abs() < 1e-4);
+            }
+        }
+    }
+
+    #[test]
+    fn test_parse_tie() {
+        let names = ["a", "b", "σ₁"];
+        let tie = |s| Tie::parse(s, &names);
+        assert_eq!(
+            tie("2*a + 1").unwrap(),
+            Tie {
+                to: 0,
+                factor: 2.0,
+                offset: 1.0
+            }
+        );
+        assert_eq!(
+            tie("σ₁").unwrap(),
+            Tie {
+                to: 2,
+                factor: 1.0,
+                offset: 0.0
+            }
+        );
+        assert_eq!(
+            tie("-b/2").unwrap(),
+            Tie {
+                to: 1,
+                factor: -0.5,
+                offset: 0.0
+            }
+        );
+        assert_eq!(
+            tie("1e-3 - a*3").unwrap(),
+            Tie {
+                to: 0,
+                factor: -3.0,
+                offset: 1e-3
+            }
+        );
+        assert!(tie("a + b").is_err());
+        assert!(tie("2").is_err());
+        assert!(tie("a*b").is_err());
+        assert!(tie("x").is_err());
+
+        let mut specs = ParameterSpecs::new(names.len());
+        let ties = ["b=2a", "b=b"].map(|s| parse_named_expression(s).unwrap());
+        assert!(specs.set_ties(&names, &ties[..1]).is_err());
+        assert!(specs.set_ties(&names, &ties[1..]).is_err());
+
+        let mut specs = ParameterSpecs::new(names.len());
+        let ties = ["b=2*a", "σ₁=b"].map(|s| parse_named_expression(s).unwrap());
+        assert!(specs.set_ties(&names, &ties).is_err());
+        assert_eq!(specs.ties, vec![None; names.len()]);
+
+        // a tie that is invalid with the ties already set leaves them unchanged
+        assert!(specs.set_ties(&names, &ties[..1]).is_ok());
+        assert!(specs.set_ties(&names, &ties[1..]).is_err());
+        assert_eq!(specs.ties[2], None);
+        assert!(specs.ties[1].is_some());
+    }
+
+    #[test]
+    fn test_specs() {
+        let names = ["a", "μ", "σ"];
+        let mut specs = ParameterSpecs::new(names.len());
+        let ranges = vec![parse_named_range("μ=-5:5").unwrap()];
+        specs.set_ranges(&names, &ranges).unwrap();
+        let bounds = vec![
+            parse_named_bounds("σ=0:").unwrap(),
+            parse_named_bounds("μ=-2:2").unwrap(),
+        ];
+        specs.set_bounds(&names, &bounds).unwrap();
+        assert_eq!(specs.ranges[0], None);
+        assert_eq!(specs.ranges[1], Some(Range::parse("-5:5").unwrap()));
+        assert!(!specs.bounds[0].is_bounded());
+
+        let search_ranges = specs.search_ranges(&[3.0, 0.0, 0.5]);
+        assert_eq!(search_ranges[0], Range::parse("0:6").unwrap());
+        assert_eq!(search_ranges[1], Range::parse("-2:2").unwrap());
+        assert_eq!(search_ranges[2], Range::parse("0:1.5").unwrap());
+
+        assert_eq!(
+            specs.statuses(&[0.0, 2.0, 0.5]),
+            vec![
+                ParameterStatus::Free,
+                ParameterStatus::AtBound,
+                ParameterStatus::Free
+            ]
+        );
+
+        specs
+            .set_fixed(&names, &[parse_named_value("a = 2").unwrap()])
+            .unwrap();
+        let mut parameters = [1.0, 1.0, 1.0];
+        specs.apply_fixed(&mut parameters);
+        assert_eq!(parameters, [2.0, 1.0, 1.0]);
+        assert_eq!(specs.statuses(&parameters)[0], ParameterStatus::Fixed);
+
+        let ranges = vec![parse_named_range("x=0:1").unwrap()];
+        assert!(specs.set_ranges(&names, &ranges).is_err());
+        assert!(parse_named_value("a=b").is_err());
+    }
+}
@@ -5,18 +5,20 @@ use std::{
 };
 
 use itertools::{Itertools, MinMaxResult, izip};
-use nalgebra::SVector;
+use nalgebra::{DefaultAllocator, Dim, OVector, allocator::Allocator};
 
 use crate::utils::{format_vector, format_with_uncertainty};
 
-pub fn plot_static<const D: usize>(
+pub fn plot_static<D: Dim>(
     x_ray: &[f64],
     y_ray: &[f64],
-    f: fn(f64, &SVector<f64, D>) -> f64,
-    optimal_parameters: &SVector<f64, D>,
-    uncertainties: &SVector<f64, D>,
+    f: impl Fn(f64, &OVector<f64, D>) -> f64,
+    optimal_parameters: &OVector<f64, D>,
+    uncertainties: &OVector<f64, D>,
     filename: &str,
-) {
+) where
+    DefaultAllocator: Allocator<D>,
+{
     let datafile = "src/plotting/data.dat";
     let mut file = File::create(datafile).unwrap();
 
@@ -24,10 +26,7 @@ pub fn plot_static<const D: usize>(
     writeln!(
         &mut file,
         "{}",
-        format_with_uncertainty(
-            optimal_parameters.data.as_slice(),
-            uncertainties.data.as_slice()
-        )
+        format_with_uncertainty(optimal_parameters.as_slice(), uncertainties.as_slice())
     )
     .unwrap();
 
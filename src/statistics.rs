@@ -1,38 +1,105 @@
 use itertools::izip;
-use nalgebra::{DMatrix, SMatrix, SVector};
+use nalgebra::{DMatrix, DefaultAllocator, Dim, OMatrix, OVector};
 
 use crate::error_functions::outer;
-use crate::functions::Differentiated;
-
-/// Estimate variance of the experimental error
-fn calculate_variance<const D: usize, F: Differentiated<D>>(
-    x_ray: &[f64],
-    y_ray: &[f64],
-    parameters: &SVector<f64, D>,
-) -> f64 {
-    let mut variance = 0.0;
-    for (x, y) in izip!(x_ray, y_ray) {
-        variance += (y - F::f(*x, parameters)).powi(2);
+use crate::functions::{Model, ParameterAllocator};
+use crate::parameters::ParameterStatus;
+use crate::utils::{Dataset, blockwise_sum};
+
+/// The weight of each data point, which is 1/σ² for data with uncertainties.
+fn weights(data: &Dataset) -> Vec<f64> {
+    match &data.sigma {
+        Some(sigma) => sigma.iter().map(|s| s.powi(-2)).collect(),
+        None => vec![1.0; data.x.len()],
     }
-    variance / ((x_ray.len() - D) as f64)
 }
 
-fn calculate_covariance<const D: usize, F: Differentiated<D>>(
-    x_ray: &[f64],
-    y_ray: &[f64],
-    parameters: &SVector<f64, D>,
-) -> SMatrix<f64, D, D> {
-    let mut outer_sum = SMatrix::<f64, D, D>::zeros();
-    for x in x_ray {
-        let g = F::grad(*x, parameters);
-        outer_sum += outer(&g);
+/// The number of data points minus the number of free parameters, or None if there
+/// are no degrees of freedom left to estimate the variance of the errors from.
+pub fn degrees_of_freedom(points: usize, free: usize) -> Option<usize> {
+    points.checked_sub(free).filter(|dof| *dof > 0)
+}
+
+/// Estimate variance of the experimental error, where `free` is the number of
+/// parameters that were fitted freely. For data with uncertainties, this is the
+/// reduced χ², which is the factor the uncertainties are off by. Returns None if
+/// there are no degrees of freedom.
+fn calculate_variance<D: Dim>(
+    model: &impl Model<D>,
+    data: &Dataset,
+    parameters: &OVector<f64, D>,
+    free: usize,
+) -> Option<f64>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let degrees_of_freedom = degrees_of_freedom(data.x.len(), free)?;
+    let weights = weights(data);
+    let variance = blockwise_sum(data.x.len(), 0, |block| {
+        let mut variance = 0.0;
+        for i in block {
+            variance += weights[i] * (data.y[i] - model.f(data.x[i], parameters)).powi(2);
+        }
+        variance
+    });
+    Some(variance / degrees_of_freedom as f64)
+}
+
+/// The derivatives of the parameters with respect to the free parameters. Column j
+/// is zero unless parameter j is free, so this maps the covariance of the free
+/// parameters to the covariance of all parameters.
+fn free_parameter_jacobian<D: Dim>(dimension: D, statuses: &[ParameterStatus]) -> OMatrix<f64, D, D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let mut jacobian = OMatrix::zeros_generic(dimension, dimension);
+    for (i, status) in statuses.iter().enumerate() {
+        match status {
+            ParameterStatus::Free => jacobian[(i, i)] = 1.0,
+            ParameterStatus::Tied { to, factor } if statuses[*to].is_free() => {
+                jacobian[(i, *to)] = *factor
+            }
+            _ => {}
+        }
     }
+    jacobian
+}
+
+/// Calculates the covariance of the parameters, where only the free parameters are
+/// fitted. Fixed parameters and parameters at a bound have zero rows and columns, and
+/// tied parameters follow the parameter they are tied to.
+///
+/// With `absolute_sigma`, the uncertainties of the data are taken as the actual
+/// standard deviations of the errors. Otherwise, they are only relative weights, and
+/// the covariance is scaled by the variance estimated from the residuals.
+fn calculate_covariance<D: Dim>(
+    model: &impl Model<D>,
+    data: &Dataset,
+    parameters: &OVector<f64, D>,
+    statuses: &[ParameterStatus],
+    absolute_sigma: bool,
+) -> OMatrix<f64, D, D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    let dimension = model.dimension();
+    let jacobian = free_parameter_jacobian(dimension, statuses);
+    let weights = weights(data);
+    let outer_sum = blockwise_sum(data.x.len(), 0, |block| {
+        let mut outer_sum = OMatrix::zeros_generic(dimension, dimension);
+        for (x, w) in izip!(&data.x[block.clone()], &weights[block]) {
+            let g = jacobian.transpose() * model.grad(*x, parameters);
+            outer_sum += *w * outer(&g);
+        }
+        outer_sum
+    });
     if outer_sum.iter().any(|v| v.is_nan()) {
-        return SMatrix::<f64, D, D>::from_element(f64::NAN);
+        return OMatrix::from_element_generic(dimension, dimension, f64::NAN);
     }
 
     // Why must I use a DMatrix to calculate the pseudo inverse? IDK
-    let outer_sum_dynamic = DMatrix::from_row_slice(D, D, outer_sum.data.as_slice());
+    let n = dimension.value();
+    let outer_sum_dynamic = DMatrix::from_row_slice(n, n, outer_sum.as_slice());
 
     // I use the pseudo inverse instead of the true inverse as I found that in some cases,
     // M * M.inverse() != Identity. I don't know why this is.
@@ -40,18 +107,97 @@ fn calculate_covariance<const D: usize, F: Differentiated<D>>(
         panic!("Sum of outer products is not invertible!");
     };
 
-    // Back to SMatrix, yay!
-    let outer_inverse = SMatrix::from_row_slice(outer_inverse_dynamic.data.as_slice());
+    // Back to the dimension of the model, yay!
+    let outer_inverse =
+        OMatrix::from_row_slice_generic(dimension, dimension, outer_inverse_dynamic.as_slice());
 
-    outer_inverse * calculate_variance::<D, F>(x_ray, y_ray, parameters)
+    let variance = if absolute_sigma && data.sigma.is_some() {
+        1.0
+    } else {
+        // without degrees of freedom, the uncertainties can't be estimated
+        let free_count = statuses.iter().filter(|s| s.is_free()).count();
+        calculate_variance(model, data, parameters, free_count).unwrap_or(f64::NAN)
+    };
+    &jacobian * outer_inverse * jacobian.transpose() * variance
 }
 
-pub fn get_uncertainties<const D: usize, F: Differentiated<D>>(
-    x_ray: &[f64],
-    y_ray: &[f64],
-    parameters: &SVector<f64, D>,
-) -> SVector<f64, D> {
-    calculate_covariance::<D, F>(x_ray, y_ray, parameters)
+/// The uncertainty of each parameter, which is zero for parameters that are fixed
+/// or at a bound.
+pub fn get_uncertainties<D: Dim>(
+    model: &impl Model<D>,
+    data: &Dataset,
+    parameters: &OVector<f64, D>,
+    statuses: &[ParameterStatus],
+    absolute_sigma: bool,
+) -> OVector<f64, D>
+where
+    DefaultAllocator: ParameterAllocator<D>,
+{
+    calculate_covariance(model, data, parameters, statuses, absolute_sigma)
         .diagonal()
         .map(|v| v.sqrt())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::functions::line::Line;
+    use nalgebra::{Matrix2, Vector2};
+
+    #[test]
+    fn test_weighted_uncertainties() {
+        let parameters = Vector2::new(2.0, 1.0);
+        let x: Vec<f64> = (0..10).map(|i| i as f64).collect();
+        // every other point is off by 0.1, which the uncertainties should account for
+        let y: Vec<f64> = x
+            .iter()
+            .enumerate()
+            .map(|(i, x)| Line.f(*x, &parameters) + if i % 2 == 0 { 0.1 } else { -0.1 })
+            .collect();
+        let sigma: Vec<f64> = x.iter().map(|x| 0.1 + 0.01 * x).collect();
+        let data = Dataset {
+            x: x.clone(),
+            y,
+            sigma: Some(sigma.clone()),
+            x_sigma: None,
+        };
+        let statuses = [ParameterStatus::Free; 2];
+
+        // with absolute sigma, the covariance is the inverse of the weighted JᵀJ
+        let mut weighted = Matrix2::zeros();
+        for (x, s) in izip!(&x, &sigma) {
+            weighted += outer(&Vector2::new(*x, 1.0)) / (s * s);
+        }
+        let expected = weighted.try_inverse().unwrap().diagonal().map(f64::sqrt);
+        let absolute = get_uncertainties(&Line, &data, &parameters, &statuses, true);
+        assert!((absolute - expected).abs().max() < 1e-12);
+
+        // with relative sigma, it is scaled by the reduced χ²
+        let reduced_chi_squared = calculate_variance(&Line, &data, &parameters, 2).unwrap();
+        let chi_squared: f64 = sigma.iter().map(|s| (0.1 / s).powi(2)).sum();
+        assert!((reduced_chi_squared - chi_squared / 8.0).abs() < 1e-12);
+        let relative = get_uncertainties(&Line, &data, &parameters, &statuses, false);
+        assert!(
+            (relative - expected * reduced_chi_squared.sqrt())
+                .abs()
+                .max()
+                < 1e-12
+        );
+
+        // a line through two points has no degrees of freedom, so only the absolute
+        // uncertainties can be estimated
+        let data = Dataset {
+            x: vec![0.0, 1.0],
+            y: vec![1.0, 3.0],
+            sigma: Some(vec![0.1, 0.1]),
+            x_sigma: None,
+        };
+        assert_eq!(degrees_of_freedom(2, 2), None);
+        assert_eq!(calculate_variance(&Line, &data, &parameters, 2), None);
+        let absolute = get_uncertainties(&Line, &data, &parameters, &statuses, true);
+        assert!(absolute.iter().all(|u| u.is_finite()));
+        let relative = get_uncertainties(&Line, &data, &parameters, &statuses, false);
+        assert!(relative.iter().all(|u| u.is_nan()));
+    }
+}
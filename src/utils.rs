@@ -4,39 +4,79 @@ use std::{
     cmp::{max, min},
     fs::File,
     io::{BufRead, BufReader},
+    ops::{Add, Range},
     path::PathBuf,
+    thread,
 };
 
 use itertools::izip;
 
-/// Read in x- and y-values from a plaintext data file.
-pub fn load_txt(datafile: &PathBuf) -> Result<(Vec<f64>, Vec<f64>), String> {
+/// Sums over data are taken in blocks of this many points, which are summed in
+/// order before the sums of the blocks are added pairwise.
+pub const SUMMATION_BLOCK: usize = 1024;
+/// Sums over fewer points than this stay on one thread, as starting threads would
+/// cost more than it saves.
+pub const PARALLEL_THRESHOLD: usize = 64 * SUMMATION_BLOCK;
+
+/// Data points, with optional uncertainties for each y-value and x-value.
+#[derive(Debug, Clone, Default)]
+pub struct Dataset {
+    pub x: Vec<f64>,
+    pub y: Vec<f64>,
+    pub sigma: Option<Vec<f64>>,
+    pub x_sigma: Option<Vec<f64>>,
+}
+
+/// Read in x- and y-values from a plaintext data file, where rows can have a third
+/// value with the uncertainty of the y-value, and a fourth with the uncertainty of
+/// the x-value. All rows must have the same number of values.
+pub fn load_txt(datafile: &PathBuf) -> Result<Dataset, String> {
     let file = match File::open(datafile) {
         Ok(v) => v,
         Err(e) => return Err(format!("Got error when opening {:?}: {}", datafile, e)),
     };
     let reader = BufReader::new(file);
 
-    let (mut x_ray, mut y_ray) = (Vec::new(), Vec::new());
+    let mut columns: Vec<Vec<f64>> = Vec::new();
     for line in reader.lines().map_while(Result::ok) {
         let vals: Vec<&str> = line.split(' ').collect();
-        if let [x, y] = vals[..] {
-            if let (Ok(x), Ok(y)) = (x.parse::<f64>(), y.parse::<f64>()) {
-                x_ray.push(x);
-                y_ray.push(y);
-            } else {
-                return Err(format!("Found non-float values in data list: {}, {}", x, y));
-            }
-        } else {
+        let parsed: Result<Vec<f64>, _> = vals.iter().map(|v| v.parse::<f64>()).collect();
+        let Ok(parsed) = parsed else {
             return Err(format!(
-                "Got malformed data: {}. Data rows must \
-                only contain two space-separated values",
+                "Found non-float values in data list: {}",
+                vals.join(", ")
+            ));
+        };
+        if columns.is_empty() && (2..=4).contains(&parsed.len()) {
+            columns = vec![Vec::new(); parsed.len()];
+        }
+        if parsed.len() != columns.len() {
+            return Err(format!(
+                "Got malformed data: {}. Data rows must all contain the same number of \
+                space-separated values, which is two, or three with the uncertainty of \
+                the second value, or four with the uncertainty of the first value as well",
                 line,
             ));
         }
+        if parsed[2..].iter().any(|s| !(*s > 0.0 && s.is_finite())) {
+            return Err(format!(
+                "Got invalid uncertainty in data row: {}. \
+                Uncertainties must be positive and finite",
+                line
+            ));
+        }
+        for (column, value) in columns.iter_mut().zip(parsed) {
+            column.push(value);
+        }
     }
 
-    Ok((x_ray, y_ray))
+    let mut columns = columns.into_iter();
+    Ok(Dataset {
+        x: columns.next().unwrap_or_default(),
+        y: columns.next().unwrap_or_default(),
+        sigma: columns.next(),
+        x_sigma: columns.next(),
+    })
 }
 
 /// Format a number so only a given number of significant digits are shown.
@@ -78,8 +118,8 @@ pub fn prettify_list<T: fmt::Display>(list: &[T]) -> String {
     for (i, string) in list.iter().enumerate() {
         pretty_list += &format!("'{}'", string);
         match i {
-            i if i < list.len() - 2 => pretty_list += ", ",
-            i if i == list.len() - 2 => pretty_list += " and ",
+            i if i + 2 < list.len() => pretty_list += ", ",
+            i if i + 2 == list.len() => pretty_list += " and ",
             _ => {}
         }
     }
@@ -94,6 +134,15 @@ pub fn format_with_uncertainty(values: &[f64], uncertainties: &[f64]) -> String
 
     let mut output_string = "[".to_string();
     for (i, (v, e)) in izip!(values, uncertainties).enumerate() {
+        if *e == 0.0 || e.is_nan() {
+            // the uncertainty was not estimated, such as for parameters at a bound or
+            // when there are no degrees of freedom
+            output_string += &g_format(*v, 5);
+            if i != values.len() - 1 {
+                output_string += ", ";
+            }
+            continue;
+        }
         let e_digits = e.abs().log10().ceil() as i64;
         let v_digits = v.abs().log10().ceil() as i64;
         let extra_digits = max(v_digits - e_digits, 0) as usize;
@@ -110,10 +159,101 @@ pub fn format_with_uncertainty(values: &[f64], uncertainties: &[f64]) -> String
     output_string + "]"
 }
 
+/// The number of threads to use, where zero means one thread per core.
+pub fn thread_count(threads: usize) -> usize {
+    if threads == 0 {
+        thread::available_parallelism().map_or(1, |n| n.get())
+    } else {
+        threads
+    }
+}
+
+/// Apply `f` to every item, splitting the items between `threads` threads.
+/// The results are in the same order as the items.
+pub fn parallel_map<T: Sync, R: Send>(
+    items: &[T],
+    threads: usize,
+    f: impl Fn(&T) -> R + Sync,
+) -> Vec<R> {
+    let threads = thread_count(threads).min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let chunk_size = items.len().div_ceil(threads);
+    thread::scope(|scope| {
+        let handles: Vec<_> = items
+            .chunks(chunk_size)
+            .map(|chunk| scope.spawn(|| chunk.iter().map(&f).collect::<Vec<R>>()))
+            .collect();
+        handles
+            .into_iter()
+            .flat_map(|handle| handle.join().unwrap())
+            .collect()
+    })
+}
+
+/// Sum the items by adding the sums of both halves, so that the rounding error grows
+/// with the logarithm of the number of items instead of linearly.
+pub fn pairwise_sum<T: Clone + Add<Output = T>>(items: &[T]) -> T {
+    match items {
+        [] => panic!("Can't sum no items without a zero!"),
+        [item] => item.clone(),
+        _ => {
+            let (left, right) = items.split_at(items.len() / 2);
+            pairwise_sum(left) + pairwise_sum(right)
+        }
+    }
+}
+
+/// Sum `f` over the indices `0..len`, which `f` gets in blocks of `SUMMATION_BLOCK`.
+/// The blocks are split between `threads` threads if there are at least
+/// `PARALLEL_THRESHOLD` indices, and their sums are added pairwise, so the result
+/// doesn't depend on the number of threads. For at most one block, this is just
+/// `f(0..len)`.
+pub fn blockwise_sum<T: Clone + Send + Add<Output = T>>(
+    len: usize,
+    threads: usize,
+    f: impl Fn(Range<usize>) -> T + Sync,
+) -> T {
+    let blocks: Vec<Range<usize>> = (0..len.max(1))
+        .step_by(SUMMATION_BLOCK)
+        .map(|start| start..(start + SUMMATION_BLOCK).min(len))
+        .collect();
+    let threads = if len < PARALLEL_THRESHOLD { 1 } else { threads };
+    pairwise_sum(&parallel_map(&blocks, threads, |block| f(block.clone())))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_load_txt() {
+        let path = std::env::temp_dir().join("omega_optimizer_test_load_txt.txt");
+        let load = |contents: &str| {
+            std::fs::write(&path, contents).unwrap();
+            load_txt(&path)
+        };
+
+        let data = load("0 1\n1 3").unwrap();
+        assert_eq!(data.y, vec![1.0, 3.0]);
+        assert!(data.sigma.is_none());
+
+        let data = load("0 1 0.5\n1 3 0.25").unwrap();
+        assert_eq!(data.x, vec![0.0, 1.0]);
+        assert_eq!(data.sigma, Some(vec![0.5, 0.25]));
+
+        let data = load("0 1 0.5 0.1").unwrap();
+        assert_eq!(data.x_sigma, Some(vec![0.1]));
+
+        assert!(load("0 1 0.5\n1 3").is_err());
+        assert!(load("0 1 0.5 0.1 1").is_err());
+        assert!(load("0 1\n1 3 0.5").is_err());
+        assert!(load("0 1 0").is_err());
+        assert!(load("0 a").is_err());
+    }
+
     #[test]
     fn test_g_format() {
         assert_eq!(g_format(1426837.0, 4), "1.427e6");
@@ -138,6 +278,37 @@ mod tests {
         assert_eq!(format_vector(&vector, 2), "[2.1, 9.8, 0.012]")
     }
 
+    #[test]
+    fn test_parallel_map() {
+        let items: Vec<usize> = (0..100).collect();
+        let squares: Vec<usize> = items.iter().map(|i| i * i).collect();
+        for threads in [1, 3, 8] {
+            assert_eq!(parallel_map(&items, threads, |i| i * i), squares);
+        }
+        assert!(parallel_map(&[] as &[usize], 4, |i| *i).is_empty());
+    }
+
+    #[test]
+    fn test_blockwise_sum() {
+        let values = vec![0.1; 1_000_000];
+        let sum = |threads| {
+            blockwise_sum(values.len(), threads, |block| {
+                values[block].iter().sum::<f64>()
+            })
+        };
+        let naive: f64 = values.iter().sum();
+        assert!((naive - 1e5).abs() > 1e-6);
+        assert!((sum(1) - 1e5).abs() < 1e-8);
+        for threads in [3, 8] {
+            assert_eq!(sum(threads), sum(1));
+        }
+
+        // a single block is summed in order, and nothing sums to the empty block
+        assert_eq!(blockwise_sum(10, 4, |block| block.sum::<usize>()), 45);
+        assert_eq!(blockwise_sum(0, 4, |block| block.len()), 0);
+        assert_eq!(pairwise_sum(&[1, 2, 3, 4, 5]), 15);
+    }
+
     #[test]
     fn test_prettify_list() {
         let list = ["apple", "orange", "banana"];